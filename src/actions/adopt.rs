@@ -0,0 +1,46 @@
+use std::path::PathBuf;
+
+use anyhow::{Error, Result};
+use nix::unistd::{getpgid, Pid};
+
+use crate::runs::{Run, Runs};
+
+pub fn adopt_process(
+    runs: &Runs,
+    pid: i32,
+    label: Option<String>,
+    output_file: Option<PathBuf>,
+) -> Result<()> {
+    let pid = Pid::from_raw(pid);
+    let pgid = getpgid(Some(pid)).map_err(|_| Error::msg(format!("No process with PID {}", pid)))?;
+    if pgid == getpgid(None)? {
+        // Signalling the run would then also signal us (and likely our shell)
+        return Err(Error::msg(format!(
+            "Process {} is in our own process group; refusing to adopt it",
+            pid
+        )));
+    }
+
+    let run = runs.new_run()?;
+    if let Err(e) = run.adopt(pid, label, output_file) {
+        runs.remove_run(run)?;
+        return Err(e);
+    }
+    println!("Adopted process {} as run {}", pid, run.id);
+    if pgid != pid {
+        println!(
+            "Note: signals will be sent to the process' whole process group ({}).",
+            pgid
+        );
+    }
+    Ok(())
+}
+
+pub fn readopt_run(run: &Run) -> Result<()> {
+    if run.readopt()? {
+        println!("Monitoring run {} again", run.id);
+    } else {
+        println!("Run {} is no longer running; marked it as finished", run.id);
+    }
+    Ok(())
+}
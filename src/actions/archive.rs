@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+use anyhow::{Error, Result};
+
+use crate::{archive, config::Config, events::Event, output::is_output_file_name, runs::Run};
+
+/// Upload a run to the archive (see `archive` in the configuration), and print URLs through which
+/// its output can be downloaded (for as long as `validity`).
+pub fn archive_run(config: &Config, run: &Run, validity: Duration) -> Result<()> {
+    let archive = config.archive.as_ref().ok_or_else(|| {
+        Error::msg("No archive is configured (see `archive` in the configuration)")
+    })?;
+
+    let keys = archive::archive_run(archive, run)?;
+    let location = archive::location(archive, run);
+    run.record_event(Event::Archived {
+        location: location.clone(),
+    });
+    println!("Archived run '{}' to {}", run.id, location);
+
+    for key in keys {
+        let file_name = key.rsplit('/').next().unwrap_or_default();
+        if is_output_file_name(file_name) {
+            println!(
+                "{}: {}",
+                file_name,
+                archive::share_url(archive, &key, validity)?
+            );
+        }
+    }
+    Ok(())
+}
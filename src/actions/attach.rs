@@ -0,0 +1,38 @@
+use anyhow::{Error, Result};
+
+use crate::{
+    runs::{Run, RunDataState},
+    session,
+};
+
+/// Attach the terminal to an interactive run (see `--interactive`), until detaching from it with
+/// Ctrl+] or it finishing.
+pub fn attach_run(run: &Run) -> Result<()> {
+    match run.get_data()?.state {
+        RunDataState::Done { .. } => return Err(Error::msg("Run is not running")),
+        RunDataState::Queued { .. } => {
+            return Err(Error::msg(format!(
+                "Run is queued; attach to it once it starts, with `rum -attach {}`",
+                &run.id[..8]
+            )))
+        }
+        RunDataState::Running { .. } => (),
+    }
+    let socket = run.get_attach_socket();
+    if !socket.exists() {
+        return Err(Error::msg(
+            "Run is not interactive (see `--interactive`), so there's nothing to attach to",
+        ));
+    }
+
+    // Raw mode is left by now, so these print as usual
+    match session::attach(&socket)? {
+        true => println!("Run {} finished.", &run.id[..8]),
+        false => println!(
+            "Detached from run {} (reattach with `rum -attach {}`).",
+            &run.id[..8],
+            &run.id[..8]
+        ),
+    }
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use colored::Colorize;
+
+use crate::{runs::Runs, utils::format_datetime};
+
+/// Show what was done to runs, by whom and with which command, oldest first; with `since`, only
+/// what was done in that long.
+pub fn show_audit_log(runs: &Runs, since: Option<Duration>) -> Result<()> {
+    let now = Utc::now();
+    let entries = runs
+        .get_audit_log()?
+        .into_iter()
+        .filter(|audit_entry| {
+            since.is_none_or(|since| {
+                (now - audit_entry.entry.datetime)
+                    .to_std()
+                    .unwrap_or_default()
+                    <= since
+            })
+        })
+        .collect::<Vec<_>>();
+    if entries.is_empty() {
+        println!("Nothing was done to any runs.");
+    }
+
+    for audit_entry in entries {
+        let entry = audit_entry.entry;
+        println!(
+            "{} {} {} {}{}",
+            format_datetime(entry.datetime).dimmed(),
+            entry.user.as_deref().unwrap_or("?").bold(),
+            &audit_entry.run[..8.min(audit_entry.run.len())],
+            entry.event,
+            entry
+                .by
+                .map(|by| format!(" ({by})").dimmed().to_string())
+                .unwrap_or_default(),
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,210 @@
+use std::{
+    sync::atomic::{AtomicI32, Ordering},
+    time::Duration,
+};
+
+use anyhow::{Error, Result};
+use nix::sys::signal::{self, SigHandler, Signal};
+
+use crate::{
+    actions::{send_signal::send_signal, start::start_run},
+    config::Config,
+    runs::{Run, RunData, RunDataState, Runs, StartOptions},
+};
+
+/// How often to check whether the current attempt has finished.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The signal the benchmark was asked to stop with, if any (0 otherwise).
+static STOP_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn on_stop_signal(signal: i32) {
+    STOP_SIGNAL.store(signal, Ordering::SeqCst);
+}
+
+/// Start a run which runs `command` `attempts` times (see [`run_attempts`]), so that each attempt
+/// is a run of its own, nested under it.
+pub fn start_bench(
+    runs: &Runs,
+    config: &Config,
+    attempts: u32,
+    command: Vec<String>,
+    global: bool,
+    options: &StartOptions,
+) -> Result<Run> {
+    let mut bench_command = vec![
+        std::env::current_exe()?.to_string_lossy().into_owned(),
+        "-bench".to_string(),
+        "--run-attempts".to_string(),
+        "--runs".to_string(),
+        attempts.to_string(),
+    ];
+    if global {
+        bench_command.push("--global".to_string());
+    }
+    bench_command.push("--".to_string());
+    bench_command.extend(command);
+    start_run(runs, config, bench_command, options)
+}
+
+/// Run `command` `attempts` times, one after the other, each as a run of its own, and then print
+/// statistics on how long they took (and how much CPU time they used). Fails if any attempt did.
+///
+/// When interrupted or terminated, the signal is passed on to the attempt which is running, and
+/// no more attempts are started.
+pub fn run_attempts(
+    runs: &Runs,
+    config: &Config,
+    attempts: u32,
+    command: Vec<String>,
+) -> Result<()> {
+    // SAFETY: the handler only sets an atomic integer
+    unsafe {
+        signal::signal(Signal::SIGINT, SigHandler::Handler(on_stop_signal))?;
+        signal::signal(Signal::SIGTERM, SigHandler::Handler(on_stop_signal))?;
+    }
+
+    let mut finished = Vec::new();
+    for attempt in 1..=attempts {
+        let options = StartOptions {
+            label: Some(format!("attempt {attempt}/{attempts}")),
+            ..Default::default()
+        };
+        let run = start_run(runs, config, command.clone(), &options)?;
+        let data = wait_for_attempt(&run)?;
+        println!(
+            "Attempt {attempt}: {} in {}.",
+            data.state.status(),
+            wall_time(&data)?
+        );
+        finished.push(data);
+        if STOP_SIGNAL.load(Ordering::SeqCst) != 0 {
+            println!("Stopping, after {attempt} of {attempts} attempts.");
+            break;
+        }
+    }
+
+    print_statistics(&finished)?;
+    match finished
+        .iter()
+        .filter(|data| !data.state.is_success())
+        .count()
+    {
+        0 => Ok(()),
+        failed => Err(Error::msg(format!(
+            "Not every attempt succeeded: {failed} of {} failed",
+            finished.len()
+        ))),
+    }
+}
+
+/// Wait for an attempt to finish, passing on whatever signal we are asked to stop with.
+fn wait_for_attempt(run: &Run) -> Result<RunData> {
+    let mut signalled = false;
+    loop {
+        let data = run.get_data()?;
+        if run.is_orphaned(&data) {
+            // Once readopted, the run is marked as done as soon as it finishes
+            let _ = run.readopt();
+        }
+        if let RunDataState::Done { .. } = data.state {
+            return Ok(data);
+        }
+        if let Ok(signal) = Signal::try_from(STOP_SIGNAL.load(Ordering::SeqCst)) {
+            if !signalled {
+                println!("Got {signal}; passing it on to the attempt which is running.");
+                let _ = send_signal(run, signal, false);
+                signalled = true;
+            }
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn wall_time(data: &RunData) -> Result<String> {
+    Ok(format_seconds(wall_seconds(data)?))
+}
+
+fn wall_seconds(data: &RunData) -> Result<f64> {
+    match data.state {
+        RunDataState::Done { end_datetime, .. } => {
+            Ok((end_datetime - data.start_datetime).num_milliseconds() as f64 / 1000.0)
+        }
+        _ => Err(Error::msg("Run is not done")),
+    }
+}
+
+fn print_statistics(finished: &[RunData]) -> Result<()> {
+    let wall = finished
+        .iter()
+        .map(wall_seconds)
+        .collect::<Result<Vec<_>>>()?;
+    let cpu = finished
+        .iter()
+        .filter_map(|data| match data.state {
+            RunDataState::Done {
+                resource_usage: Some(usage),
+                ..
+            } => Some(usage.user_time + usage.system_time),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    println!();
+    print_statistic("Wall clock", &wall);
+    // Attempts whose monitor died have no resource usage, so it's all or nothing
+    if cpu.len() == wall.len() {
+        print_statistic("CPU", &cpu);
+    }
+    Ok(())
+}
+
+fn print_statistic(name: &str, values: &[f64]) {
+    let Some(statistics) = Statistics::of(values) else {
+        return;
+    };
+    println!(
+        "{name:<10}  mean {} ± {}  (min {}, max {}, over {} attempts)",
+        format_seconds(statistics.mean),
+        format_seconds(statistics.stddev),
+        format_seconds(statistics.min),
+        format_seconds(statistics.max),
+        values.len()
+    );
+}
+
+struct Statistics {
+    min: f64,
+    max: f64,
+    mean: f64,
+    /// The sample standard deviation (0 for a single value).
+    stddev: f64,
+}
+
+impl Statistics {
+    fn of(values: &[f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = match values.len() {
+            1 => 0.0,
+            _ => values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0),
+        };
+        Some(Self {
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            mean,
+            stddev: variance.sqrt(),
+        })
+    }
+}
+
+fn format_seconds(seconds: f64) -> String {
+    match seconds {
+        s if s < 1.0 => format!("{:.1}ms", s * 1000.0),
+        s if s < 60.0 => format!("{:.3}s", s),
+        s => format!("{}m {:04.1}s", (s / 60.0) as u64, s % 60.0),
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+
+use crate::{
+    actions::start::start_run,
+    config::{Config, MASKED_VALUE},
+    runs::{Run, Runs, StartOptions},
+};
+
+/// Whether `arg` is an option (rather than e.g. a negative number).
+fn is_option(arg: &str) -> bool {
+    arg.starts_with('-') && arg.parse::<f64>().is_err()
+}
+
+/// Apply `extra` arguments to `command`: the options and `key=value` arguments it already has
+/// (e.g. `--lr 0.1`, `--lr=0.1` or `lr=0.1`) are given the new values, and all other arguments
+/// are appended.
+fn apply_arguments(command: &[String], extra: &[String]) -> Vec<String> {
+    // Where the argument setting `key` is in the command, and whether its value is in the next one
+    let find = |command: &[String], key: &str, as_option: bool| {
+        command.iter().enumerate().skip(1).find_map(|(i, arg)| {
+            if arg
+                .strip_prefix(key)
+                .is_some_and(|rest| rest.starts_with('='))
+            {
+                Some((i, false))
+            } else if as_option
+                && arg == key
+                && command.get(i + 1).is_some_and(|next| !is_option(next))
+            {
+                Some((i, true))
+            } else {
+                None
+            }
+        })
+    };
+
+    let mut command = command.to_vec();
+    let mut extra = extra.iter().peekable();
+    while let Some(arg) = extra.next() {
+        if is_option(arg) {
+            let (key, value) = match arg.split_once('=') {
+                Some((key, value)) => (key, Some(value.to_string())),
+                None => (
+                    arg.as_str(),
+                    extra.next_if(|value| !is_option(value)).cloned(),
+                ),
+            };
+            match (find(&command, key, true), value) {
+                (Some((i, true)), Some(value)) => command[i + 1] = value,
+                (Some((i, _)), Some(value)) => command[i] = format!("{key}={value}"),
+                (None, Some(value)) if arg.contains('=') => command.push(format!("{key}={value}")),
+                (None, Some(value)) => command.extend([key.to_string(), value]),
+                // A flag, which either is there already or isn't
+                (_, None) if command[1..].contains(arg) => (),
+                (_, None) => command.push(arg.clone()),
+            }
+        } else if let Some((key, _)) = arg.split_once('=').filter(|(key, _)| !key.is_empty()) {
+            match find(&command, key, false) {
+                Some((i, _)) => command[i] = arg.clone(),
+                None => command.push(arg.clone()),
+            }
+        } else {
+            command.push(arg.clone());
+        }
+    }
+    command
+}
+
+/// Start a new run with the same command (with `extra` arguments applied to it), label,
+/// environment and working directory as `run`.
+pub fn clone_run(
+    runs: &Runs,
+    config: &Config,
+    run: &Run,
+    extra: &[String],
+    options: &StartOptions,
+) -> Result<Run> {
+    let data = run.get_data()?;
+    if let Some(directory) = &data.working_directory {
+        std::env::set_current_dir(directory)
+            .with_context(|| format!("Could not enter {:?}", directory))?;
+    }
+    let command = apply_arguments(&data.command, extra);
+    let message = format!(
+        "{} {}",
+        format!("Cloning {} as", run.id).dimmed(),
+        shell_words::join(&command)
+    );
+    match options.id_only {
+        true => eprintln!("{}", message),
+        false => println!("{}", message),
+    }
+
+    // Masked values can't be restored, so those variables are left as they are now
+    let env = data
+        .environment
+        .into_iter()
+        .flatten()
+        .filter(|(_, value)| value != MASKED_VALUE)
+        .collect();
+    let options = StartOptions {
+        label: options.label.clone().or(data.label),
+        encrypt: options.encrypt || data.encrypted,
+        env,
+        ..options.clone()
+    };
+    start_run(runs, config, command, &options)
+}
@@ -0,0 +1,153 @@
+use std::io::BufRead;
+
+use anyhow::{Error, Result};
+use chrono::Utc;
+use regex::Regex;
+use tabled::{builder::Builder, settings::Style};
+
+use crate::{
+    config::Encryption,
+    encryption::Key,
+    runs::{Run, RunData, RunDataState, Runs},
+    utils::format_duration,
+};
+
+/// Options controlling how the runs in a group are compared.
+#[derive(Debug, Clone, clap::Args)]
+pub struct CompareOptions {
+    /// Scrape a metric from each run's output with this regex (the last match is used; if the
+    /// regex has a capture group, only that part of the match is), and sort by it
+    #[clap(long, value_name = "REGEX")]
+    pub metric: Option<Regex>,
+
+    /// Sort by the metric in descending order
+    #[clap(long, requires = "metric")]
+    pub descending: bool,
+}
+
+/// Get the parameters a command was given, i.e. its `--key value`, `--key=value` and `key=value`
+/// arguments. Flags without a value get `true` as their value.
+fn parameters(command: &[String]) -> Vec<(String, String)> {
+    let is_option = |arg: &str| arg.starts_with('-') && arg.parse::<f64>().is_err();
+
+    let mut parameters = Vec::new();
+    let mut args = command.iter().skip(1).peekable();
+    while let Some(arg) = args.next() {
+        if is_option(arg) {
+            let arg = arg.trim_start_matches('-');
+            match arg.split_once('=') {
+                Some((key, value)) => parameters.push((key.to_string(), value.to_string())),
+                None => {
+                    let value = args.next_if(|value| !is_option(value));
+                    let value = value.map_or("true".to_string(), String::clone);
+                    parameters.push((arg.to_string(), value));
+                }
+            }
+        } else if let Some((key, value)) = arg.split_once('=').filter(|(key, _)| !key.is_empty()) {
+            parameters.push((key.to_string(), value.to_string()));
+        }
+    }
+    parameters
+}
+
+/// Find the last match of `metric` in the run's output.
+fn scrape_metric(run: &Run, metric: &Regex, key: Option<&Key>) -> Result<Option<f64>> {
+    let mut last = None;
+    for line in run.read_output(key)?.split(b'\n') {
+        let line = String::from_utf8_lossy(&line?).into_owned();
+        for captures in metric.captures_iter(&line) {
+            let value = captures.get(1).or_else(|| captures.get(0));
+            if let Some(value) = value.and_then(|v| v.as_str().trim().parse::<f64>().ok()) {
+                last = Some(value);
+            }
+        }
+    }
+    Ok(last)
+}
+
+/// Tabulate the runs with the given label, showing the parameters which differ between them.
+pub fn compare_runs(
+    runs: &Runs,
+    group: &str,
+    options: &CompareOptions,
+    encryption: &Encryption,
+) -> Result<()> {
+    let mut members = runs
+        .get_all()?
+        .into_iter()
+        .filter_map(|run| run.get_data().ok().map(|data| (run, data)))
+        .filter(|(_, data)| data.label.as_deref() == Some(group))
+        .map(|(run, data)| {
+            let metric = match &options.metric {
+                Some(metric) => scrape_metric(&run, metric, encryption.key_for(&data)?.as_ref())?,
+                None => None,
+            };
+            Ok((run, data, metric))
+        })
+        .collect::<Result<Vec<(Run, RunData, Option<f64>)>>>()?;
+    if members.is_empty() {
+        return Err(Error::msg(format!("No runs are labeled '{}'", group)));
+    }
+
+    members.sort_by_key(|(_, data, _)| data.start_datetime);
+    if options.metric.is_some() {
+        members.sort_by(|(_, _, a), (_, _, b)| match (a, b) {
+            (Some(a), Some(b)) if options.descending => b.total_cmp(a),
+            (Some(a), Some(b)) => a.total_cmp(b),
+            // Runs without the metric go last
+            (a, b) => b.is_some().cmp(&a.is_some()),
+        });
+    }
+
+    // Only show the parameters which actually vary within the group
+    let member_parameters = members
+        .iter()
+        .map(|(_, data, _)| parameters(&data.command))
+        .collect::<Vec<_>>();
+    let value_of = |parameters: &[(String, String)], key: &str| {
+        parameters
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.clone())
+    };
+    let mut keys: Vec<String> = Vec::new();
+    for (key, _) in member_parameters.iter().flatten() {
+        let varies = member_parameters
+            .iter()
+            .any(|p| value_of(p, key) != value_of(&member_parameters[0], key));
+        if varies && !keys.contains(key) {
+            keys.push(key.clone());
+        }
+    }
+
+    let mut builder = Builder::default();
+    let mut header = vec!["ID".to_string()];
+    header.extend(keys.iter().map(|key| key.to_uppercase()));
+    header.extend(["STATUS".to_string(), "DURATION".to_string()]);
+    if options.metric.is_some() {
+        header.push("METRIC".to_string());
+    }
+    builder.push_record(header);
+
+    let now = Utc::now();
+    for ((run, data, metric), parameters) in members.iter().zip(&member_parameters) {
+        let end_datetime = match data.state {
+            RunDataState::Done { end_datetime, .. } => end_datetime,
+            _ => now,
+        };
+        let mut record = vec![run.id[..8].to_string()];
+        record.extend(
+            keys.iter()
+                .map(|key| value_of(parameters, key).unwrap_or_else(|| "-".to_string())),
+        );
+        record.push(data.state.status());
+        record.push(format_duration(end_datetime - data.start_datetime));
+        if options.metric.is_some() {
+            record.push(metric.map_or("-".to_string(), |metric| metric.to_string()));
+        }
+        builder.push_record(record);
+    }
+
+    println!("{}", builder.build().with(Style::blank()));
+    Ok(())
+}
@@ -0,0 +1,406 @@
+use std::{collections::HashSet, path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use nix::{
+    sys::{
+        signal,
+        wait::{waitpid, WaitPidFlag, WaitStatus},
+    },
+    unistd::Pid,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::start::start_run,
+    config::{self, Config},
+    events::Event,
+    filter::Filter,
+    policy::{self, Decision},
+    runs::{Run, RunData, RunDataState, RunId, Runs, StartOptions},
+    utils::{format_datetime, format_duration},
+};
+
+/// What the daemon has already done about a run, so that it doesn't do it again.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct WatchdogState {
+    /// The events the hook was already run for.
+    #[serde(default)]
+    events: HashSet<String>,
+    /// Whether the run was already terminated for being stalled.
+    #[serde(default)]
+    terminated: bool,
+    /// The run this one is a retry of, if any.
+    #[serde(default)]
+    retry_of: Option<RunId>,
+    /// How many times the command was retried to get this run.
+    #[serde(default)]
+    attempt: u32,
+    /// The run this one was retried as, if any.
+    #[serde(default)]
+    retried_as: Option<RunId>,
+}
+
+impl WatchdogState {
+    fn load(run: &Run) -> Self {
+        std::fs::read(run.get_watchdog_file())
+            .ok()
+            .and_then(|contents| serde_json::from_slice(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, run: &Run) -> Result<()> {
+        serde_json::to_writer(std::fs::File::create(run.get_watchdog_file())?, self)?;
+        Ok(())
+    }
+}
+
+/// Keep an eye on runs, every `interval`: apply the retention policy, monitor runs which lost
+/// their monitor again, and apply the watchdog policies. If `resume`, first start the runs which
+/// were still queued when the system shut down again.
+pub fn run_daemon(runs: &Runs, config: &Config, interval: Duration, resume: bool) -> Result<()> {
+    let retry_where = config
+        .watchdog
+        .retry_where
+        .as_deref()
+        .map(Filter::parse)
+        .transpose()
+        .with_context(|| "Invalid `watchdog.retry_where` in the configuration")?;
+    // Runs which were already done before we started are none of our business
+    let started = Utc::now();
+
+    if resume {
+        resume_queue(runs, config)?;
+    }
+    log("Watching runs.");
+    loop {
+        if let Err(e) = crate::actions::prune::prune_runs_automatically(runs, &config.retention) {
+            log(&format!("Could not apply the retention policy: {e}"));
+        }
+
+        let all_runs = runs.get_all()?;
+        let history = all_runs
+            .iter()
+            .filter_map(|r| r.get_data().ok())
+            .collect::<Vec<_>>();
+        for run in &all_runs {
+            if let Err(e) = check_run(runs, config, run, &history, retry_where.as_ref(), started) {
+                log(&format!("Could not check run '{}': {e}", run.id));
+            }
+        }
+
+        std::thread::sleep(interval);
+        reap_children();
+    }
+}
+
+/// Start the runs which were still queued (for a lock or a quota) when the system shut down (and
+/// so were marked as interrupted; see [`Runs::mark_interrupted_by_reboot`]) again, in the order
+/// they were queued in, unless they were already.
+fn resume_queue(runs: &Runs, config: &Config) -> Result<()> {
+    let all_runs = runs.get_all()?;
+    let mut interrupted = all_runs
+        .into_iter()
+        .filter_map(|run| {
+            let data = run.get_data().ok()?;
+            let events = run.get_events().ok()?;
+            let started = events
+                .iter()
+                .any(|entry| matches!(entry.event, Event::Started { .. } | Event::Adopted { .. }));
+            // Whether for a lock (see `--lock`) or for a slot of a quota
+            let waited = events
+                .iter()
+                .any(|entry| matches!(entry.event, Event::Queued { .. }));
+            let interrupted = matches!(data.state, RunDataState::Done { exit_code: -4, .. });
+            let was_queued = interrupted && waited && !started;
+            was_queued.then_some((run, data))
+        })
+        .collect::<Vec<_>>();
+    interrupted.sort_by_key(|(_, data)| data.start_datetime);
+
+    for (run, data) in interrupted {
+        let mut state = WatchdogState::load(&run);
+        if state.retried_as.is_some() {
+            continue;
+        }
+        let resumed = match retry_run(runs, config, &data) {
+            Ok(resumed) => resumed,
+            Err(e) => {
+                log(&format!("Could not resume run '{}': {e}", run.id));
+                continue;
+            }
+        };
+        run.record_event(Event::Retried {
+            as_run: resumed.id.clone(),
+        });
+        state.retried_as = Some(resumed.id.clone());
+        state.save(&run)?;
+        log(&format!(
+            "Resumed run '{}', which was queued when the system shut down, as run '{}'.",
+            run.id, resumed.id
+        ));
+    }
+    Ok(())
+}
+
+/// Get a systemd user unit running the daemon (with `rum` as found at `executable`), so that it
+/// keeps running across reboots.
+pub fn systemd_unit(executable: &Path, interval: Duration, resume: bool, global: bool) -> String {
+    let mut command = vec![
+        executable.to_string_lossy().into_owned(),
+        "-daemon".to_string(),
+        "--interval".to_string(),
+        format!("{}s", interval.as_secs()),
+    ];
+    if resume {
+        command.push("--resume".to_string());
+    }
+    if global {
+        command.push("--global".to_string());
+    }
+    // The daemon should look after the same runs, with the same configuration, as we would
+    let environment = ["XDG_DATA_HOME", "XDG_CONFIG_HOME"]
+        .into_iter()
+        .filter_map(|name| {
+            Some(format!(
+                "Environment={name}={}\n",
+                std::env::var(name).ok()?
+            ))
+        })
+        .collect::<String>();
+    format!(
+        "[Unit]\n\
+         Description=rum daemon, keeping an eye on runs\n\
+         \n\
+         [Service]\n\
+         ExecStart={}\n\
+         {environment}\
+         Restart=on-failure\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        shell_words::join(&command)
+    )
+}
+
+/// The monitors of the runs we start (or readopt) are our children, so we need to wait for them
+/// once they're done, lest they linger around as zombies.
+fn reap_children() {
+    while let Ok(status) = waitpid(None, Some(WaitPidFlag::WNOHANG)) {
+        if status == WaitStatus::StillAlive {
+            break;
+        }
+    }
+}
+
+fn check_run(
+    runs: &Runs,
+    config: &Config,
+    run: &Run,
+    history: &[RunData],
+    retry_where: Option<&Filter>,
+    started: DateTime<Utc>,
+) -> Result<()> {
+    let watchdog = &config.watchdog;
+    let mut data = run.get_data()?;
+
+    if run.is_orphaned(&data) {
+        match run.readopt()? {
+            true => log(&format!("Monitoring run '{}' again.", run.id)),
+            false => log(&format!("Run '{}' is no longer running.", run.id)),
+        }
+        data = run.get_data()?;
+    }
+
+    let mut state = WatchdogState::load(run);
+    let previous_state = serde_json::to_string(&state)?;
+
+    let mut decisions = Vec::new();
+    match data.state {
+        RunDataState::Running { pgid, .. } => {
+            let stalled_for = run.stalled_for(&data);
+            if stalled_for.is_some() {
+                decisions.extend(fire(config, &mut state, run, &data, "stalled"));
+            }
+            if data.is_overdue(history) {
+                decisions.extend(fire(config, &mut state, run, &data, "overdue"));
+            }
+
+            if let (Some(stalled_for), Some(terminate_after)) =
+                (stalled_for, watchdog.terminate_stalled_after)
+            {
+                if stalled_for > terminate_after && !state.terminated {
+                    terminate(run, pgid, &mut state)?;
+                    log(&format!(
+                        "Terminated run '{}', which was stalled for {}.",
+                        run.id,
+                        format_duration(chrono::Duration::from_std(stalled_for)?),
+                    ));
+                    decisions.extend(fire(config, &mut state, run, &data, "terminated"));
+                }
+            }
+        }
+        RunDataState::Queued { .. } => (),
+        RunDataState::Done { end_datetime, .. } if end_datetime > started => {
+            if !data.state.is_success() {
+                decisions.extend(fire(config, &mut state, run, &data, "failed"));
+            }
+
+            if retry_where.is_some_and(|filter| filter.matches(run, &data)) {
+                retry(runs, config, run, &data, &mut state)?;
+            }
+        }
+        RunDataState::Done { .. } => (),
+    }
+
+    for decision in decisions {
+        if let Some(message) = decision.log {
+            log(&format!("Run '{}': {}", run.id, message));
+        }
+        if let Some(label) = decision.label {
+            run.set_label(Some(label.clone()))?;
+            log(&format!("Labeled run '{}' as '{}'.", run.id, label));
+        }
+        match data.state {
+            RunDataState::Running { pgid, .. } if decision.terminate && !state.terminated => {
+                terminate(run, pgid, &mut state)?;
+                log(&format!(
+                    "Terminated run '{}', as the policy decided.",
+                    run.id
+                ));
+            }
+            RunDataState::Done { .. } if decision.retry => {
+                retry(runs, config, run, &data, &mut state)?;
+            }
+            _ => (),
+        }
+    }
+
+    if serde_json::to_string(&state)? != previous_state {
+        state.save(run)?;
+    }
+    Ok(())
+}
+
+fn terminate(run: &Run, pgid: Pid, state: &mut WatchdogState) -> Result<()> {
+    signal::killpg(pgid, signal::Signal::SIGTERM)
+        .with_context(|| "Couldn't send signal to run's process")?;
+    run.record_event(Event::Signalled {
+        signal: signal::Signal::SIGTERM.to_string(),
+    });
+    state.terminated = true;
+    Ok(())
+}
+
+/// Start `run` again, unless it was already retried, or retried too many times.
+fn retry(
+    runs: &Runs,
+    config: &Config,
+    run: &Run,
+    data: &RunData,
+    state: &mut WatchdogState,
+) -> Result<()> {
+    let watchdog = &config.watchdog;
+    if state.retried_as.is_some() || state.attempt >= watchdog.max_retries {
+        return Ok(());
+    }
+
+    let retry = retry_run(runs, config, data)?;
+    state.retried_as = Some(retry.id.clone());
+    run.record_event(Event::Retried {
+        as_run: retry.id.clone(),
+    });
+    WatchdogState {
+        retry_of: Some(run.id.clone()),
+        attempt: state.attempt + 1,
+        ..Default::default()
+    }
+    .save(&retry)?;
+    log(&format!(
+        "Retrying run '{}' as run '{}' (attempt {} of {}).",
+        run.id,
+        retry.id,
+        state.attempt + 1,
+        watchdog.max_retries,
+    ));
+    // Nothing more is to be decided about a retry
+    let _ = fire(config, state, run, data, "retried");
+    Ok(())
+}
+
+/// Start the command of a run again, from the same directory and with the same options (as far
+/// as they were recorded).
+fn retry_run(runs: &Runs, config: &Config, data: &RunData) -> Result<Run> {
+    let options = StartOptions {
+        working_directory: data.working_directory.clone(),
+        label: data.label.clone(),
+        artifacts: data.artifacts.clone(),
+        expect: data.expected_duration,
+        stall_after: data.stall_after,
+        oom_score_adj: data.oom_score_adj,
+        ulimits: data.ulimits.clone(),
+        umask: data.umask,
+        cpus: data.cpus.clone(),
+        no_network: data.no_network,
+        sandbox: data.sandbox.as_ref().map(|sandbox| sandbox.preset),
+        log_to: data.log_to.clone(),
+        encrypt: data.encrypted,
+        lock: data.lock.clone(),
+        ..Default::default()
+    };
+    start_run(runs, config, data.command.clone(), &options)
+}
+
+/// Run the hook for `event` on `run`, and ask the policy what to do about it, unless this was
+/// already done for it.
+fn fire(
+    config: &Config,
+    state: &mut WatchdogState,
+    run: &Run,
+    data: &RunData,
+    event: &str,
+) -> Option<Decision> {
+    if !state.events.insert(event.to_string()) {
+        return None;
+    }
+    log(&format!("Run '{}': {}.", run.id, event));
+
+    let watchdog = &config.watchdog;
+    if let Some(hook) = &watchdog.hook {
+        let status = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(hook)
+            .env("RUM_EVENT", event)
+            .env("RUM_RUN_ID", &run.id)
+            .env("RUM_STATUS", data.state.status())
+            .env("RUM_LABEL", data.label.as_deref().unwrap_or(""))
+            .env("RUM_COMMAND", shell_words::join(&data.command))
+            .status();
+        match status {
+            Ok(status) if status.success() => (),
+            Ok(status) => log(&format!("The hook failed for run '{}': {status}", run.id)),
+            Err(e) => log(&format!("Could not run the hook for run '{}': {e}", run.id)),
+        }
+    }
+
+    let policy = watchdog.policy.as_deref()?;
+    let key = config.encryption.key_for(data).ok().flatten();
+    let decision = config::config_directory().and_then(|directory| {
+        policy::decide(&directory.join(policy), event, run, data, key.as_ref())
+    });
+    match decision {
+        Ok(decision) => Some(decision),
+        Err(e) => {
+            log(&format!(
+                "Could not apply the policy to run '{}': {e:#}",
+                run.id
+            ));
+            None
+        }
+    }
+}
+
+fn log(message: &str) {
+    println!("{} {}", format_datetime(Utc::now()).dimmed(), message);
+}
@@ -0,0 +1,265 @@
+use std::{
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Error, Result};
+use colored::Colorize;
+use nix::unistd::{access, AccessFlags, Uid};
+
+use crate::{
+    runs::{NewerSchema, Run, RunData, Runs},
+    theme::{self, paint},
+};
+
+/// How recently a run's directory must have been changed for it to be left alone even though it
+/// has no data, as it may be a run which is being started right now.
+const CREATION_GRACE_PERIOD: Duration = Duration::from_secs(60);
+
+/// Something wrong with the store.
+struct Problem {
+    description: String,
+    fix: Fix,
+}
+
+/// What `--fix` does about a [`Problem`].
+enum Fix {
+    /// Give ourselves the permissions we lack on a file or directory we own.
+    Permissions(PathBuf),
+    /// Put back the run's data from the temporary file it was being written to.
+    RestoreData(Run),
+    /// Start monitoring the run again (which marks it as done, if it isn't running anymore).
+    Readopt(Run),
+    /// Mark the run as done, with an unknown exit code.
+    MarkDone(Run),
+    /// Move the file or directory out of the store (see [`Runs::quarantine`]).
+    Quarantine(PathBuf),
+    /// Nothing rum can do; the description says what to do instead.
+    Manual,
+}
+
+impl Fix {
+    fn describe(&self) -> Option<&'static str> {
+        Some(match self {
+            Fix::Permissions(_) => "give yourself access to it",
+            Fix::RestoreData(_) => "restore its data from data.json.tmp",
+            Fix::Readopt(_) => "monitor it again, or mark it as done if it isn't running",
+            Fix::MarkDone(_) => "mark it as done, with an unknown exit code",
+            Fix::Quarantine(_) => "move it to the quarantine",
+            Fix::Manual => return None,
+        })
+    }
+
+    fn apply(&self, runs: &Runs) -> Result<String> {
+        match self {
+            Fix::Permissions(path) => {
+                let mut permissions = std::fs::metadata(path)?.permissions();
+                let mode = match path.is_dir() {
+                    true => 0o700,
+                    false => 0o600,
+                };
+                permissions.set_mode(permissions.mode() | mode);
+                std::fs::set_permissions(path, permissions)?;
+                Ok("gave you access to it".to_string())
+            }
+            Fix::RestoreData(run) => {
+                std::fs::rename(
+                    run.get_data_file().with_extension("json.tmp"),
+                    run.get_data_file(),
+                )?;
+                Ok("restored its data".to_string())
+            }
+            Fix::Readopt(run) => Ok(match run.readopt()? {
+                true => "monitoring it again".to_string(),
+                false => "marked it as done".to_string(),
+            }),
+            Fix::MarkDone(run) => {
+                run.mark_done(-3)?;
+                Ok("marked it as done".to_string())
+            }
+            Fix::Quarantine(path) => {
+                let destination = runs.quarantine(path)?;
+                Ok(format!("moved it to {}", destination.display()))
+            }
+            Fix::Manual => unreachable!("only described, never applied"),
+        }
+    }
+}
+
+/// Check the store for problems: files and directories we can't access, runs whose data is
+/// missing or corrupt, stray files, and runs stuck as running (or queued) although nothing is
+/// running or monitoring them anymore. With `fix`, repair what can be repaired, and move what
+/// can't be out of the way (to the quarantine). Fails if any problem is left.
+pub fn run_doctor(runs: &Runs, fix: bool) -> Result<()> {
+    let mut problems = Vec::new();
+    for directory in [runs.get_data_directory(), runs.get_runs_directory()] {
+        problems.extend(check_access(directory));
+    }
+    // Nothing else can be checked without access to the runs
+    if problems.is_empty() {
+        let mut entries = std::fs::read_dir(runs.get_runs_directory())?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        entries.sort();
+        for path in entries {
+            problems.extend(check_entry(&path));
+        }
+    }
+
+    if problems.is_empty() {
+        println!("No problems found.");
+        return Ok(());
+    }
+    let theme = theme::get();
+    let mut left = 0;
+    for problem in &problems {
+        let problem_label = paint("PROBLEM", theme.warning).bold();
+        let Some(fix_description) = problem.fix.describe() else {
+            println!("{}: {}", problem_label, problem.description);
+            left += 1;
+            continue;
+        };
+        if !fix {
+            println!(
+                "{}: {} (--fix would {})",
+                problem_label, problem.description, fix_description
+            );
+            left += 1;
+            continue;
+        }
+        match problem.fix.apply(runs) {
+            Ok(done) => println!(
+                "{}: {}; {}.",
+                paint("FIXED", theme.note).bold(),
+                problem.description,
+                done
+            ),
+            Err(e) => {
+                println!(
+                    "{}: {} (could not {}: {})",
+                    problem_label, problem.description, fix_description, e
+                );
+                left += 1;
+            }
+        }
+    }
+    match left {
+        0 => Ok(()),
+        _ if fix => Err(Error::msg(format!("{left} problem(s) could not be fixed"))),
+        _ => Err(Error::msg(format!(
+            "Found {left} problem(s); see `rum -doctor --fix`"
+        ))),
+    }
+}
+
+/// Check that we can read and write `path` (and go into it, if it's a directory).
+fn check_access(path: &Path) -> Option<Problem> {
+    let is_dir = path.is_dir();
+    let mut flags = AccessFlags::R_OK | AccessFlags::W_OK;
+    if is_dir {
+        flags |= AccessFlags::X_OK;
+    }
+    access(path, flags).err()?;
+
+    let owner = std::fs::metadata(path).ok()?.uid();
+    let what = if is_dir { "directory" } else { "file" };
+    Some(match owner == Uid::current().as_raw() {
+        true => Problem {
+            description: format!("You lack permissions on the {what} {}", path.display()),
+            fix: Fix::Permissions(path.to_path_buf()),
+        },
+        false => Problem {
+            description: format!(
+                "The {what} {} belongs to another user (UID {owner}), so you can't use it; \
+                 `chown` it to yourself",
+                path.display()
+            ),
+            fix: Fix::Manual,
+        },
+    })
+}
+
+/// Check an entry of the runs directory, which should be a run's directory, with valid data.
+fn check_entry(path: &Path) -> Option<Problem> {
+    let name = path.file_name()?.to_string_lossy().into_owned();
+    if !path.is_dir() {
+        return Some(Problem {
+            description: format!("{} is not a run", path.display()),
+            fix: Fix::Quarantine(path.to_path_buf()),
+        });
+    }
+    if let Some(problem) = check_access(path) {
+        return Some(problem);
+    }
+    let run = Run {
+        id: name.clone(),
+        run_directory: path.to_path_buf(),
+    };
+
+    let data_file = run.get_data_file();
+    if data_file.exists() {
+        if let Some(problem) = check_access(&data_file) {
+            return Some(problem);
+        }
+    }
+    let data = match run.get_data() {
+        Ok(data) => data,
+        // Nothing's wrong with it; it just isn't for us to read
+        Err(e) if e.downcast_ref::<NewerSchema>().is_some() => {
+            return Some(Problem {
+                description: format!("Run '{}' can't be read: {}", name, e),
+                fix: Fix::Manual,
+            });
+        }
+        Err(e) => {
+            let description = match data_file.exists() {
+                true => format!("Run '{}' has corrupt data ({:#})", name, e),
+                false => format!("Run '{}' has no data", name),
+            };
+            let temporary_file = data_file.with_extension("json.tmp");
+            let has_valid_temporary_file = std::fs::File::open(temporary_file)
+                .ok()
+                .and_then(|file| serde_json::from_reader::<_, RunData>(file).ok())
+                .is_some();
+            let is_new = std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| {
+                    modified.elapsed().unwrap_or_default() < CREATION_GRACE_PERIOD
+                });
+            return match (has_valid_temporary_file, data_file.exists()) {
+                (true, _) => Some(Problem {
+                    description,
+                    fix: Fix::RestoreData(run),
+                }),
+                (false, false) if is_new => None,
+                (false, _) => Some(Problem {
+                    description,
+                    fix: Fix::Quarantine(path.to_path_buf()),
+                }),
+            };
+        }
+    };
+
+    if run.is_orphaned(&data) {
+        return Some(Problem {
+            description: format!(
+                "Run '{}' is {}, but nothing is monitoring it",
+                name,
+                data.state.status()
+            ),
+            fix: Fix::Readopt(run),
+        });
+    }
+    if run.is_stuck(&data) {
+        return Some(Problem {
+            description: format!(
+                "Run '{}' is marked as running, but its process is gone",
+                name
+            ),
+            fix: Fix::MarkDone(run),
+        });
+    }
+    None
+}
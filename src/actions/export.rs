@@ -0,0 +1,222 @@
+use std::{collections::BTreeMap, io::Write};
+
+use anyhow::Result;
+use chrono::Utc;
+
+use crate::{
+    config::Config,
+    filter::Filter,
+    output::AnsiStripper,
+    runs::{Run, RunData, RunDataState, Runs},
+};
+
+/// Write the metadata of the (matching) runs to stdout as CSV, one row per run.
+pub fn export_csv(runs: &Runs, filter: Option<&Filter>) -> Result<()> {
+    let mut rows = runs
+        .get_all()?
+        .into_iter()
+        .filter_map(|run| run.get_data().ok().map(|data| (run, data)))
+        .filter(|(run, data)| filter.is_none_or(|filter| filter.matches(run, data)))
+        .collect::<Vec<_>>();
+    rows.sort_by_key(|(_, data)| data.start_datetime);
+
+    let mut writer = csv::Writer::from_writer(std::io::stdout());
+    writer.write_record([
+        "id",
+        "label",
+        "command",
+        "status",
+        "exit_code",
+        "start",
+        "end",
+        "duration",
+    ])?;
+    let now = Utc::now();
+    for (run, data) in rows {
+        let (exit_code, end_datetime) = match data.state {
+            RunDataState::Done {
+                exit_code,
+                end_datetime,
+                ..
+            } => (Some(exit_code), Some(end_datetime)),
+            _ => (None, None),
+        };
+        let duration = end_datetime.unwrap_or(now) - data.start_datetime;
+        writer.write_record([
+            run.id.clone(),
+            data.label.unwrap_or_default(),
+            shell_words::join(&data.command),
+            data.state.status(),
+            exit_code.map(|c| c.to_string()).unwrap_or_default(),
+            data.start_datetime.to_rfc3339(),
+            end_datetime.map(|e| e.to_rfc3339()).unwrap_or_default(),
+            // In seconds, so that it is easy to work with
+            format!("{:.3}", duration.num_milliseconds() as f64 / 1000.0),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write the (matching) runs to stdout as a JUnit XML report, for CI systems and test report
+/// viewers to show: each run is a test case, with the last `lines` lines of its output if it
+/// failed. Runs are grouped into test suites by the run they were started from (e.g. a pipeline's
+/// steps), or else by label (e.g. a parameter sweep's runs).
+///
+/// If `run` is given, only the runs started from within it are exported (or the run itself, if
+/// there are none), as a single test suite.
+pub fn export_junit(
+    runs: &Runs,
+    config: &Config,
+    run: Option<&Run>,
+    filter: Option<&Filter>,
+    lines: usize,
+) -> Result<()> {
+    let mut all = runs
+        .get_all()?
+        .into_iter()
+        .filter_map(|run| run.get_data().ok().map(|data| (run, data)))
+        .collect::<Vec<_>>();
+    all.sort_by_key(|(_, data)| data.start_datetime);
+    let suite_name = |run: &Run, data: &RunData| {
+        data.label
+            .clone()
+            .unwrap_or_else(|| format!("{} {}", &run.id[..8], shell_words::join(&data.command)))
+    };
+
+    let mut suites = BTreeMap::<String, Vec<(Run, RunData)>>::new();
+    match run {
+        Some(run) => {
+            let data = run.get_data()?;
+            let mut children = all
+                .into_iter()
+                .filter(|(_, child)| child.parent.as_ref() == Some(&run.id))
+                .filter(|(run, data)| filter.is_none_or(|filter| filter.matches(run, data)))
+                .collect::<Vec<_>>();
+            if children.is_empty() {
+                children.push((run.clone(), data.clone()));
+            }
+            suites.insert(suite_name(run, &data), children);
+        }
+        None => {
+            let names = all
+                .iter()
+                .map(|(run, data)| (run.id.clone(), suite_name(run, data)))
+                .collect::<BTreeMap<_, _>>();
+            for (run, data) in all {
+                if !filter.is_none_or(|filter| filter.matches(&run, &data)) {
+                    continue;
+                }
+                let suite = match (&data.parent, &data.label) {
+                    (Some(parent), _) if names.contains_key(parent) => names[parent].clone(),
+                    (_, Some(label)) => label.clone(),
+                    _ => "rum".to_string(),
+                };
+                suites.entry(suite).or_default().push((run, data));
+            }
+        }
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    let now = Utc::now();
+    writeln!(stdout, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(stdout, "<testsuites>")?;
+    for (suite, cases) in suites {
+        let count =
+            |f: fn(&RunDataState) -> bool| cases.iter().filter(|(_, d)| f(&d.state)).count();
+        let duration = |data: &RunData| match data.state {
+            RunDataState::Done { end_datetime, .. } => end_datetime - data.start_datetime,
+            _ => now - data.start_datetime,
+        };
+        let total = cases
+            .iter()
+            .map(|(_, data)| duration(data))
+            .fold(chrono::Duration::zero(), |a, b| a + b);
+        writeln!(
+            stdout,
+            r#"  <testsuite name="{}" tests="{}" failures="{}" errors="{}" skipped="{}" time="{}" timestamp="{}">"#,
+            escape(&suite),
+            cases.len(),
+            count(RunDataState::is_failure),
+            count(|state| matches!(state, RunDataState::Done { .. })
+                && !state.is_success()
+                && !state.is_failure()),
+            count(|state| !matches!(state, RunDataState::Done { .. })),
+            seconds(total),
+            cases
+                .first()
+                .map(|(_, data)| data.start_datetime)
+                .unwrap_or(now)
+                .format("%Y-%m-%dT%H:%M:%S"),
+        )?;
+        for (run, data) in &cases {
+            // The runs of a sweep share their label, which is the suite's name already
+            let name = match &data.label {
+                Some(label) if *label != suite => label.clone(),
+                _ => shell_words::join(&data.command),
+            };
+            write!(
+                stdout,
+                r#"    <testcase name="{}" classname="{}" time="{}""#,
+                escape(&name),
+                escape(&suite),
+                seconds(duration(data))
+            )?;
+            let status = data.state.status();
+            let element = match &data.state {
+                RunDataState::Done { .. } if data.state.is_success() => {
+                    writeln!(stdout, "/>")?;
+                    continue;
+                }
+                RunDataState::Done { .. } if data.state.is_failure() => "failure",
+                RunDataState::Done { .. } => "error",
+                _ => {
+                    writeln!(stdout, ">")?;
+                    writeln!(stdout, r#"      <skipped message="{}"/>"#, status)?;
+                    writeln!(stdout, "    </testcase>")?;
+                    continue;
+                }
+            };
+            let excerpt = config
+                .encryption
+                .key_for(data)
+                .and_then(|key| run.read_last_lines(lines, key.as_ref()))
+                .map(|lines| lines.join("\n"))
+                .unwrap_or_default();
+            writeln!(stdout, ">")?;
+            writeln!(
+                stdout,
+                r#"      <{element} message="{}" type="{}">{}</{element}>"#,
+                escape(&format!("Run {} {}", run.id, status)),
+                escape(&status),
+                escape(&plain_text(&excerpt)),
+            )?;
+            writeln!(stdout, "    </testcase>")?;
+        }
+        writeln!(stdout, "  </testsuite>")?;
+    }
+    writeln!(stdout, "</testsuites>")?;
+    Ok(())
+}
+
+fn seconds(duration: chrono::Duration) -> String {
+    format!("{:.3}", duration.num_milliseconds() as f64 / 1000.0)
+}
+
+/// Strip escape sequences (e.g. colors) from output.
+fn plain_text(output: &str) -> String {
+    let mut stripped = Vec::new();
+    // Writing to a vector can't fail
+    let _ = AnsiStripper::new(&mut stripped).write_all(output.as_bytes());
+    String::from_utf8_lossy(&stripped).into_owned()
+}
+
+fn escape(text: &str) -> String {
+    text.chars()
+        .filter(|c| matches!(c, '\n' | '\t') || !c.is_control())
+        .collect::<String>()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,110 @@
+use anyhow::{Error, Result};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+
+use crate::{
+    actions::start::start_run,
+    config::Config,
+    runs::{Run, RunData, RunDataState, Runs, StartOptions},
+    theme::{self, paint},
+    utils::format_datetime,
+};
+
+/// All the runs of a single command.
+struct CommandHistory {
+    command: Vec<String>,
+    last_started: DateTime<Utc>,
+    runs: usize,
+    successes: usize,
+    failures: usize,
+}
+
+/// Get all the runs whose command contains `pattern`, most recently started first.
+fn matching_runs(runs: &Runs, pattern: Option<&str>) -> Result<Vec<RunData>> {
+    let mut matching = runs
+        .get_all()?
+        .iter()
+        .filter_map(|run| run.get_data().ok())
+        .filter(|data| pattern.is_none_or(|p| shell_words::join(&data.command).contains(p)))
+        .collect::<Vec<_>>();
+    matching.sort_by_key(|data| std::cmp::Reverse(data.start_datetime));
+    Ok(matching)
+}
+
+pub fn show_history(runs: &Runs, pattern: Option<&str>) -> Result<()> {
+    let mut history: Vec<CommandHistory> = Vec::new();
+    for data in matching_runs(runs, pattern)? {
+        let entry = match history.iter_mut().find(|h| h.command == data.command) {
+            Some(entry) => entry,
+            None => {
+                history.push(CommandHistory {
+                    command: data.command.clone(),
+                    last_started: data.start_datetime,
+                    runs: 0,
+                    successes: 0,
+                    failures: 0,
+                });
+                history.last_mut().unwrap()
+            }
+        };
+        entry.runs += 1;
+        match data.state {
+            ref state if state.is_success() => entry.successes += 1,
+            // Neither is the run's fault
+            RunDataState::Done { exit_code, .. } if exit_code != -3 && exit_code != -4 => {
+                entry.failures += 1
+            }
+            _ => (),
+        }
+    }
+
+    for entry in history {
+        let stats = format!(
+            "{} run{}, {} ok, {} failed",
+            entry.runs,
+            if entry.runs == 1 { "" } else { "s" },
+            entry.successes,
+            entry.failures,
+        );
+        println!(
+            "{} {} {}",
+            format_datetime(entry.last_started).dimmed(),
+            if entry.failures > 0 {
+                paint(&stats, theme::get().failure)
+            } else {
+                paint(&stats, theme::get().success)
+            },
+            shell_words::join(&entry.command).bold(),
+        );
+    }
+    Ok(())
+}
+
+/// Start the most recently run command containing `pattern` again.
+pub fn run_again(
+    runs: &Runs,
+    config: &Config,
+    pattern: Option<&str>,
+    options: &StartOptions,
+) -> Result<Run> {
+    let data = matching_runs(runs, pattern)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::msg("No past command matches"))?;
+    let message = format!(
+        "{} {}",
+        "Running".dimmed(),
+        shell_words::join(&data.command)
+    );
+    match options.id_only {
+        true => eprintln!("{}", message),
+        false => println!("{}", message),
+    }
+
+    let options = StartOptions {
+        label: options.label.clone().or(data.label),
+        encrypt: options.encrypt || data.encrypted,
+        ..options.clone()
+    };
+    start_run(runs, config, data.command, &options)
+}
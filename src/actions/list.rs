@@ -1,83 +1,563 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    sync::mpsc::{channel, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
 use anyhow::Result;
 use colored::Colorize;
+use notify::Watcher;
 
 use crate::{
-    runs::{RunData, RunDataState, Runs},
-    utils::format_datetime,
+    config::project_directory,
+    events,
+    filter::Filter,
+    runs::{Run, RunData, RunDataState, RunId, Runs},
+    theme::{self, paint},
+    utils::{format_datetime, format_duration, format_size, parse_duration},
 };
 
-pub fn list_runs(runs: &Runs) -> Result<()> {
-    let (runs, bad_runs): (Vec<_>, Vec<_>) = runs
-        .get_all()?
+/// How often the listing is redrawn at most with `--watch`, however often runs change.
+const MIN_REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Options controlling how runs are listed.
+#[derive(Debug, Clone, clap::Args)]
+pub struct ListOptions {
+    /// How to order the runs
+    #[clap(long, value_enum, default_value = "start")]
+    pub sort: SortKey,
+
+    /// Only list runs matching this filter (e.g. `status=failed and started<1d`)
+    #[clap(long = "where", value_name = "FILTER", value_parser = Filter::parse)]
+    pub filter: Option<Filter>,
+
+    /// Only list the runs of this user (in a store shared by several users)
+    #[clap(long, value_name = "NAME")]
+    pub user: Option<String>,
+
+    /// Group the runs by project (the git repository, or else the directory, they were started
+    /// from) and label, with how many runs of each group are in each state
+    #[clap(long)]
+    pub tree: bool,
+
+    /// Keep the listing up to date, redrawing it whenever runs change (and at least every
+    /// INTERVAL, 2s by default)
+    #[clap(
+        long,
+        value_name = "INTERVAL",
+        value_parser = parse_duration,
+        num_args = 0..=1,
+        default_missing_value = "2s"
+    )]
+    pub watch: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortKey {
+    /// Running runs first, then by start time
+    Start,
+    /// By disk usage, largest last
+    Size,
+}
+
+pub fn list_runs(runs: &Runs, options: &ListOptions) -> Result<()> {
+    match options.watch {
+        Some(interval) => watch_runs(runs, options, interval),
+        None => print_runs(runs, options),
+    }
+}
+
+/// List the runs again and again (until interrupted), whenever they change or `interval` passes.
+fn watch_runs(runs: &Runs, options: &ListOptions, interval: Duration) -> Result<()> {
+    let (tx, rx) = channel();
+    // Without a watcher (e.g. on NFS), we just redraw every `interval`
+    let _watcher = notify::raw_watcher(tx)
+        .and_then(|mut watcher| {
+            watcher.watch(runs.get_runs_directory(), notify::RecursiveMode::Recursive)?;
+            Ok(watcher)
+        })
+        .ok();
+    // Runs' output changes all the time, but only their data matters to the listing
+    let is_relevant = |event: &notify::RawEvent| {
+        event.path.as_ref().is_some_and(|path| {
+            path.parent() == Some(runs.get_runs_directory())
+                || path.file_name().is_some_and(|name| name == "data.json")
+        })
+    };
+
+    loop {
+        print!("{}{}", termion::clear::All, termion::cursor::Goto(1, 1));
+        println!(
+            "{}",
+            format!(
+                "Every {}, or whenever runs change. Press Ctrl+C to exit.",
+                format_duration(chrono::Duration::from_std(interval)?)
+            )
+            .dimmed()
+        );
+        println!();
+        print_runs(runs, options)?;
+        std::io::stdout().flush()?;
+
+        let drawn = Instant::now();
+        loop {
+            match rx.recv_timeout(interval.saturating_sub(drawn.elapsed())) {
+                Ok(event) if is_relevant(&event) => break,
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => break,
+                // The sender is dropped right away when there is no watcher
+                Err(RecvTimeoutError::Disconnected) => {
+                    std::thread::sleep(interval.saturating_sub(drawn.elapsed()));
+                    break;
+                }
+            }
+        }
+        std::thread::sleep(MIN_REDRAW_INTERVAL.saturating_sub(drawn.elapsed()));
+        // Changes usually come in bursts, all of which the next listing covers
+        while rx.try_recv().is_ok() {}
+    }
+}
+
+fn print_runs(runs: &Runs, options: &ListOptions) -> Result<()> {
+    let all_runs = runs.get_all()?;
+    for run in &all_runs {
+        if run.get_data().is_ok_and(|d| run.is_orphaned(&d)) {
+            // TODO change into logging
+            match run.readopt() {
+                Ok(true) => println!(
+                    "{}: Run '{}' had lost its monitor; monitoring it again.",
+                    paint("NOTE", theme::get().note).bold(),
+                    run.id,
+                ),
+                Ok(false) => println!(
+                    "{}: Run '{}' had lost its monitor, and is no longer running.",
+                    paint("NOTE", theme::get().note).bold(),
+                    run.id,
+                ),
+                Err(e) => println!(
+                    "{}: Run '{}' has lost its monitor, and could not be readopted: {}",
+                    paint("WARNING", theme::get().warning).bold(),
+                    run.id,
+                    e,
+                ),
+            }
+        }
+    }
+
+    let (listed_runs, bad_runs): (Vec<_>, Vec<_>) = all_runs
         .iter()
+        .filter(|r| match (&options.filter, &options.user) {
+            // Runs which can't be read still get reported (as warnings) when not filtering
+            (None, None) => true,
+            (filter, user) => r.get_data().is_ok_and(|d| {
+                filter.as_ref().is_none_or(|filter| filter.matches(r, &d))
+                    && user
+                        .as_ref()
+                        .is_none_or(|user| d.owner.as_ref() == Some(user))
+            }),
+        })
         .map(|r| {
             r.get_data()
-                .map(|d| (r.id.clone(), d))
+                .map(|d| (r.clone(), d, r.get_disk_usage().unwrap_or(0)))
                 .map_err(|_| r.id.clone())
         })
         .partition(Result::is_ok);
-    let mut runs = runs.into_iter().map(Result::unwrap).collect::<Vec<_>>();
+    let mut listed_runs = listed_runs
+        .into_iter()
+        .map(Result::unwrap)
+        .collect::<Vec<_>>();
     let bad_runs = bad_runs.into_iter().map(Result::unwrap_err);
-    runs.sort_by_key(|(_, r)| r.start_datetime);
-    runs.sort_by_key(|(_, r)| match r.state {
-        RunDataState::Running { .. } => 0,
-        RunDataState::Done { .. } => 1,
-    });
+    listed_runs.sort_by_key(|(_, r, _)| r.start_datetime);
+    match options.sort {
+        SortKey::Start => listed_runs.sort_by_key(|(_, r, _)| match r.state {
+            RunDataState::Running { .. } => 0,
+            RunDataState::Queued { .. } => 1,
+            RunDataState::Done { .. } => 2,
+        }),
+        SortKey::Size => listed_runs.sort_by_key(|(_, _, size)| *size),
+    }
+    let listed_runs = nest(listed_runs);
 
     for bad_run in bad_runs {
         // TODO change into logging
         println!(
-            "{}: Could not read run '{}'; ignoring it.",
-            "WARNING".bold().yellow(),
+            "{}: Could not read run '{}'; ignoring it (see `rum -doctor`).",
+            paint("WARNING", theme::get().warning).bold(),
             bad_run,
         );
     }
 
-    for (
-        run_id,
-        RunData {
-            label,
-            command,
-            start_datetime,
-            state,
-        },
-    ) in runs.into_iter()
-    {
-        print!("{} ", &run_id[..8]);
-        match state {
-            RunDataState::Done { exit_code: 0, .. } => {
-                print!("{}", "[done] ".green().bold())
-            }
-            RunDataState::Done { exit_code: -1, .. } => {
-                print!("{}", "[killed] ".yellow().bold())
+    // To tell how long runs are expected to take, from previous runs of the same commands
+    let history = all_runs
+        .iter()
+        .filter_map(|r| r.get_data().ok())
+        .collect::<Vec<_>>();
+
+    let listed_runs = match options.tree {
+        true => group(listed_runs),
+        false => listed_runs
+            .into_iter()
+            .map(|(depth, run)| Entry::Run(depth, Box::new(run)))
+            .collect(),
+    };
+    let ids = listed_runs
+        .iter()
+        .filter_map(|entry| match entry {
+            Entry::Run(_, listed) => Some(listed.0.id.clone()),
+            Entry::Group { .. } => None,
+        })
+        .collect::<Vec<_>>();
+    let index_width = ids.len().to_string().len() + 1;
+    runs.save_list_indices(ids)?;
+
+    let current_user = events::current_user();
+    let mut index = 0;
+    for entry in listed_runs {
+        match entry {
+            Entry::Group {
+                level,
+                name,
+                states,
+            } => print_group(level, &name, &states),
+            Entry::Run(depth, listed) => {
+                let (run, data, size) = *listed;
+                index += 1;
+                let listing = Listing {
+                    index,
+                    index_width,
+                    level: if options.tree { 2 } else { 0 },
+                    history: &history,
+                    current_user: &current_user,
+                };
+                print_run(&listing, depth, &run, data, size);
             }
-            RunDataState::Done { exit_code: -2, .. } => {
-                print!("{}", "[crashed] ".magenta().bold())
+        }
+    }
+
+    Ok(())
+}
+
+/// What is needed to list any run.
+struct Listing<'a> {
+    /// The run's index in the listing (for `%N`).
+    index: usize,
+    /// How wide indices are printed.
+    index_width: usize,
+    /// How many groups the run is in (see `--tree`), to indent it by.
+    level: usize,
+    /// All runs, to tell how long runs are expected to take from previous runs of the same
+    /// commands.
+    history: &'a [RunData],
+    current_user: &'a str,
+}
+
+/// Print a run on two lines, indented by `depth`.
+fn print_run(listing: &Listing, depth: usize, run: &Run, data: RunData, size: u64) {
+    let Listing {
+        index,
+        index_width,
+        level,
+        history,
+        current_user,
+    } = *listing;
+    let theme = theme::get();
+    let expected_duration = data.get_expected_duration(history);
+    let is_overdue = data.is_overdue(history);
+    let is_stalled = run.is_stalled(&data);
+    let RunData {
+        label,
+        name,
+        command,
+        start_datetime,
+        state,
+        owner,
+        ..
+    } = data;
+
+    let indent = match depth {
+        0 => String::new(),
+        depth => format!("{}└ ", "  ".repeat(depth - 1)),
+    };
+    let margin = "  ".repeat(level);
+    print!(
+        "{}{} {} {}",
+        margin,
+        format!("{:>index_width$}", format!("%{index}")).dimmed(),
+        &run.id[..8],
+        indent.dimmed(),
+    );
+    match state {
+        RunDataState::Done {
+            exit_code: 0,
+            failed_output: Some(_),
+            ..
+        } => {
+            print!("{}", paint("[failed:output] ", theme.failure).bold())
+        }
+        RunDataState::Done { exit_code: 0, .. } => {
+            print!("{}", paint("[done] ", theme.success).bold())
+        }
+        RunDataState::Done { exit_code: -1, .. } => {
+            print!("{}", paint("[killed] ", theme.killed).bold())
+        }
+        RunDataState::Done { exit_code: -2, .. } => {
+            print!("{}", paint("[crashed] ", theme.crashed).bold())
+        }
+        RunDataState::Done { exit_code: -3, .. } => {
+            print!("{}", paint("[finished] ", theme.unknown).bold())
+        }
+        RunDataState::Done { exit_code: -4, .. } => {
+            print!("{}", paint("[interrupted] ", theme.killed).bold())
+        }
+        RunDataState::Done { exit_code, .. } => {
+            print!(
+                "{}",
+                paint(&format!("[failed:{exit_code}] "), theme.failure).bold()
+            )
+        }
+        RunDataState::Running { .. } => {
+            print!("{}", "[running] ".bold())
+        }
+        RunDataState::Queued { .. } => {
+            print!("{}", "[queued] ".dimmed().bold())
+        }
+    }
+    if is_stalled {
+        print!("{}", paint("[stalled] ", theme.warning).bold());
+    }
+    if is_overdue {
+        print!("{}", paint("[overdue] ", theme.warning).bold());
+    }
+    if let Some(label) = label {
+        print!("{} ", paint(&format!("({label})"), theme.label));
+    }
+    println!("{}", shell_words::join(command).bold(),);
+    print!(
+        "{}{:index_width$}          {:indent_width$}",
+        margin,
+        "",
+        "",
+        indent_width = indent.chars().count()
+    );
+    if let Some(name) = name {
+        print!("{} {}, ", "Name".dimmed(), name);
+    }
+    // Only worth mentioning in stores shared with other users
+    if let Some(owner) = owner.filter(|owner| *owner != current_user) {
+        print!("{} {}, ", "User".dimmed(), owner);
+    }
+    match state {
+        RunDataState::Done { end_datetime, .. } => {
+            println!(
+                "{} {}, {} {}, {} {}",
+                "Started".dimmed(),
+                format_datetime(start_datetime),
+                "Finished".dimmed(),
+                format_datetime(end_datetime),
+                "Size".dimmed(),
+                format_size(size),
+            )
+        }
+        RunDataState::Running { .. } => {
+            print!(
+                "{} {}, {} {}",
+                "Started".dimmed(),
+                format_datetime(start_datetime),
+                "Size".dimmed(),
+                format_size(size),
+            );
+            match expected_duration.and_then(|d| chrono::Duration::from_std(d).ok()) {
+                Some(expected_duration) => println!(
+                    ", {} {}",
+                    "Expected".dimmed(),
+                    format_duration(expected_duration)
+                ),
+                None => println!(),
             }
-            RunDataState::Done { exit_code, .. } => {
-                print!("{}", format!("[failed:{exit_code}] ").red().bold())
+        }
+        RunDataState::Queued { lock, .. } => {
+            println!(
+                "{} {}, {} '{}'",
+                "Queued".dimmed(),
+                format_datetime(start_datetime),
+                "Waiting for".dimmed(),
+                lock,
+            );
+        }
+    }
+}
+
+/// A run to list, along with its data and disk usage.
+type Listed = (Run, RunData, u64);
+
+/// A line of the listing: either a run (along with how deeply nested it is, see [`nest`]), or,
+/// with `--tree`, the heading of a group of runs.
+enum Entry {
+    Run(usize, Box<Listed>),
+    Group {
+        /// 0 for a project, 1 for a label within it.
+        level: usize,
+        name: String,
+        /// How many runs of the group are in each state (e.g. `running` or `failed`).
+        states: Vec<(String, usize)>,
+    },
+}
+
+/// The state a run is counted as in its group's summary.
+fn group_state(state: &RunDataState) -> String {
+    match state {
+        state if state.is_success() => "done".to_string(),
+        state if state.is_failure() => "failed".to_string(),
+        state => state.status(),
+    }
+}
+
+/// Group `runs` (as nested by [`nest`]) by project, and then by label, keeping runs started from
+/// within other runs (e.g. a pipeline's steps) with the run they were started from. Groups are in
+/// the order their first run is in.
+fn group(runs: Vec<(usize, Listed)>) -> Vec<Entry> {
+    // Each top-level run along with the runs nested under it
+    let mut families: Vec<Vec<(usize, Listed)>> = Vec::new();
+    for entry in runs {
+        match families.last_mut() {
+            Some(family) if entry.0 > 0 => family.push(entry),
+            _ => families.push(vec![entry]),
+        }
+    }
+
+    let mut projects: Vec<(String, Vec<(String, Vec<_>)>)> = Vec::new();
+    for family in families {
+        let data = &family[0].1 .1;
+        let project = data
+            .working_directory
+            .as_deref()
+            .map(|directory| {
+                let project = project_directory(directory);
+                project
+                    .file_name()
+                    .unwrap_or(project.as_os_str())
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .unwrap_or_else(|| "(unknown project)".to_string());
+        let label = data
+            .label
+            .clone()
+            .unwrap_or_else(|| "(no label)".to_string());
+        let labels = match projects.iter().position(|(name, _)| *name == project) {
+            Some(i) => &mut projects[i].1,
+            None => {
+                projects.push((project, Vec::new()));
+                &mut projects.last_mut().unwrap().1
             }
-            RunDataState::Running { .. } => {
-                print!("{}", "[running] ".bold())
+        };
+        match labels.iter().position(|(name, _)| *name == label) {
+            Some(i) => labels[i].1.extend(family),
+            None => labels.push((label, family)),
+        }
+    }
+
+    let count_states = |runs: &mut dyn Iterator<Item = &RunData>| {
+        let mut states: Vec<(String, usize)> = Vec::new();
+        for data in runs {
+            let state = group_state(&data.state);
+            match states.iter_mut().find(|(name, _)| *name == state) {
+                Some((_, count)) => *count += 1,
+                None => states.push((state, 1)),
             }
         }
-        println!("{}", shell_words::join(command).bold(),);
-        print!("         ");
-        match state {
-            RunDataState::Done { end_datetime, .. } => {
-                println!(
-                    "{} {}, {} {}",
-                    "Started".dimmed(),
-                    format_datetime(start_datetime),
-                    "Finished".dimmed(),
-                    format_datetime(end_datetime),
-                )
+        states
+    };
+    let mut entries = Vec::new();
+    for (project, labels) in projects {
+        entries.push(Entry::Group {
+            level: 0,
+            name: project,
+            states: count_states(
+                &mut labels
+                    .iter()
+                    .flat_map(|(_, runs)| runs.iter().map(|(_, (_, data, _))| data)),
+            ),
+        });
+        for (label, runs) in labels {
+            entries.push(Entry::Group {
+                level: 1,
+                name: label,
+                states: count_states(&mut runs.iter().map(|(_, (_, data, _))| data)),
+            });
+            entries.extend(
+                runs.into_iter()
+                    .map(|(depth, run)| Entry::Run(depth, Box::new(run))),
+            );
+        }
+    }
+    entries
+}
+
+/// Print the heading of a group of runs (see `--tree`), with how many of its runs are in each
+/// state.
+fn print_group(level: usize, name: &str, states: &[(String, usize)]) {
+    let theme = theme::get();
+    let total = states.iter().map(|(_, count)| count).sum::<usize>();
+    let states = states
+        .iter()
+        .map(|(state, count)| {
+            let summary = format!("{count} {state}");
+            match state.as_str() {
+                "failed" | "crashed" => paint(&summary, theme.failure).to_string(),
+                "done" => paint(&summary, theme.success).to_string(),
+                _ => summary,
             }
-            RunDataState::Running { .. } => {
-                println!("{} {}", "Started".dimmed(), format_datetime(start_datetime),);
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let name = match level {
+        0 => name.bold().to_string(),
+        _ => paint(name, theme.label).to_string(),
+    };
+    println!(
+        "{}{} {}",
+        "  ".repeat(level),
+        name,
+        format!(
+            "({} run{}: {})",
+            total,
+            if total == 1 { "" } else { "s" },
+            states
+        )
+        .dimmed()
+    );
+}
+
+/// Order `runs` so that runs started from within other runs come right after them, keeping the
+/// order of runs otherwise; each run is given along with how deeply nested it is. Runs whose
+/// parent isn't listed are shown as top-level runs.
+fn nest(runs: Vec<Listed>) -> Vec<(usize, Listed)> {
+    let ids = runs
+        .iter()
+        .map(|(run, _, _)| run.id.clone())
+        .collect::<HashSet<_>>();
+    let mut children = HashMap::<RunId, Vec<_>>::new();
+    let mut roots = Vec::new();
+    for entry in runs {
+        match &entry.1.parent {
+            Some(parent) if ids.contains(parent) => {
+                children.entry(parent.clone()).or_default().push(entry)
             }
+            _ => roots.push(entry),
         }
     }
 
-    Ok(())
+    let mut nested = Vec::new();
+    let mut stack = roots.into_iter().rev().map(|r| (0, r)).collect::<Vec<_>>();
+    while let Some((depth, entry)) = stack.pop() {
+        if let Some(children) = children.remove(&entry.0.id) {
+            stack.extend(children.into_iter().rev().map(|c| (depth + 1, c)));
+        }
+        nested.push((depth, entry));
+    }
+    // Runs can't really be started from within themselves, but don't lose any if they somehow were
+    nested.extend(children.into_values().flatten().map(|c| (0, c)));
+    nested
 }
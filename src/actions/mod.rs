@@ -1,6 +1,27 @@
+pub mod adopt;
+pub mod archive;
+pub mod attach;
+pub mod audit;
+pub mod bench;
+pub mod clone;
+pub mod compare;
+pub mod daemon;
+pub mod doctor;
+pub mod export;
+pub mod history;
 pub mod list;
 pub mod open;
+pub mod pick;
+pub mod pipeline;
+pub mod plot;
+pub mod prune;
 pub mod remove;
+pub mod restore;
 pub mod send_signal;
-pub mod start;
 pub mod show_info;
+pub mod show_path;
+pub mod start;
+pub mod status;
+pub mod stream_events;
+pub mod time;
+pub mod top;
@@ -1,67 +1,336 @@
-use std::io::Write;
+use std::{
+    cell::{Cell, RefCell},
+    io::Write,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use nix::sys::signal::{self, SigHandler, Signal};
+use regex::Regex;
 use termion::{event::Key, input::TermRead, raw::IntoRawMode};
 
-use crate::runs::Run;
-use crate::utils::tail;
+use crate::encryption;
+use crate::runs::{Run, RunDataState};
+use crate::utils::{
+    binary, clipboard, format_size, jsonl::JsonlRenderer, parse_duration, parse_size, tail,
+    viewport::Viewport,
+};
 
-pub fn open_run(run: &Run) -> Result<()> {
-    let output_file_path = run.get_output_file();
+/// What lines are highlighted as errors by default.
+const DEFAULT_ERROR_REGEX: &str = r"(?i)\b(error|fatal|panic(ked)?|exception|traceback|failed)\b";
 
+/// Options controlling how a run is viewed.
+#[derive(Debug, Clone, clap::Args)]
+pub struct ViewOptions {
+    /// How much of the existing output to show initially
+    #[clap(long, value_name = "SIZE", value_parser = parse_size, default_value = "1M")]
+    pub tail: u64,
+
+    /// How often to check for new output, for filesystems without change notifications
+    #[clap(long, value_name = "DURATION", value_parser = parse_duration, default_value = "1s")]
+    pub poll_interval: Duration,
+
+    /// Show the output as-is, even if it looks like binary data
+    #[clap(long)]
+    pub raw: bool,
+
+    /// Highlight lines matching this regex as errors; press n and N to jump between them
+    #[clap(long, value_name = "REGEX", default_value = DEFAULT_ERROR_REGEX)]
+    pub error_regex: Regex,
+
+    /// Render lines of structured (JSON) logs readably: time, level and message first, then the
+    /// other fields as key=value
+    #[clap(long)]
+    pub jsonl: bool,
+}
+
+impl Default for ViewOptions {
+    fn default() -> Self {
+        Self {
+            tail: 1024 * 1024,
+            poll_interval: Duration::from_secs(1),
+            raw: false,
+            error_regex: Regex::new(DEFAULT_ERROR_REGEX).unwrap(),
+            jsonl: false,
+        }
+    }
+}
+
+pub fn open_run(run: &Run, options: &ViewOptions, key: Option<&encryption::Key>) -> Result<()> {
     let mut screen = termion::screen::AlternateScreen::from(std::io::stdout()).into_raw_mode()?;
     let mut input = termion::async_stdin().keys();
 
-    write!(
-        screen,
-        "{}{}",
-        termion::clear::All,
-        termion::cursor::Goto(1, 2)
-    )?;
+    write!(screen, "{}", termion::clear::All)?;
 
-    tail::follow_tail(
-        &output_file_path,
-        |new_text: &str| -> Result<()> {
-            let new_text = new_text.replace('\n', "\r\n");
-            write!(screen, "{}", new_text)?;
-
-            // FIXME what if the output is already styled?
-            write!(
-                screen,
-                "{}{}{}{}",
-                termion::cursor::Save,
-                termion::cursor::Goto(1, 1),
-                termion::clear::CurrentLine,
-                termion::style::Faint,
-            )?;
-            write!(
-                screen,
-                "You are currently viewing a run. Press Ctrl+C to exit."
-            )?;
-            write!(
-                screen,
-                "{}",
-                termion::cursor::Goto(termion::terminal_size()?.0 - (run.id.len() as u16) + 1, 1),
-            )?;
-            write!(screen, "{}", run.id)?;
-            write!(
-                screen,
-                "{}{}",
-                termion::style::NoFaint,
-                termion::cursor::Restore
-            )?;
-
-            screen.flush()?;
+    // Both following the output and handling input need these
+    let viewport = RefCell::new(Viewport::new(Some(options.error_regex.clone())));
+    let status = RefCell::new(Status::default());
+    let needs_redraw = Cell::new(true);
 
+    let mut jsonl = options.jsonl.then(JsonlRenderer::default);
+    let mut last_size = None;
+    let mut last_state_check: Option<Instant> = None;
+
+    follow_output(
+        run,
+        key,
+        options.tail,
+        options.poll_interval,
+        |new_text: &str| -> Result<()> {
+            let mut status = status.borrow_mut();
+            status.bytes_read += new_text.len() as u64;
+            let new_text = match &mut jsonl {
+                Some(renderer) => renderer.render(new_text),
+                None => new_text.to_string(),
+            };
+            if !options.raw && !status.is_binary && binary::looks_binary(&new_text) {
+                status.is_binary = true;
+            }
+            let new_text = if status.is_binary {
+                binary::escape_control(&new_text)
+            } else {
+                new_text
+            };
+            viewport.borrow_mut().push(&new_text);
+            needs_redraw.set(true);
             Ok(())
         },
         || {
             for key in &mut input {
-                if let Key::Ctrl('c') = key? {
-                    return Ok(true);
+                let mut viewport = viewport.borrow_mut();
+                status.borrow_mut().notice = None;
+                match key? {
+                    Key::Ctrl('c') | Key::Char('q') => return Ok(true),
+                    Key::Up | Key::Char('k') if viewport.is_selecting() => viewport.move_cursor(-1),
+                    Key::Down | Key::Char('j') if viewport.is_selecting() => {
+                        viewport.move_cursor(1)
+                    }
+                    Key::Esc | Key::Char('v') if viewport.is_selecting() => {
+                        viewport.cancel_selection()
+                    }
+                    Key::Char('v') => viewport.start_selection(),
+                    Key::Char('y') => {
+                        if let Some((line_count, text)) = viewport.take_selection() {
+                            let how = clipboard::copy(&text, &mut screen)?;
+                            status.borrow_mut().notice =
+                                Some(format!("Copied {} line(s) with {}", line_count, how));
+                        }
+                    }
+                    Key::Char('w') => viewport.toggle_wrap(),
+                    Key::Left | Key::Char('h') => viewport.scroll_horizontally(-1),
+                    Key::Right | Key::Char('l') => viewport.scroll_horizontally(1),
+                    Key::Up | Key::Char('k') => viewport.scroll(-1),
+                    Key::Down | Key::Char('j') => viewport.scroll(1),
+                    Key::PageUp | Key::Char('b') => viewport.scroll_pages(-1),
+                    Key::PageDown | Key::Char(' ') => viewport.scroll_pages(1),
+                    Key::Home | Key::Char('g') => viewport.scroll_to_start(),
+                    Key::End | Key::Char('G') => viewport.follow(),
+                    Key::Char('n') => _ = viewport.jump_to_error(true),
+                    Key::Char('N') => _ = viewport.jump_to_error(false),
+                    _ => continue,
+                }
+                needs_redraw.set(true);
+            }
+
+            if last_state_check.is_none_or(|t| t.elapsed() >= options.poll_interval) {
+                let run_state = describe_state(&run.get_data()?.state);
+                let mut status = status.borrow_mut();
+                if status.run_state != run_state {
+                    status.run_state = run_state;
+                    needs_redraw.set(true);
                 }
+                last_state_check = Some(Instant::now());
+            }
+
+            let size = termion::terminal_size()?;
+            if needs_redraw.replace(false) || last_size != Some(size) {
+                draw(
+                    &mut screen,
+                    run,
+                    &mut viewport.borrow_mut(),
+                    &status.borrow(),
+                    size,
+                )?;
+                last_size = Some(size);
             }
             Ok(false)
         },
     )
 }
+
+/// What the viewer knows about the run, besides its output.
+#[derive(Debug, Default)]
+struct Status {
+    /// Whether the output looks like binary data.
+    is_binary: bool,
+    /// How much output was read so far.
+    bytes_read: u64,
+    /// The state of the run, as shown in the status bar.
+    run_state: String,
+    /// Something to tell the user about in the status bar, until the next key is pressed.
+    notice: Option<String>,
+}
+
+/// Describe the state of a run for the status bar, e.g. `running` or `done, exit code 0`.
+fn describe_state(state: &RunDataState) -> String {
+    match state {
+        RunDataState::Done { exit_code, .. } if *exit_code >= 0 => format!(
+            "{}, exit code {}",
+            if state.is_success() { "done" } else { "failed" },
+            exit_code
+        ),
+        state => state.status(),
+    }
+}
+
+/// Draw the header (on the first row), the output (on the rows in between) and the status bar (on
+/// the last row).
+fn draw(
+    screen: &mut impl Write,
+    run: &Run,
+    viewport: &mut Viewport,
+    status: &Status,
+    (width, height): (u16, u16),
+) -> Result<()> {
+    viewport.render(screen, 2, (width, height.saturating_sub(2)))?;
+
+    let message = match (status.is_binary, viewport.is_wrapping()) {
+        _ if viewport.is_selecting() => {
+            "Selecting lines. ↑/↓: extend the selection, y: copy it to the clipboard, Esc: cancel"
+        }
+        (true, _) => "Binary output; showing it escaped (use --raw to disable). Press q to exit.",
+        (false, true) => {
+            "Viewing a run. q: exit, ↑/↓: scroll, n/N: next/previous error, v: select, w: unwrap"
+        }
+        (false, false) => {
+            "Viewing a run. q: exit, ↑/↓/←/→: scroll, n/N: next/previous error, v: select, w: wrap"
+        }
+    };
+    let room = usize::from(width).saturating_sub(run.id.len() + 1);
+    write!(
+        screen,
+        "{}{}{}{}{}{}{}",
+        termion::cursor::Goto(1, 1),
+        termion::clear::CurrentLine,
+        termion::style::Faint,
+        message.chars().take(room).collect::<String>(),
+        termion::cursor::Goto((width + 1).saturating_sub(run.id.len() as u16).max(1), 1),
+        run.id,
+        termion::style::Reset,
+    )?;
+
+    let (line, line_count) = viewport.position();
+    let output_size = run
+        .get_output_files()?
+        .iter()
+        .filter_map(|file| file.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    let status_bar = format!(
+        " line {}/{} | read {} of {} | {} | {}",
+        line.min(line_count),
+        line_count,
+        format_size(status.bytes_read),
+        format_size(output_size),
+        match viewport.is_following() {
+            true => "following",
+            false => "not following (G to follow)",
+        },
+        status.run_state,
+    );
+    let status_bar = match &status.notice {
+        Some(notice) => format!(" {} |{}", notice, status_bar),
+        None => status_bar,
+    };
+    write!(
+        screen,
+        "{}{}{}{:<width$}{}",
+        termion::cursor::Goto(1, height.max(1)),
+        termion::clear::CurrentLine,
+        termion::style::Invert,
+        status_bar.chars().take(width.into()).collect::<String>(),
+        termion::style::Reset,
+        width = width.into(),
+    )?;
+
+    screen.flush()?;
+    Ok(())
+}
+
+/// Follow the run's output (see `tail::follow_tail`), decrypting it with `key` if it is encrypted;
+/// encrypted output is always followed from the start.
+fn follow_output<F, G>(
+    run: &Run,
+    key: Option<&encryption::Key>,
+    initial_bytes: u64,
+    poll_interval: Duration,
+    on_new_text: F,
+    on_iter: G,
+) -> Result<()>
+where
+    F: FnMut(&str) -> Result<()>,
+    G: FnMut() -> Result<bool>,
+{
+    match key {
+        Some(key) => encryption::follow(
+            run.get_encrypted_output_file(),
+            key,
+            poll_interval,
+            on_new_text,
+            on_iter,
+            || {
+                run.get_data()
+                    .is_ok_and(|data| matches!(data.state, RunDataState::Done { .. }))
+            },
+        ),
+        None => tail::follow_tail(
+            &run.run_directory,
+            || run.get_output_files(),
+            initial_bytes,
+            poll_interval,
+            on_new_text,
+            on_iter,
+        ),
+    }
+}
+
+/// Whether Ctrl+C was pressed while streaming a run's output.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: i32) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Wait for a run started with `--fg` to finish (its monitor copies its output to our standard
+/// output meanwhile), passing Ctrl+C on to it; returns what rum should exit with.
+pub fn wait_for_run(run: &Run) -> Result<i32> {
+    // Ctrl+C is meant for the run, which lives in a session of its own
+    // SAFETY: the handler only sets an atomic flag
+    unsafe { signal::signal(Signal::SIGINT, SigHandler::Handler(on_interrupt)) }?;
+
+    loop {
+        let state = run.get_data()?.state;
+        if INTERRUPTED.swap(false, Ordering::SeqCst) {
+            if let RunDataState::Running { pgid, .. } = state {
+                signal::killpg(pgid, Signal::SIGINT)
+                    .with_context(|| "Couldn't send signal to run's process")?;
+            }
+        }
+        // The output is all copied before the run is marked as done
+        if let RunDataState::Done {
+            exit_code,
+            failed_output,
+            ..
+        } = state
+        {
+            return Ok(match (exit_code, failed_output) {
+                (0, Some(_)) => 1,
+                (code, _) if code >= 0 => code,
+                // Killed, crashed or unknown
+                _ => 1,
+            });
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,70 @@
+use std::io::IsTerminal;
+
+use anyhow::{Error, Result};
+
+use crate::runs::{AmbiguousQuery, Run, RunData, Runs};
+
+/// Interactively pick a run, fuzzy-searching over IDs, labels and commands.
+pub fn pick_run(runs: &Runs) -> Result<Run> {
+    if !can_pick() {
+        return Err(Error::msg("No run was given"));
+    }
+
+    let mut candidates = runs
+        .get_all()?
+        .into_iter()
+        .filter_map(|run| run.get_data().ok().map(|data| (run, data)))
+        .collect::<Vec<_>>();
+    candidates.sort_by_key(|(_, data)| std::cmp::Reverse(data.start_datetime));
+    if candidates.is_empty() {
+        return Err(Error::msg("There are no runs"));
+    }
+
+    pick_among(candidates, "Pick a run")
+}
+
+/// Get a run from a query (see [`Runs::get_run`]); if it matches several runs, let the user pick
+/// one of them interactively (when possible).
+pub fn get_run_or_pick_among_matches(runs: &Runs, query: &str) -> Result<Run> {
+    let error = match runs.get_run(&query.to_string()) {
+        Ok(run) => return Ok(run),
+        Err(error) => error,
+    };
+    match error.downcast::<AmbiguousQuery>() {
+        Ok(ambiguous) if can_pick() => {
+            let candidates = ambiguous
+                .candidates
+                .into_iter()
+                .filter_map(|run| run.get_data().ok().map(|data| (run, data)))
+                .collect();
+            pick_among(
+                candidates,
+                &format!("Multiple runs match '{}'; pick one", ambiguous.query),
+            )
+        }
+        Ok(ambiguous) => Err(ambiguous.into()),
+        Err(error) => Err(error),
+    }
+}
+
+fn can_pick() -> bool {
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+fn pick_among(mut candidates: Vec<(Run, RunData)>, prompt: &str) -> Result<Run> {
+    let items = candidates
+        .iter()
+        .map(|(run, data)| run.summarize(data))
+        .collect::<Vec<_>>();
+
+    let selection = dialoguer::FuzzySelect::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt(prompt)
+        .items(&items)
+        .default(0)
+        .interact_opt()?;
+
+    match selection {
+        Some(i) => Ok(candidates.swap_remove(i).0),
+        None => Err(Error::msg("No run was picked")),
+    }
+}
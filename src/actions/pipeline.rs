@@ -0,0 +1,208 @@
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::Path,
+    sync::atomic::{AtomicI32, Ordering},
+    time::Duration,
+};
+
+use anyhow::{Context, Error, Result};
+use nix::sys::signal::{self, SigHandler, Signal};
+
+use crate::{
+    actions::{send_signal::send_signal, start::start_run},
+    config::Config,
+    pipeline::{Pipeline, Step},
+    runs::{Run, RunDataState, Runs, StartOptions},
+};
+
+/// How often to check on the steps which are running.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The signal the pipeline was asked to stop with, if any (0 otherwise).
+static STOP_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn on_stop_signal(signal: i32) {
+    STOP_SIGNAL.store(signal, Ordering::SeqCst);
+}
+
+enum StepState {
+    Pending,
+    Running(Run),
+    Succeeded,
+    /// The step's outputs were newer than its inputs, so it wasn't run at all.
+    UpToDate,
+    Failed,
+    Skipped,
+}
+
+/// Start a run which runs the steps of the pipeline in `file` (see [`run_steps`]), so that the
+/// pipeline as a whole is tracked like any other run, with its steps nested under it.
+pub fn start_pipeline(
+    runs: &Runs,
+    config: &Config,
+    file: &Path,
+    global: bool,
+    options: &StartOptions,
+) -> Result<Run> {
+    let file = file
+        .canonicalize()
+        .with_context(|| format!("Could not find {:?}", file))?;
+    // Better to find out about mistakes now than once the pipeline is running
+    Pipeline::load(&file)?;
+
+    let mut command = vec![
+        std::env::current_exe()?.to_string_lossy().into_owned(),
+        "-pipeline".to_string(),
+        "--run-steps".to_string(),
+    ];
+    if global {
+        command.push("--global".to_string());
+    }
+    command.push(file.to_string_lossy().into_owned());
+    start_run(runs, config, command, options)
+}
+
+/// Run the steps of the pipeline in `file` (from the directory it is in), each as a run of its
+/// own, starting each step once all of the steps it needs have succeeded. Steps which need a step
+/// which didn't succeed are skipped. Fails unless every step succeeded.
+///
+/// When interrupted or terminated, the signal is passed on to the steps which are running, and no
+/// more steps are started.
+pub fn run_steps(runs: &Runs, config: &Config, file: &Path) -> Result<()> {
+    let pipeline = Pipeline::load(file)?;
+    if let Some(directory) = file.parent() {
+        std::env::set_current_dir(directory)
+            .with_context(|| format!("Could not enter {:?}", directory))?;
+    }
+    // SAFETY: the handler only sets an atomic integer
+    unsafe {
+        signal::signal(Signal::SIGINT, SigHandler::Handler(on_stop_signal))?;
+        signal::signal(Signal::SIGTERM, SigHandler::Handler(on_stop_signal))?;
+    }
+
+    let order = pipeline.order()?;
+    let mut states = order
+        .iter()
+        .map(|name| (*name, StepState::Pending))
+        .collect::<BTreeMap<_, _>>();
+    let mut stopping = false;
+    loop {
+        if let Ok(signal) = Signal::try_from(STOP_SIGNAL.swap(0, Ordering::SeqCst)) {
+            println!("Got {signal}; passing it on to the running steps, and starting no more.");
+            for state in states.values() {
+                if let StepState::Running(run) = state {
+                    let _ = send_signal(run, signal, false);
+                }
+            }
+            stopping = true;
+        }
+
+        for name in &order {
+            let step = &pipeline.steps[*name];
+            let new_state = match &states[name] {
+                StepState::Pending => {
+                    let unsuccessful = step.needs.iter().find(|need| {
+                        matches!(
+                            states[need.as_str()],
+                            StepState::Failed | StepState::Skipped
+                        )
+                    });
+                    let is_ready = step.needs.iter().all(|need| {
+                        matches!(
+                            states[need.as_str()],
+                            StepState::Succeeded | StepState::UpToDate
+                        )
+                    });
+                    if let Some(unsuccessful) = unsuccessful {
+                        println!(
+                            "Skipping step '{name}', as step '{unsuccessful}' didn't succeed."
+                        );
+                        StepState::Skipped
+                    } else if is_ready && stopping {
+                        println!("Skipping step '{name}', as the pipeline is stopping.");
+                        StepState::Skipped
+                    } else if is_ready && is_up_to_date(name, step) {
+                        println!("Skipping step '{name}', as it is up to date.");
+                        StepState::UpToDate
+                    } else if is_ready {
+                        start_step(runs, config, name, step.command.to_command())
+                    } else {
+                        continue;
+                    }
+                }
+                StepState::Running(run) => {
+                    let data = run.get_data()?;
+                    if run.is_orphaned(&data) {
+                        // Once readopted, the run is marked as done as soon as it finishes
+                        let _ = run.readopt();
+                    }
+                    match data.state {
+                        RunDataState::Done { .. } if data.state.is_success() => {
+                            println!("Step '{name}' succeeded.");
+                            StepState::Succeeded
+                        }
+                        RunDataState::Done { .. } => {
+                            println!("Step '{name}' failed ({}).", data.state.status());
+                            StepState::Failed
+                        }
+                        _ => continue,
+                    }
+                }
+                _ => continue,
+            };
+            states.insert(name, new_state);
+        }
+
+        if !states
+            .values()
+            .any(|s| matches!(s, StepState::Pending | StepState::Running(_)))
+        {
+            break;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+
+    let count = |f: fn(&StepState) -> bool| states.values().filter(|s| f(s)).count();
+    let failed = count(|s| matches!(s, StepState::Failed));
+    let skipped = count(|s| matches!(s, StepState::Skipped));
+    match (failed, skipped) {
+        (0, 0) => {
+            match count(|s| matches!(s, StepState::UpToDate)) {
+                0 => println!("All {} steps succeeded.", states.len()),
+                up_to_date => println!(
+                    "All {} steps succeeded ({up_to_date} of them were up to date).",
+                    states.len()
+                ),
+            }
+            Ok(())
+        }
+        (failed, skipped) => Err(Error::msg(format!(
+            "Not every step succeeded: {failed} failed and {skipped} skipped, of {}",
+            states.len()
+        ))),
+    }
+}
+
+fn is_up_to_date(name: &str, step: &Step) -> bool {
+    step.is_up_to_date().unwrap_or_else(|e| {
+        println!("Could not tell whether step '{name}' is up to date, so running it: {e:#}");
+        false
+    })
+}
+
+fn start_step(runs: &Runs, config: &Config, name: &str, command: Vec<String>) -> StepState {
+    print!("Step '{name}': ");
+    let _ = std::io::stdout().flush();
+    let options = StartOptions {
+        label: Some(name.to_string()),
+        ..Default::default()
+    };
+    match start_run(runs, config, command, &options) {
+        Ok(run) => StepState::Running(run),
+        Err(e) => {
+            println!("could not start it: {e:#}");
+            StepState::Failed
+        }
+    }
+}
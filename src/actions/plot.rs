@@ -0,0 +1,92 @@
+use anyhow::{Error, Result};
+use colored::Colorize;
+
+use crate::{
+    metrics::read_samples,
+    runs::Run,
+    utils::{format_duration, format_size},
+};
+
+/// How many lines each chart takes up.
+const CHART_HEIGHT: usize = 8;
+
+/// Characters for bars filled to 1/8, 2/8, ..., 7/8 of a cell.
+const PARTIAL_BLOCKS: [char; 7] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇'];
+
+/// Plot the resources used by a run over time.
+pub fn plot_run(run: &Run) -> Result<()> {
+    let samples = read_samples(&run.get_metrics_file())?;
+    if samples.len() < 2 {
+        return Err(Error::msg(
+            "Not enough resource usage was recorded yet to plot it",
+        ));
+    }
+
+    let cpu = samples
+        .windows(2)
+        .map(|pair| {
+            let elapsed = (pair[1].datetime - pair[0].datetime).num_milliseconds() as f64 / 1000.0;
+            // Processes exiting take their CPU time with them, which can make it go down
+            ((pair[1].cpu_time - pair[0].cpu_time) / elapsed * 100.0).max(0.0)
+        })
+        .collect::<Vec<_>>();
+    let rss = samples.iter().map(|s| s.rss as f64).collect::<Vec<_>>();
+
+    let width = termion::terminal_size()
+        .map(|(width, _)| width as usize)
+        .unwrap_or(80)
+        .saturating_sub(2)
+        .max(10);
+    let max_cpu = cpu.iter().cloned().fold(0.0, f64::max);
+    let max_rss = rss.iter().cloned().fold(0.0, f64::max);
+
+    println!(
+        "{} {}",
+        "CPU".bold(),
+        format!("(max {:.1}%)", max_cpu).dimmed()
+    );
+    print_chart(&cpu, max_cpu, width);
+    println!(
+        "{} {}",
+        "Memory".bold(),
+        format!("(max {})", format_size(max_rss as u64)).dimmed()
+    );
+    print_chart(&rss, max_rss, width);
+
+    let duration = samples.last().unwrap().datetime - samples[0].datetime;
+    let end = format_duration(duration);
+    println!(" 0s{:>width$}", end, width = width.saturating_sub(2));
+    Ok(())
+}
+
+/// Print `values` as a bar chart `width` characters wide, scaled so that `max` fills it up.
+fn print_chart(values: &[f64], max: f64, width: usize) {
+    let columns = (0..width)
+        .map(|column| {
+            // Each column shows the largest value in its share of the samples
+            let start = column * values.len() / width;
+            let end = ((column + 1) * values.len() / width).max(start + 1);
+            values[start..end].iter().cloned().fold(0.0, f64::max)
+        })
+        .collect::<Vec<_>>();
+
+    for row in (0..CHART_HEIGHT).rev() {
+        let line = columns
+            .iter()
+            .map(|value| {
+                let eighths = if max > 0.0 {
+                    (value / max * (CHART_HEIGHT * 8) as f64).round() as usize
+                } else {
+                    0
+                };
+                match eighths.saturating_sub(row * 8) {
+                    0 => ' ',
+                    filled if filled >= 8 => '█',
+                    filled => PARTIAL_BLOCKS[filled - 1],
+                }
+            })
+            .collect::<String>();
+        println!("{}{}", "│".dimmed(), line.cyan());
+    }
+    println!("{}", format!("└{}", "─".repeat(width)).dimmed());
+}
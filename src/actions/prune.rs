@@ -0,0 +1,150 @@
+use anyhow::{Error, Result};
+use chrono::{DateTime, Utc};
+
+use crate::{
+    actions::remove::print_would_remove,
+    config::Retention,
+    runs::{Run, RunDataState, Runs},
+    utils::format_duration,
+};
+
+/// How often the retention policy is applied automatically.
+const AUTOMATIC_PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Get the runs which should be removed according to `retention`. Unless `force`, only the
+/// current user's runs are considered (so `max_runs` is how many runs each user keeps), as one
+/// user's retention policy shouldn't remove another's runs.
+fn runs_to_prune(runs: &Runs, retention: &Retention, force: bool) -> Result<Vec<Run>> {
+    let now = Utc::now();
+    let all_runs = runs
+        .get_all()?
+        .into_iter()
+        .filter_map(|run| {
+            let data = run.get_data().ok()?;
+            run.check_owner(&data, "remove", force).ok()?;
+            Some((run, data))
+        })
+        .collect::<Vec<_>>();
+    let total = all_runs.len();
+
+    let mut finished = all_runs
+        .into_iter()
+        .filter_map(|(run, data)| match data.state {
+            RunDataState::Done { end_datetime, .. } => {
+                Some((run, end_datetime, data.state.is_success()))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    finished.sort_by_key(|(_, end_datetime, _)| *end_datetime);
+
+    let (expired, kept): (Vec<_>, Vec<_>) =
+        finished
+            .into_iter()
+            .partition(|(_, end_datetime, succeeded)| {
+                let keep_for = match succeeded {
+                    true => retention.succeeded,
+                    false => retention.failed,
+                };
+                let age = (now - *end_datetime).to_std().unwrap_or_default();
+                keep_for.is_some_and(|keep_for| age > keep_for)
+            });
+
+    let mut to_prune = expired
+        .into_iter()
+        .map(|(run, _, _)| run)
+        .collect::<Vec<_>>();
+    if let Some(max_runs) = retention.max_runs {
+        let excess = (total - to_prune.len()).saturating_sub(max_runs);
+        to_prune.extend(kept.into_iter().take(excess).map(|(run, _, _)| run));
+    }
+    Ok(to_prune)
+}
+
+/// Get the runs which have been in the trash for longer than `retention` allows (only the
+/// current user's, unless `force`).
+fn trash_to_empty(
+    runs: &Runs,
+    retention: &Retention,
+    force: bool,
+) -> Result<Vec<(Run, DateTime<Utc>)>> {
+    let now = Utc::now();
+    Ok(runs
+        .get_trashed()?
+        .into_iter()
+        .filter(|(run, trashed_datetime)| {
+            (now - *trashed_datetime).to_std().unwrap_or_default() > retention.trash()
+                && run
+                    .get_data()
+                    .is_ok_and(|data| run.check_owner(&data, "remove", force).is_ok())
+        })
+        .collect())
+}
+
+/// Remove the runs which should be removed according to `retention`, and empty the trash of
+/// the runs which have been in it for too long (or, with `dry_run`, just tell which ones they
+/// are). Other users' runs are left alone, unless `force`.
+pub fn prune_runs(runs: &Runs, retention: &Retention, dry_run: bool, force: bool) -> Result<()> {
+    if dry_run {
+        for run in runs_to_prune(runs, retention, force)? {
+            print_would_remove(&run)?;
+        }
+        for (run, trashed_datetime) in trash_to_empty(runs, retention, force)? {
+            println!(
+                "Would delete run '{}' from the trash (removed {} ago).",
+                run.id,
+                format_duration(Utc::now() - trashed_datetime),
+            );
+        }
+        return Ok(());
+    }
+
+    // A run which can't be deleted (e.g. for lack of permissions) shouldn't keep the others
+    let mut failed = 0;
+    for run in runs_to_prune(runs, retention, force)? {
+        let id = run.id.clone();
+        match runs.remove_run(run) {
+            Ok(()) => println!("Deleted run '{id}'."),
+            Err(e) => {
+                println!("Could not delete run '{id}': {e:#}");
+                failed += 1;
+            }
+        }
+    }
+    for (run, _) in trash_to_empty(runs, retention, force)? {
+        let id = run.id.clone();
+        match runs.remove_run(run) {
+            Ok(()) => println!("Deleted run '{id}' from the trash."),
+            Err(e) => {
+                println!("Could not delete run '{id}' from the trash: {e:#}");
+                failed += 1;
+            }
+        }
+    }
+    std::fs::File::create(runs.get_last_prune_file())?;
+    match failed {
+        0 => Ok(()),
+        failed => Err(Error::msg(format!("Could not delete {failed} runs"))),
+    }
+}
+
+/// Apply the retention policy, if it hasn't been applied in a while.
+pub fn prune_runs_automatically(runs: &Runs, retention: &Retention) -> Result<()> {
+    let last_prune_file = runs.get_last_prune_file();
+    let recently_pruned = std::fs::metadata(&last_prune_file)
+        .and_then(|m| m.modified())
+        .is_ok_and(|modified| modified.elapsed().unwrap_or_default() < AUTOMATIC_PRUNE_INTERVAL);
+    if recently_pruned {
+        return Ok(());
+    }
+
+    // Runs which can't be deleted are tried again next time, rather than every time
+    for run in runs_to_prune(runs, retention, false)? {
+        let _ = runs.remove_run(run);
+    }
+    for (run, _) in trash_to_empty(runs, retention, false)? {
+        let _ = runs.remove_run(run);
+    }
+    std::fs::File::create(last_prune_file)?;
+    Ok(())
+}
@@ -1,62 +1,244 @@
+use std::{
+    collections::HashSet,
+    io::IsTerminal,
+    time::{Duration, Instant},
+};
+
 use anyhow::{Error, Result};
+use chrono::Utc;
 use colored::Colorize;
 
 use crate::{
-    actions::show_info::show_run_info,
-    runs::{Run, RunData, RunDataState, Runs},
+    actions::{
+        pick::{get_run_or_pick_among_matches, pick_run},
+        send_signal::stop_run,
+        show_info::show_run_info,
+    },
+    events::Event,
+    filter::Filter,
+    runs::{Run, RunDataState, Runs},
+    theme::{self, paint},
+    utils::{format_duration, parse_duration},
 };
 
-pub fn remove_run(runs: &Runs, run: Run, ask_for_confirmation: bool) -> Result<()> {
-    match run.get_data()? {
-        RunData {
-            state: RunDataState::Done { .. },
-            ..
-        } => {
-            if ask_for_confirmation {
-                show_run_info(&run)?;
-
-                println!();
-
-                if dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
-                    .with_prompt("Are you sure you want to delete this run?")
-                    .interact()?
-                {
-                    let id = run.id.clone();
-                    runs.remove_run(run)?;
-                    println!("Deleted run '{id}'.");
-                }
-            } else {
-                let id = run.id.clone();
-                runs.remove_run(run)?;
-                println!("Deleted run '{id}'.");
-            }
-            Ok(())
+/// How long to wait for a stopped run's monitor to record it as done, before removing it anyway.
+const RECORD_GRACE: Duration = Duration::from_secs(5);
+
+/// Options controlling how runs are removed.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct RemoveOptions {
+    /// Don't ask for confirmation (e.g. in scripts)
+    #[clap(
+        long,
+        short = 'y',
+        alias = "no-confirm",
+        conflicts_with = "interactive"
+    )]
+    pub yes: bool,
+
+    /// Ask for confirmation for each run separately, rather than once for all of them
+    #[clap(long)]
+    pub interactive: bool,
+
+    /// Only show which runs would be removed, without removing anything
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Also remove runs which belong to other users
+    #[clap(long)]
+    pub force: bool,
+
+    /// Also remove runs which are still running (or queued), stopping them first
+    #[clap(long)]
+    pub kill: bool,
+
+    /// With --kill, how long to give runs to stop before escalating to the next signal
+    #[clap(long, value_name = "DURATION", value_parser = parse_duration, default_value = "5s", requires = "kill")]
+    pub grace: Duration,
+}
+
+/// Tell what removing `run` would do, instead of actually doing it.
+pub fn print_would_remove(run: &Run) -> Result<()> {
+    let data = run.get_data()?;
+    match data.state {
+        RunDataState::Done { end_datetime, .. } => println!(
+            "Would delete run '{}' ({}, ended {} ago): {}",
+            run.id,
+            data.state.status(),
+            format_duration(Utc::now() - end_datetime),
+            shell_words::join(&data.command),
+        ),
+        RunDataState::Running { .. } | RunDataState::Queued { .. } => println!(
+            "Would stop and delete run '{}' ({}): {}",
+            run.id,
+            data.state.status(),
+            shell_words::join(&data.command),
+        ),
+    }
+    Ok(())
+}
+
+/// Remove `run`. Runs which haven't finished yet are only removed if `kill` is given, in which
+/// case they are stopped first (see `stop_run`), giving them `grace` for each signal.
+pub fn remove_run(
+    runs: &Runs,
+    run: Run,
+    ask_for_confirmation: bool,
+    kill: Option<Duration>,
+) -> Result<()> {
+    let data = run.get_data()?;
+    let is_done = matches!(data.state, RunDataState::Done { .. });
+    if !is_done && kill.is_none() {
+        run.record_event(Event::RemoveRefused);
+        return Err(Error::msg(format!(
+            "Still {}: {} (pass --kill to stop and remove it anyway)",
+            data.state.status(),
+            run.id
+        )));
+    }
+
+    if ask_for_confirmation {
+        show_run_info(&run)?;
+
+        println!();
+
+        let prompt = match is_done {
+            true => "Are you sure you want to delete this run?",
+            false => "Are you sure you want to stop and delete this run?",
+        };
+        if !confirm(prompt)? {
+            return Ok(());
         }
-        RunData {
-            state: RunDataState::Running { .. },
-            ..
-        } => Err(Error::msg(format!("Still running: {}", run.id))),
     }
+
+    if let (false, Some(grace)) = (is_done, kill) {
+        stop_for_removal(&run, grace)?;
+    }
+    let id = run.id.clone();
+    runs.trash_run(run)?;
+    println!("Moved run '{id}' to the trash (see `rum -restore`).");
+    Ok(())
+}
+
+/// Stop `run`, and give whatever is monitoring it a moment to record it as done, so that it
+/// doesn't write to the run's directory as it is being removed.
+fn stop_for_removal(run: &Run, grace: Duration) -> Result<()> {
+    // The run may have finished on its own in the meantime
+    if let RunDataState::Running { .. } | RunDataState::Queued { .. } = run.get_data()?.state {
+        stop_run(run, grace, false)?;
+    }
+
+    let stopped = Instant::now();
+    while stopped.elapsed() < RECORD_GRACE {
+        let data = run.get_data()?;
+        if matches!(data.state, RunDataState::Done { .. }) || run.is_orphaned(&data) {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    Ok(())
+}
+
+fn confirm(prompt: &str) -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        return Err(Error::msg(
+            "Can't ask for confirmation without a terminal (pass --yes to remove runs anyway)",
+        ));
+    }
+    Ok(
+        dialoguer::Confirm::with_theme(&dialoguer::theme::ColorfulTheme::default())
+            .with_prompt(prompt)
+            .interact()?,
+    )
 }
 
+/// Remove the given runs, along with those matching `filter`; a run is picked interactively if
+/// there are neither. Unless told otherwise, a single confirmation is asked for.
 pub fn remove_runs(
     runs: &Runs,
     runs_to_remove: &[String],
-    ask_for_confirmation: bool,
+    filter: Option<&Filter>,
+    options: &RemoveOptions,
 ) -> Result<()> {
     let (good_runs, bad_runs): (Vec<_>, Vec<_>) = runs_to_remove
         .iter()
-        .map(|r| runs.get_run(r))
+        .map(|r| get_run_or_pick_among_matches(runs, r))
         .partition(Result::is_ok);
-    let good_runs = good_runs.into_iter().map(Result::unwrap);
+    let mut good_runs = good_runs
+        .into_iter()
+        .map(Result::unwrap)
+        .collect::<Vec<_>>();
     let bad_runs = bad_runs.into_iter().map(Result::unwrap_err);
 
-    for run in good_runs {
-        remove_run(runs, run, ask_for_confirmation)?;
+    match filter {
+        Some(filter) => good_runs.extend(runs.get_matching(filter)?),
+        None if runs_to_remove.is_empty() => good_runs.push(pick_run(runs)?),
+        None => (),
     }
+    let mut seen = HashSet::new();
+    good_runs.retain(|run| seen.insert(run.id.clone()));
+    // Unless told to kill them, only finished runs can be removed; the others are reported along
+    // with the bad IDs
+    let (good_runs, unfinished_runs): (Vec<_>, Vec<_>) = good_runs.into_iter().partition(|run| {
+        options.kill
+            || run
+                .get_data()
+                .is_ok_and(|data| matches!(data.state, RunDataState::Done { .. }))
+    });
+    let kill = options.kill.then_some(options.grace);
+    // Nor are other users' runs, unless forced to
+    let (good_runs, others_runs): (Vec<_>, Vec<_>) = good_runs.into_iter().partition(|run| {
+        run.get_data()
+            .is_ok_and(|data| run.check_owner(&data, "remove", options.force).is_ok())
+    });
 
-    for error in bad_runs {
-        println!("{} {}", "ERROR".red().bold(), error,)
+    if options.dry_run {
+        for run in &good_runs {
+            print_would_remove(run)?;
+        }
+    } else if options.yes || options.interactive || good_runs.len() == 1 {
+        for run in good_runs {
+            remove_run(runs, run, !options.yes, kill)?;
+        }
+    } else if !good_runs.is_empty() {
+        for run in &good_runs {
+            let data = run.get_data()?;
+            println!(
+                "{} {} {}",
+                &run.id[..8],
+                format!("[{}]", data.state.status()).dimmed(),
+                shell_words::join(&data.command).bold(),
+            );
+        }
+        println!();
+        if confirm(&format!(
+            "Are you sure you want to delete these {} runs?",
+            good_runs.len()
+        ))? {
+            for run in good_runs {
+                remove_run(runs, run, false, kill)?;
+            }
+        }
+    }
+
+    for run in &unfinished_runs {
+        run.record_event(Event::RemoveRefused);
+    }
+    let unfinished_runs = unfinished_runs.into_iter().map(|run| match run.get_data() {
+        Ok(data) => Error::msg(format!(
+            "Still {}: {} (pass --kill to stop and remove it anyway)",
+            data.state.status(),
+            run.id
+        )),
+        Err(e) => e,
+    });
+    let others_runs = others_runs.into_iter().map(|run| {
+        run.get_data()
+            .and_then(|data| run.check_owner(&data, "remove", options.force))
+            .unwrap_err()
+    });
+    for error in bad_runs.chain(unfinished_runs).chain(others_runs) {
+        println!("{} {}", paint("ERROR", theme::get().failure).bold(), error,)
     }
 
     Ok(())
@@ -0,0 +1,41 @@
+use anyhow::Result;
+use chrono::Utc;
+use colored::Colorize;
+
+use crate::{runs::Runs, utils::format_duration};
+
+/// Restore a removed run from the trash, given (a prefix of) its ID; without one, list the runs
+/// in the trash instead.
+pub fn restore_run(runs: &Runs, id: Option<String>) -> Result<()> {
+    let Some(id) = id else {
+        return list_trash(runs);
+    };
+    let run = runs.restore_run(&id)?;
+    println!("Restored run '{}'.", run.id);
+    Ok(())
+}
+
+fn list_trash(runs: &Runs) -> Result<()> {
+    let trashed = runs.get_trashed()?;
+    if trashed.is_empty() {
+        println!("The trash is empty.");
+        return Ok(());
+    }
+    for (run, trashed_datetime) in trashed {
+        let command = run
+            .get_data()
+            .map(|data| shell_words::join(&data.command))
+            .unwrap_or_default();
+        println!(
+            "{} {} {}",
+            &run.id[..8],
+            format!(
+                "[removed {} ago]",
+                format_duration(Utc::now() - trashed_datetime)
+            )
+            .dimmed(),
+            command.bold(),
+        );
+    }
+    Ok(())
+}
@@ -1,13 +1,135 @@
+use std::time::{Duration, Instant};
+
 use anyhow::{Context, Error, Result};
-use nix::sys::signal;
+use nix::{sys::signal, unistd::Pid};
+
+use crate::{
+    events::Event,
+    filter::Filter,
+    runs::{Run, RunDataState, Runs},
+    utils::proc,
+};
+
+/// How long to wait for a run to die after SIGKILL, which it can't ignore.
+const KILL_GRACE: Duration = Duration::from_secs(5);
+
+/// The processes of a running run.
+struct Processes {
+    pgid: Pid,
+    pid: Option<Pid>,
+    /// Whether to include processes which left the run's process group (e.g. daemonized
+    /// children), by looking for all descendants of its processes.
+    tree: bool,
+}
 
-use crate::runs::{Run, RunDataState};
+impl Processes {
+    fn send(&self, signal: signal::Signal) -> Result<()> {
+        if !self.tree {
+            return signal::killpg(self.pgid, signal)
+                .with_context(|| "Couldn't send signal to run's process");
+        }
 
-pub fn send_signal(run: &Run, signal: signal::Signal) -> Result<()> {
+        let tree = proc::process_tree(self.pgid.as_raw(), self.pid.map(Pid::as_raw))?;
+        if tree.is_empty() {
+            return Err(Error::msg("The run has no processes left"));
+        }
+        for pid in tree {
+            // The process may have exited in the meantime
+            let _ = signal::kill(Pid::from_raw(pid), signal);
+        }
+        Ok(())
+    }
+
+    fn are_alive(&self) -> bool {
+        match self.tree {
+            false => proc::process_group_is_alive(self.pgid.as_raw()),
+            true => proc::process_tree(self.pgid.as_raw(), self.pid.map(Pid::as_raw))
+                .is_ok_and(|tree| !tree.is_empty()),
+        }
+    }
+}
+
+pub fn send_signal(run: &Run, signal: signal::Signal, tree: bool) -> Result<()> {
     match run.get_data()?.state {
-        RunDataState::Running { pgid } => {
-            signal::killpg(pgid, signal).with_context(|| "Couldn't send signal to run's process")
+        RunDataState::Running { pgid, pid, .. } => {
+            Processes { pgid, pid, tree }.send(signal)?;
+            run.record_event(Event::Signalled {
+                signal: signal.to_string(),
+            });
+            Ok(())
+        }
+        // The run hasn't started yet, so whatever the signal, we just make sure it never does
+        RunDataState::Queued { .. } => run.cancel(),
+        RunDataState::Done { .. } => Err(Error::msg(format!("Not running: {}", run.id))),
+    }
+}
+
+/// Stop a run as gently as possible: interrupt it, then terminate it if it's still around after
+/// `grace`, and then kill it if it still is after another `grace`.
+pub fn stop_run(run: &Run, grace: Duration, tree: bool) -> Result<()> {
+    let processes = match run.get_data()?.state {
+        RunDataState::Running { pgid, pid, .. } => Processes { pgid, pid, tree },
+        RunDataState::Queued { .. } => {
+            run.cancel()?;
+            println!("Cancelled queued run '{}'.", run.id);
+            return Ok(());
+        }
+        RunDataState::Done { .. } => return Err(Error::msg(format!("Not running: {}", run.id))),
+    };
+
+    for (signal, grace) in [
+        (signal::Signal::SIGINT, grace),
+        (signal::Signal::SIGTERM, grace),
+        (signal::Signal::SIGKILL, KILL_GRACE),
+    ] {
+        if !processes.are_alive() {
+            break;
+        }
+        processes.send(signal)?;
+        run.record_event(Event::Signalled {
+            signal: signal.to_string(),
+        });
+        println!("Sent {} to run '{}'.", signal, run.id);
+
+        let sent = Instant::now();
+        while processes.are_alive() && sent.elapsed() < grace {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        if !processes.are_alive() {
+            println!("Run '{}' stopped after {}.", run.id, signal);
+            return Ok(());
+        }
+    }
+
+    match processes.are_alive() {
+        true => Err(Error::msg(format!(
+            "Run '{}' is still running, even after SIGKILL",
+            run.id
+        ))),
+        false => {
+            println!("Run '{}' stopped.", run.id);
+            Ok(())
+        }
+    }
+}
+
+pub fn send_signal_to_matching(
+    runs: &Runs,
+    filter: &Filter,
+    signal: signal::Signal,
+    tree: bool,
+    force: bool,
+) -> Result<()> {
+    for run in runs.get_matching(filter)? {
+        let data = run.get_data()?;
+        if let RunDataState::Running { .. } | RunDataState::Queued { .. } = data.state {
+            if let Err(e) = run.check_owner(&data, "signal", force) {
+                println!("Skipping: {e}.");
+                continue;
+            }
+            send_signal(&run, signal, tree)?;
+            println!("Sent {} to run '{}'.", signal, run.id);
         }
-        RunDataState::Done { .. } => Err(Error::msg(format!("Still running: {}", run.id))),
     }
+    Ok(())
 }
@@ -1,53 +1,222 @@
+use std::collections::HashSet;
+
 use anyhow::Result;
+use chrono::Utc;
 use colored::Colorize;
 
 use crate::{
+    annotations::Annotate,
+    config::Config,
     runs::{Run, RunData, RunDataState},
-    utils::format_datetime,
+    theme::{self, paint},
+    utils::{format_datetime, format_duration, format_size},
 };
 
+/// Show information on each of `runs` in turn (each only once, even if given several times).
+pub fn show_runs_info(mut runs: Vec<Run>) -> Result<()> {
+    let mut seen = HashSet::new();
+    runs.retain(|run| seen.insert(run.id.clone()));
+    if runs.is_empty() {
+        println!("No runs to show.");
+    }
+    for (index, run) in runs.iter().enumerate() {
+        if runs.len() > 1 {
+            if index > 0 {
+                println!();
+            }
+            println!("Run:       {}", run.id.bold());
+        }
+        show_run_info(run)?;
+    }
+    Ok(())
+}
+
+/// Show information on runs, as [`show_runs_info`] does, but for a CI log: each run's information
+/// is put in a group of its own, and whatever is wrong with it is annotated.
+pub fn show_runs_info_annotated(
+    mut runs: Vec<Run>,
+    annotate: Annotate,
+    config: &Config,
+) -> Result<()> {
+    let mut seen = HashSet::new();
+    runs.retain(|run| seen.insert(run.id.clone()));
+    if runs.is_empty() {
+        println!("No runs to show.");
+    }
+    let mut annotations = Vec::new();
+    for run in &runs {
+        let data = run.get_data()?;
+        print!("{}", annotate.start_group(&run.summarize(&data)));
+        show_run_info(run)?;
+        print!("{}", annotate.end_group());
+        let key = config.encryption.key_for(&data)?;
+        annotations.extend(annotate.annotate_run(run, &data, key.as_ref(), run.is_stalled(&data)));
+    }
+    // Outside of the groups, so that they aren't hidden away
+    for annotation in annotations {
+        print!("{annotation}");
+    }
+    Ok(())
+}
+
 pub fn show_run_info(run: &Run) -> Result<()> {
-    match run.get_data()? {
+    let data = run.get_data()?;
+    let is_stalled = run.is_stalled(&data);
+    let parent = data.parent.clone();
+    let owner = data.owner.clone();
+    let theme = theme::get();
+    match data {
         RunData {
             label,
+            name,
             command,
             start_datetime,
             state:
                 RunDataState::Done {
                     end_datetime,
                     exit_code,
+                    resource_usage,
+                    failed_output,
                 },
+            ..
         } => {
             println!("Command:   {}", shell_words::join(command));
             if let Some(label) = label {
                 println!("Label:     {label}");
             }
+            if let Some(name) = name {
+                println!("Name:      {name}");
+            }
             println!("Status:    finished");
             println!(
                 "Exit code: {}",
                 match exit_code {
-                    0 => format!("0 ({})", "success".green()),
-                    -1 => format!("none ({})", "killed".yellow()),
-                    -2 => format!("none ({})", "crashed".magenta()),
-                    c => format!("{} ({})", c, "failed".red()),
+                    0 => format!("0 ({})", paint("success", theme.success)),
+                    -1 => format!("none ({})", paint("killed", theme.killed)),
+                    -2 => format!("none ({})", paint("crashed", theme.crashed)),
+                    -3 => format!("none ({})", paint("unknown", theme.unknown)),
+                    -4 => format!("none ({})", paint("interrupted by shutdown", theme.killed)),
+                    c => format!("{} ({})", c, paint("failed", theme.failure)),
                 }
             );
+            if let Some(failed_output) = failed_output {
+                println!(
+                    "Failed:    {} {}",
+                    paint("output matched:", theme.failure),
+                    failed_output.trim_end()
+                );
+            }
             println!("Started:   {}", format_datetime(start_datetime));
             println!("Finished:  {}", format_datetime(end_datetime));
+            if let Some(resource_usage) = resource_usage {
+                println!("Max RSS:   {}", format_size(resource_usage.max_rss));
+                println!(
+                    "CPU time:  {:.2}s user, {:.2}s system",
+                    resource_usage.user_time, resource_usage.system_time
+                );
+            }
+            let artifacts = run.get_artifacts()?;
+            if !artifacts.is_empty() {
+                println!("Artifacts: {}", run.get_artifacts_directory().display());
+                for artifact in artifacts {
+                    println!("           {}", artifact.display());
+                }
+            }
         }
         RunData {
             label,
+            name,
             command,
             start_datetime,
-            state: RunDataState::Running { pgid: _ },
+            expected_duration,
+            state: RunDataState::Running { .. },
+            ..
         } => {
             println!("Command:   {}", shell_words::join(command));
             if let Some(label) = label {
                 println!("Label:     {label}");
             }
+            if let Some(name) = name {
+                println!("Name:      {name}");
+            }
             println!("Status:    running");
             println!("Started:   {}", format_datetime(start_datetime));
+            if let Some(expected_duration) =
+                expected_duration.and_then(|d| chrono::Duration::from_std(d).ok())
+            {
+                let is_overdue = Utc::now() - start_datetime > expected_duration;
+                println!(
+                    "Expected:  {}{}",
+                    format_duration(expected_duration),
+                    if is_overdue {
+                        format!(" ({})", paint("overdue", theme.warning))
+                    } else {
+                        String::new()
+                    }
+                );
+            }
+            if let Some(last_output) = run.get_last_output_time()? {
+                println!(
+                    "Output:    last {} ago{}",
+                    format_duration(Utc::now() - last_output),
+                    if is_stalled {
+                        format!(" ({})", paint("stalled", theme.warning))
+                    } else {
+                        String::new()
+                    }
+                );
+            } else if is_stalled {
+                println!("Output:    none yet ({})", paint("stalled", theme.warning));
+            }
         }
+        RunData {
+            label,
+            name,
+            command,
+            start_datetime,
+            state: RunDataState::Queued { lock, .. },
+            ..
+        } => {
+            println!("Command:   {}", shell_words::join(command));
+            if let Some(label) = label {
+                println!("Label:     {label}");
+            }
+            if let Some(name) = name {
+                println!("Name:      {name}");
+            }
+            println!("Status:    queued (waiting for lock '{lock}')");
+            println!("Queued:    {}", format_datetime(start_datetime));
+        }
+    }
+    if let Some(owner) = owner {
+        println!("User:      {owner}");
+    }
+    if let Some(parent) = parent {
+        println!("Parent:    {parent}");
+    }
+    if run.get_snapshot_file().exists() {
+        println!("Snapshot:  {}", run.get_snapshot_file().display());
+    }
+    if run.get_core_file().exists() {
+        println!("Core dump: {}", run.get_core_file().display());
+    }
+    for (index, entry) in run.get_events()?.into_iter().enumerate() {
+        let by = match (entry.by, entry.user) {
+            (Some(by), Some(user)) => format!(" ({by}, by {user})").dimmed().to_string(),
+            (Some(by), None) => format!(" ({by})").dimmed().to_string(),
+            (None, _) => String::new(),
+        };
+        println!(
+            "{}{} {}{}",
+            if index == 0 {
+                "Events:    "
+            } else {
+                "           "
+            },
+            format_datetime(entry.datetime),
+            entry.event,
+            by
+        );
     }
     Ok(())
 }
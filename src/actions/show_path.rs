@@ -0,0 +1,25 @@
+use anyhow::Result;
+
+use crate::runs::Run;
+
+/// Which of a run's paths to show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunPath {
+    Directory,
+    Output,
+    Data,
+}
+
+pub fn show_run_path(run: &Run, which: RunPath) -> Result<()> {
+    let path = match which {
+        RunPath::Directory => run.run_directory.clone(),
+        // When the output is split into several files, the most recent one is the interesting one
+        RunPath::Output => run
+            .get_output_files()?
+            .pop()
+            .unwrap_or_else(|| run.get_output_file()),
+        RunPath::Data => run.get_data_file(),
+    };
+    println!("{}", path.display());
+    Ok(())
+}
@@ -1,11 +1,143 @@
-use anyhow::{Error, Result};
+use anyhow::{Context, Error, Result};
+use regex::Regex;
 
-use crate::runs::Runs;
+use crate::{
+    config::{self, Config, OnExceed},
+    core_dump,
+    encryption::Key,
+    events, inhibit, quota,
+    runs::{Run, RunDataState, Runs, StartOptions, RUN_ID_ENV},
+    sandbox,
+};
 
-pub fn start_run(runs: &Runs, command: Vec<String>, label: Option<String>) -> Result<()> {
+pub fn start_run(
+    runs: &Runs,
+    config: &Config,
+    command: Vec<String>,
+    options: &StartOptions,
+) -> Result<Run> {
     if command.is_empty() {
         return Err(Error::msg("Given command is empty"));
     }
 
-    runs.new_run()?.start(command, label)
+    if options.unique {
+        let already_running = runs.get_all()?.into_iter().find(|run| {
+            run.get_data().is_ok_and(|data| {
+                matches!(
+                    data.state,
+                    RunDataState::Running { .. } | RunDataState::Queued { .. }
+                ) && data.command == command
+            })
+        });
+        if let Some(run) = already_running {
+            return Err(Error::msg(format!(
+                "The same command is already running as run {}",
+                run.id
+            )));
+        }
+    }
+
+    if options.no_network {
+        sandbox::check_network_isolation()?;
+    }
+    if options.sandbox.is_some() {
+        sandbox::check_bwrap()?;
+    }
+    for target in &options.log_to {
+        target.check()?;
+    }
+    if options.core_dumps {
+        core_dump::check()?;
+    }
+    let inhibit_sleep = options.inhibit_sleep || config.inhibit_sleep;
+    if inhibit_sleep {
+        inhibit::check()?;
+    }
+
+    let directory = match &options.working_directory {
+        Some(directory) => directory.clone(),
+        None => std::env::current_dir()?,
+    };
+    let label = options
+        .label
+        .clone()
+        .or_else(|| config.auto_label_for(&directory));
+    let parent = std::env::var(RUN_ID_ENV).ok().filter(|id| !id.is_empty());
+
+    // Runs started from within other runs don't count, as their parent already does
+    let quota = match parent {
+        Some(_) => Vec::new(),
+        None => quota::slots_for(
+            &config.quota,
+            &runs.get_quota_directory()?,
+            &events::current_user(),
+            &directory,
+            label.as_deref(),
+        ),
+    };
+
+    let lock_file = options
+        .lock
+        .as_deref()
+        .map(|lock| runs.get_lock_file(lock))
+        .transpose()?;
+
+    let fail_on_regex = match (&options.fail_on_regex, &config.fail_on_regex) {
+        (None, Some(regex)) => Some(
+            Regex::new(regex).with_context(|| "Invalid `fail_on_regex` in the configuration")?,
+        ),
+        (regex, _) => regex.clone(),
+    };
+    let redact = config
+        .redact
+        .iter()
+        .map(|pattern| regex::bytes::Regex::new(pattern))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| "Invalid `redact` pattern in the configuration")?;
+    let environment = match options.capture_env || config.environment.capture {
+        true => Some(config.environment.capture()?),
+        false => None,
+    };
+    let encryption_key =
+        match options.encrypt || config.encryption.always {
+            true if options.split_logs || options.log_max.is_some() => return Err(Error::msg(
+                "Encrypted output can't be split or limited (`encryption.always` is set in the \
+                 configuration)",
+            )),
+            true if options.log_file.is_some() => {
+                return Err(Error::msg(
+                    "Encrypted output can't be written to a log file of your choosing \
+                 (`encryption.always` is set in the configuration)",
+                ))
+            }
+            true => Some(Key::load_or_generate(&config.encryption.key_file()?)?),
+            false => None,
+        };
+    // The run's directory links to the log file, so a relative path would point elsewhere
+    let log_file = options
+        .log_file
+        .as_deref()
+        .map(std::path::absolute)
+        .transpose()?;
+    let options = StartOptions {
+        fail_on_regex,
+        log_file,
+        encryption_key,
+        redact,
+        environment,
+        inhibit_sleep,
+        stall_after: options.stall_after.or(config.stall_after),
+        telemetry: config.telemetry.clone(),
+        archive: config.archive.clone().filter(|archive| archive.on_finish),
+        email: config.email.clone(),
+        hooks_directory: Some(config::config_directory()?.join("hooks")),
+        parent,
+        quota,
+        reject_over_quota: config.quota.on_exceed == OnExceed::Reject,
+        store_directory: Some(runs.get_data_directory().to_path_buf()),
+        ..options.clone()
+    };
+    let run = runs.new_run()?;
+    run.start(command, label, lock_file, &options)?;
+    Ok(run)
 }
@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+use crate::{
+    runs::{RunDataState, Runs},
+    theme::{self, paint},
+    utils::format_duration,
+};
+
+/// The default format of `rum -status-line`.
+pub const DEFAULT_STATUS_LINE_FORMAT: &str = "▶{running} ✗{failed} {oldest}";
+
+/// A quick summary of the state of all runs.
+#[derive(Debug, Default)]
+struct Summary {
+    running: usize,
+    queued: usize,
+    /// How many runs failed recently.
+    failed: usize,
+    /// What to call the run which has been running the longest, and when it started.
+    oldest_running: Option<(String, DateTime<Utc>)>,
+}
+
+impl Summary {
+    /// Summarize the runs, counting those which failed within `failed_within` as failed. Only
+    /// the runs' data is read, so that this stays cheap even with many runs.
+    fn of(runs: &Runs, failed_within: Duration) -> Result<Self> {
+        let now = Utc::now();
+        let mut summary = Self::default();
+        for run in runs.get_all()? {
+            let Ok(data) = run.get_data() else {
+                continue;
+            };
+            match data.state {
+                RunDataState::Running { .. } => {
+                    summary.running += 1;
+                    if summary
+                        .oldest_running
+                        .as_ref()
+                        .is_none_or(|(_, start)| data.start_datetime < *start)
+                    {
+                        let name = data
+                            .label
+                            .or(data.name)
+                            .unwrap_or_else(|| run.id[..8].to_string());
+                        summary.oldest_running = Some((name, data.start_datetime));
+                    }
+                }
+                RunDataState::Queued { .. } => summary.queued += 1,
+                RunDataState::Done { end_datetime, .. }
+                    if !data.state.is_success()
+                        && (now - end_datetime).to_std().unwrap_or_default() <= failed_within =>
+                {
+                    summary.failed += 1
+                }
+                RunDataState::Done { .. } => (),
+            }
+        }
+        Ok(summary)
+    }
+}
+
+/// Print a compact summary of the runs which are running and which recently failed (e.g.
+/// `▶2 ✗1`), for shell prompts; nothing is printed if there are neither.
+pub fn show_prompt_status(runs: &Runs, failed_within: Duration) -> Result<()> {
+    let summary = Summary::of(runs, failed_within)?;
+
+    let theme = theme::get();
+    let mut parts = Vec::new();
+    if summary.running > 0 {
+        parts.push(format!("▶{}", summary.running));
+    }
+    if summary.failed > 0 {
+        parts.push(paint(&format!("✗{}", summary.failed), theme.failure).to_string());
+    }
+    if !parts.is_empty() {
+        println!("{}", parts.join(" "));
+    }
+    Ok(())
+}
+
+/// Print a single line summarizing the runs according to `format`, for status bars (e.g. tmux's
+/// `status-right`). The format can refer to `{running}`, `{queued}` and `{failed}` (the numbers of
+/// such runs), and `{oldest}` and `{oldest_duration}` (the label or name of the run which has been
+/// running the longest, and for how long; both are empty if no run is running).
+pub fn show_status_line(runs: &Runs, format: &str, failed_within: Duration) -> Result<()> {
+    let summary = Summary::of(runs, failed_within)?;
+
+    let (oldest, oldest_duration) = match summary.oldest_running {
+        Some((name, start)) => (name, format_duration(Utc::now() - start)),
+        None => (String::new(), String::new()),
+    };
+    let line = format
+        .replace("{running}", &summary.running.to_string())
+        .replace("{queued}", &summary.queued.to_string())
+        .replace("{failed}", &summary.failed.to_string())
+        .replace("{oldest_duration}", &oldest_duration)
+        .replace("{oldest}", &oldest);
+    println!("{}", line.trim());
+    Ok(())
+}
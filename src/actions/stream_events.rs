@@ -0,0 +1,105 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Seek, Write},
+    path::Path,
+    sync::mpsc::{channel, RecvTimeoutError},
+    time::Duration,
+};
+
+use anyhow::Result;
+use notify::Watcher;
+
+use crate::{
+    events::{AuditEntry, Entry},
+    runs::Runs,
+};
+
+/// How often the runs' journals are checked for new events, in case we aren't notified of them
+/// (e.g. on NFS).
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Print the events of all runs (see [`crate::events::Event`]) as JSON lines, oldest first; with
+/// `follow`, print new events as they happen instead, until interrupted.
+pub fn stream_events(runs: &Runs, follow: bool) -> Result<()> {
+    if !follow {
+        let mut entries = Vec::new();
+        for run in runs.get_all()? {
+            entries.extend(run.get_events()?.into_iter().map(|entry| AuditEntry {
+                run: run.id.clone(),
+                entry,
+            }));
+        }
+        entries.sort_by_key(|audit_entry| audit_entry.entry.datetime);
+        for audit_entry in entries {
+            println!("{}", serde_json::to_string(&audit_entry)?);
+        }
+        return Ok(());
+    }
+
+    let (tx, rx) = channel();
+    // If we can't watch the runs, we just rely on polling
+    let _watcher = notify::raw_watcher(tx)
+        .and_then(|mut watcher| {
+            watcher.watch(runs.get_runs_directory(), notify::RecursiveMode::Recursive)?;
+            Ok(watcher)
+        })
+        .ok();
+
+    // How much of each journal was already seen; whatever is in them now has already happened
+    let mut positions = HashMap::new();
+    for run in runs.get_all()? {
+        let journal = run.get_events_file();
+        let size = std::fs::metadata(&journal).map_or(0, |m| m.len());
+        positions.insert(journal, size);
+    }
+
+    loop {
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(_) | Err(RecvTimeoutError::Timeout) => (),
+            // The sender is dropped right away when there is no watcher
+            Err(RecvTimeoutError::Disconnected) => std::thread::sleep(POLL_INTERVAL),
+        }
+        // Events usually come in bursts, all of which are handled at once
+        while rx.try_recv().is_ok() {}
+
+        for run in runs.get_all()? {
+            let journal = run.get_events_file();
+            let position = positions.entry(journal.clone()).or_insert(0);
+            for entry in read_new_entries(&journal, position)? {
+                let audit_entry = AuditEntry {
+                    run: run.id.clone(),
+                    entry,
+                };
+                println!("{}", serde_json::to_string(&audit_entry)?);
+            }
+        }
+        std::io::stdout().flush()?;
+        positions.retain(|journal, _| journal.exists());
+    }
+}
+
+/// Read the complete entries added to `journal` since `position`, moving it past them.
+fn read_new_entries(journal: &Path, position: &mut u64) -> Result<Vec<Entry>> {
+    let Ok(mut file) = std::fs::File::open(journal) else {
+        return Ok(Vec::new());
+    };
+    if file.metadata()?.len() <= *position {
+        return Ok(Vec::new());
+    }
+    file.seek(std::io::SeekFrom::Start(*position))?;
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+
+    let mut entries = Vec::new();
+    for line in contents.split_inclusive(|&b| b == b'\n') {
+        // A line without a newline is still being written
+        if !line.ends_with(b"\n") {
+            break;
+        }
+        *position += line.len() as u64;
+        if let Ok(entry) = serde_json::from_slice(line) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
@@ -0,0 +1,89 @@
+use anyhow::{Error, Result};
+use serde::Serialize;
+
+use crate::{
+    metrics,
+    runs::{Run, RunDataState},
+    utils::format_size,
+};
+
+/// How long a finished run took and what it used, as `time` would report it.
+#[derive(Debug, Serialize)]
+struct Report {
+    run: String,
+    status: String,
+    /// Missing for runs which were killed, crashed, or whose exit code is unknown.
+    exit_code: Option<i32>,
+    /// In seconds, as are the CPU times.
+    wall_clock: f64,
+    user: Option<f64>,
+    system: Option<f64>,
+    /// The total CPU time, when it isn't known how it splits into user and system time (e.g. for
+    /// adopted runs, for which it comes from the sampled metrics).
+    cpu: Option<f64>,
+    /// In bytes.
+    max_rss: Option<u64>,
+}
+
+/// Print how long a finished run took (wall clock, user and system CPU time), how much memory it
+/// used at most, and how it exited, like `time` does; or as JSON, if `json`.
+pub fn time_run(run: &Run, json: bool) -> Result<()> {
+    let data = run.get_data()?;
+    let RunDataState::Done {
+        end_datetime,
+        exit_code,
+        resource_usage,
+        ..
+    } = data.state
+    else {
+        return Err(Error::msg("Run has not finished yet"));
+    };
+
+    let mut report = Report {
+        run: run.id.clone(),
+        status: data.state.status(),
+        exit_code: (exit_code >= 0).then_some(exit_code),
+        wall_clock: (end_datetime - data.start_datetime).num_milliseconds() as f64 / 1000.0,
+        user: resource_usage.map(|usage| usage.user_time),
+        system: resource_usage.map(|usage| usage.system_time),
+        cpu: None,
+        max_rss: resource_usage.map(|usage| usage.max_rss),
+    };
+    // Without the resource usage from when the run was waited for, the metrics sampled while it
+    // ran are the next best thing (if less precise)
+    if resource_usage.is_none() {
+        let samples = metrics::read_samples(&run.get_metrics_file()).unwrap_or_default();
+        report.cpu = samples
+            .iter()
+            .map(|sample| sample.cpu_time)
+            .max_by(f64::total_cmp);
+        report.max_rss = samples.iter().map(|sample| sample.rss).max();
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+    let unknown = || "?".to_string();
+    let seconds = |s: Option<f64>| s.map_or_else(unknown, |s| format!("{s:.3}s"));
+    println!("real    {:.3}s", report.wall_clock);
+    match report.cpu {
+        Some(cpu) => println!("cpu     {cpu:.3}s (sampled)"),
+        None => {
+            println!("user    {}", seconds(report.user));
+            println!("sys     {}", seconds(report.system));
+        }
+    }
+    println!(
+        "maxrss  {}",
+        report.max_rss.map_or_else(unknown, format_size)
+    );
+    println!(
+        "exit    {}",
+        match report.exit_code {
+            Some(exit_code) => format!("{exit_code} ({})", report.status),
+            None => format!("none ({})", report.status),
+        }
+    );
+    Ok(())
+}
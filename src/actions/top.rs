@@ -0,0 +1,119 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use chrono::Utc;
+use tabled::{settings::Style, Table, Tabled};
+use termion::{event::Key, input::TermRead, raw::IntoRawMode};
+
+use crate::{
+    runs::{RunDataState, RunId, Runs},
+    utils::{
+        format_duration, format_size,
+        proc::{all_processes, Usage},
+    },
+};
+
+#[derive(Tabled)]
+struct Row {
+    #[tabled(rename = "ID")]
+    id: String,
+    #[tabled(rename = "ELAPSED")]
+    elapsed: String,
+    #[tabled(rename = "CPU%")]
+    cpu: String,
+    #[tabled(rename = "MEM")]
+    memory: String,
+    #[tabled(rename = "PROCS")]
+    processes: usize,
+    #[tabled(rename = "LAST OUTPUT")]
+    last_output: String,
+    #[tabled(rename = "COMMAND")]
+    command: String,
+}
+
+/// Continuously show the running runs along with the resources they are using.
+pub fn show_top(runs: &Runs, interval: Duration) -> Result<()> {
+    let mut screen = termion::screen::AlternateScreen::from(std::io::stdout()).into_raw_mode()?;
+    let mut input = termion::async_stdin().keys();
+
+    // The CPU time of each run when we last looked, to compute CPU usage over the last interval
+    let mut previous_cpu_times: HashMap<RunId, (Instant, f64)> = HashMap::new();
+
+    loop {
+        let processes = all_processes()?;
+        let now = Utc::now();
+
+        let mut rows = Vec::new();
+        let mut previous_cpu_times_of_running = HashMap::new();
+        for run in runs.get_all()? {
+            let Ok(data) = run.get_data() else { continue };
+            let RunDataState::Running { pgid, .. } = data.state else {
+                continue;
+            };
+
+            let usage = Usage::of(processes.iter().filter(|p| p.pgid == pgid.as_raw()));
+            previous_cpu_times_of_running.insert(run.id.clone(), (Instant::now(), usage.cpu_time));
+            let cpu = previous_cpu_times
+                .get(&run.id)
+                .map(|(instant, cpu_time)| {
+                    (usage.cpu_time - cpu_time) / instant.elapsed().as_secs_f64() * 100.0
+                });
+            let last_output = run
+                .get_last_output_time()?
+                .map(|last_output| now - last_output);
+
+            rows.push((
+                cpu.unwrap_or(0.0),
+                usage.rss,
+                Row {
+                    id: run.id[..8].to_string(),
+                    elapsed: format_duration(now - data.start_datetime),
+                    cpu: cpu.map_or("-".to_string(), |cpu| format!("{:.1}", cpu)),
+                    memory: format_size(usage.rss),
+                    processes: usage.processes,
+                    last_output: last_output
+                        .map_or("-".to_string(), |age| format!("{} ago", format_duration(age))),
+                    command: shell_words::join(&data.command),
+                },
+            ));
+        }
+        let running = rows.len();
+        previous_cpu_times = previous_cpu_times_of_running;
+        rows.sort_by(|(cpu_a, rss_a, _), (cpu_b, rss_b, _)| {
+            cpu_b.total_cmp(cpu_a).then(rss_b.cmp(rss_a))
+        });
+
+        let (width, _) = termion::terminal_size()?;
+        let table = Table::new(rows.into_iter().map(|(_, _, row)| row))
+            .with(Style::blank())
+            .to_string();
+        write!(
+            screen,
+            "{}{}{}rum top: {} running. Press q to exit.{}\r\n\r\n",
+            termion::clear::All,
+            termion::cursor::Goto(1, 1),
+            termion::style::Faint,
+            running,
+            termion::style::NoFaint,
+        )?;
+        for line in table.lines() {
+            let line = line.chars().take(width as usize).collect::<String>();
+            write!(screen, "{}\r\n", line)?;
+        }
+        screen.flush()?;
+
+        let deadline = Instant::now() + interval;
+        while Instant::now() < deadline {
+            for key in &mut input {
+                if let Key::Char('q') | Key::Ctrl('c') | Key::Esc = key? {
+                    return Ok(());
+                }
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
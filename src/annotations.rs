@@ -0,0 +1,104 @@
+use chrono::Utc;
+
+use crate::{
+    encryption::Key,
+    runs::{Run, RunData, RunDataState},
+};
+
+/// How many of the last lines of a failed run's output to put in its annotation.
+const OUTPUT_LINES: usize = 20;
+
+/// CI systems whose logs rum can annotate, with problems with runs and with groups for their
+/// output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Annotate {
+    /// GitHub Actions' workflow commands (`::error::`, `::group::`, ...)
+    Github,
+}
+
+/// How bad whatever is to be annotated about a run is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Severity {
+    Error,
+    Warning,
+}
+
+impl Annotate {
+    /// Start a collapsible group in the log, whose title is `title`.
+    pub fn start_group(self, title: &str) -> String {
+        match self {
+            Annotate::Github => format!("::group::{}\n", escape_data(title)),
+        }
+    }
+
+    /// End the group started last.
+    pub fn end_group(self) -> String {
+        match self {
+            Annotate::Github => "::endgroup::\n".to_string(),
+        }
+    }
+
+    /// Annotate whatever is wrong with a run, if anything: an error if it failed or crashed, and a
+    /// warning if it was killed, interrupted, or is stalled or overdue. Failed runs' annotations
+    /// have the end of their output, decrypted with `key` if it is encrypted.
+    pub fn annotate_run(
+        self,
+        run: &Run,
+        data: &RunData,
+        key: Option<&Key>,
+        is_stalled: bool,
+    ) -> Option<String> {
+        let status = data.state.status();
+        let (severity, problem) = match data.state {
+            RunDataState::Done { .. } if data.state.is_success() => return None,
+            RunDataState::Done { exit_code: -2, .. } => (Severity::Error, status),
+            RunDataState::Done { .. } if data.state.is_failure() => (Severity::Error, status),
+            RunDataState::Done { .. } => (Severity::Warning, status),
+            RunDataState::Running { .. } if is_stalled => (Severity::Warning, "stalled".into()),
+            RunDataState::Running { .. } if is_overdue(data) => {
+                (Severity::Warning, "overdue".into())
+            }
+            _ => return None,
+        };
+
+        let mut message = run.summarize(data);
+        if severity == Severity::Error {
+            let lines = run.read_last_lines(OUTPUT_LINES, key).unwrap_or_default();
+            if !lines.is_empty() {
+                message.push_str("\n\n");
+                message.push_str(&lines.join("\n"));
+            }
+        }
+        let title = format!("rum: run {} {}", &run.id[..8], problem);
+        Some(match self {
+            Annotate::Github => format!(
+                "::{} title={}::{}\n",
+                match severity {
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                },
+                escape_property(&title),
+                escape_data(&message)
+            ),
+        })
+    }
+}
+
+fn is_overdue(data: &RunData) -> bool {
+    data.expected_duration
+        .and_then(|d| chrono::Duration::from_std(d).ok())
+        .is_some_and(|expected| Utc::now() - data.start_datetime > expected)
+}
+
+/// Escape the message of a GitHub workflow command, which can't have line breaks of its own.
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escape a property of a GitHub workflow command (e.g. a title), which is also delimited by `:`
+/// and `,`.
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
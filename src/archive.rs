@@ -0,0 +1,214 @@
+use std::time::Duration;
+
+use anyhow::{Context, Error, Result};
+use chrono::{DateTime, Utc};
+use ring::{digest, hmac};
+
+use crate::{config::Archive, runs::Run};
+
+/// How long to wait for the storage before giving up on an upload.
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long shared URLs can be valid for at most, as far as S3 is concerned.
+pub const MAX_URL_VALIDITY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Upload the files in a run's directory (its output, data, events, ...) to the archive, each as
+/// `<prefix><run ID>/<file name>`. Returns the keys of the uploaded objects.
+pub fn archive_run(archive: &Archive, run: &Run) -> Result<Vec<String>> {
+    let mut files = std::fs::read_dir(&run.run_directory)?
+        .filter_map(|entry| entry.ok())
+        // Logs given with `-adopt --output` are symlinks, which we want to follow
+        .filter(|entry| std::fs::metadata(entry.path()).is_ok_and(|m| m.is_file()))
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let mut keys = Vec::new();
+    for file in files {
+        let key = object_key(archive, run, &file.file_name().unwrap().to_string_lossy());
+        let contents =
+            std::fs::read(&file).with_context(|| format!("Could not read {:?}", file))?;
+        put_object(archive, &key, &contents)
+            .with_context(|| format!("Could not upload {:?} to the archive", file))?;
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+/// Get where a run is archived, e.g. `s3://bucket/prefix/<run ID>/`.
+pub fn location(archive: &Archive, run: &Run) -> String {
+    format!("s3://{}/{}", archive.bucket, object_key(archive, run, ""))
+}
+
+/// Get the key of the object a file of a run is archived as.
+pub fn object_key(archive: &Archive, run: &Run, file_name: &str) -> String {
+    format!("{}{}/{}", archive.prefix, run.id, file_name)
+}
+
+fn put_object(archive: &Archive, key: &str, contents: &[u8]) -> Result<()> {
+    let (access_key_id, secret_access_key) = archive.credentials()?;
+    let now = Utc::now();
+    let payload_hash = hex(digest::digest(&digest::SHA256, contents).as_ref());
+    let host = host(&archive.endpoint)?;
+    let path = object_path(archive, key);
+    let headers = [
+        ("host", host),
+        ("x-amz-content-sha256", payload_hash.clone()),
+        ("x-amz-date", amz_datetime(now)),
+    ];
+
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "PUT\n{}\n\n{}\n{}\n{}",
+        path,
+        headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{value}\n"))
+            .collect::<String>(),
+        signed_headers,
+        payload_hash,
+    );
+    let signature = sign(archive, &secret_access_key, now, &canonical_request);
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        access_key_id,
+        scope(archive, now),
+        signed_headers,
+        signature,
+    );
+
+    let url = format!("{}{}", archive.endpoint.trim_end_matches('/'), path);
+    let mut request = ureq::AgentBuilder::new()
+        .timeout(UPLOAD_TIMEOUT)
+        .build()
+        .put(&url)
+        .set("Authorization", &authorization);
+    // `host` is set by ureq itself
+    for (name, value) in &headers[1..] {
+        request = request.set(name, value);
+    }
+    request.send_bytes(contents).map_err(|e| match e {
+        ureq::Error::Status(status, response) => Error::msg(format!(
+            "The storage responded with {}: {}",
+            status,
+            response.into_string().unwrap_or_default().trim()
+        )),
+        e => Error::from(e),
+    })?;
+    Ok(())
+}
+
+/// Get a URL through which anyone can download an archived object, until `validity` passes.
+pub fn share_url(archive: &Archive, key: &str, validity: Duration) -> Result<String> {
+    let (access_key_id, secret_access_key) = archive.credentials()?;
+    let now = Utc::now();
+    let host = host(&archive.endpoint)?;
+    let path = object_path(archive, key);
+
+    // Query parameters have to be sorted for signing
+    let query = [
+        ("X-Amz-Algorithm", "AWS4-HMAC-SHA256".to_string()),
+        (
+            "X-Amz-Credential",
+            format!("{}/{}", access_key_id, scope(archive, now)),
+        ),
+        ("X-Amz-Date", amz_datetime(now)),
+        (
+            "X-Amz-Expires",
+            validity.min(MAX_URL_VALIDITY).as_secs().to_string(),
+        ),
+        ("X-Amz-SignedHeaders", "host".to_string()),
+    ]
+    .iter()
+    .map(|(name, value)| format!("{}={}", name, uri_encode(value, true)))
+    .collect::<Vec<_>>()
+    .join("&");
+    let canonical_request = format!("GET\n{path}\n{query}\nhost:{host}\n\nhost\nUNSIGNED-PAYLOAD");
+    let signature = sign(archive, &secret_access_key, now, &canonical_request);
+
+    Ok(format!(
+        "{}{}?{}&X-Amz-Signature={}",
+        archive.endpoint.trim_end_matches('/'),
+        path,
+        query,
+        signature
+    ))
+}
+
+/// Sign a canonical request as described by AWS Signature Version 4.
+fn sign(
+    archive: &Archive,
+    secret_access_key: &str,
+    now: DateTime<Utc>,
+    canonical_request: &str,
+) -> String {
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_datetime(now),
+        scope(archive, now),
+        hex(digest::digest(&digest::SHA256, canonical_request.as_bytes()).as_ref()),
+    );
+    let hmac = |key: &[u8], message: &str| {
+        hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), message.as_bytes())
+    };
+    let key = hmac(
+        format!("AWS4{secret_access_key}").as_bytes(),
+        &now.format("%Y%m%d").to_string(),
+    );
+    let key = hmac(key.as_ref(), &archive.region);
+    let key = hmac(key.as_ref(), "s3");
+    let key = hmac(key.as_ref(), "aws4_request");
+    hex(hmac(key.as_ref(), &string_to_sign).as_ref())
+}
+
+fn scope(archive: &Archive, now: DateTime<Utc>) -> String {
+    format!(
+        "{}/{}/s3/aws4_request",
+        now.format("%Y%m%d"),
+        archive.region
+    )
+}
+
+fn amz_datetime(now: DateTime<Utc>) -> String {
+    now.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Get the path of an object, as addressed with path-style requests (which any S3-compatible
+/// storage supports).
+fn object_path(archive: &Archive, key: &str) -> String {
+    format!(
+        "/{}/{}",
+        uri_encode(&archive.bucket, true),
+        uri_encode(key, false)
+    )
+}
+
+/// Get the host (and port, if any) of an endpoint.
+fn host(endpoint: &str) -> Result<String> {
+    endpoint
+        .split_once("://")
+        .map(|(_, rest)| rest.split('/').next().unwrap_or_default().to_string())
+        .filter(|host| !host.is_empty())
+        .ok_or_else(|| Error::msg(format!("Invalid archive endpoint '{}'", endpoint)))
+}
+
+/// Percent-encode everything but unreserved characters (and slashes, unless `encode_slash`).
+fn uri_encode(s: &str, encode_slash: bool) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b'/' if !encode_slash => "/".to_string(),
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,439 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Error, Result};
+use serde::{Deserialize, Deserializer};
+
+use crate::{
+    encryption::Key,
+    runs::RunData,
+    theme::Theme,
+    utils::{parse_duration, DatetimeFormat},
+};
+
+/// User configuration, read from `config.toml` in rum's configuration directory (e.g.
+/// `~/.config/rum/config.toml`).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// How to label runs which are started without an explicit label.
+    pub auto_label: AutoLabel,
+    /// Which old runs to remove automatically.
+    pub retention: Retention,
+    /// Consider runs stalled if they produce no output for this long (unless overridden by
+    /// `--stall-after`).
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub stall_after: Option<Duration>,
+    /// Consider runs failed if a line of their output matches this regex (unless overridden by
+    /// `--fail-on-regex`).
+    pub fail_on_regex: Option<String>,
+    /// Regexes for secrets (e.g. API keys) to mask in runs' output before it is recorded.
+    pub redact: Vec<String>,
+    /// Whether and how to record the environment runs are started in.
+    pub environment: Environment,
+    /// Whether and how to encrypt runs' output.
+    pub encryption: Encryption,
+    /// Where to send traces of runs to, if anywhere.
+    pub telemetry: Option<Telemetry>,
+    /// Where to archive runs' logs to, if anywhere.
+    pub archive: Option<Archive>,
+    /// An SMTP server to send emails about finished runs through, if any.
+    pub email: Option<Email>,
+    /// How many runs can run at once, per user, project or label.
+    pub quota: Quota,
+    /// Keep the system from going to sleep while runs are running (also enabled for a single run
+    /// by `--inhibit-sleep`).
+    pub inhibit_sleep: bool,
+    /// What `rum -daemon` should do about unhealthy runs.
+    pub watchdog: Watchdog,
+    /// The colors to show runs' statuses (and such) in.
+    pub theme: Theme,
+    /// How to show dates and times (unless overridden by `--datetime-format`).
+    pub datetime_format: DatetimeFormat,
+}
+
+/// Policies applied by `rum -daemon` to runs which are stalled, overdue or failed.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Watchdog {
+    /// Terminate (SIGTERM) runs which have been stalled for this long.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub terminate_stalled_after: Option<Duration>,
+    /// Start runs which finish while the daemon is running, and match this filter (e.g.
+    /// `status=crashed`), again.
+    pub retry_where: Option<String>,
+    /// How many times to retry a command at most.
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// A shell command to run whenever the daemon notices something about a run; it gets the
+    /// details through `RUM_EVENT`, `RUM_RUN_ID`, `RUM_STATUS`, `RUM_LABEL` and `RUM_COMMAND`.
+    pub hook: Option<String>,
+    /// A Rhai script deciding what to do whenever the daemon notices something about a run (see
+    /// [`crate::policy::decide`]); relative paths are relative to rum's configuration directory.
+    pub policy: Option<PathBuf>,
+}
+
+fn default_max_retries() -> u32 {
+    1
+}
+
+/// What the values of masked environment variables are recorded as.
+pub const MASKED_VALUE: &str = "***";
+
+/// Glob patterns for the variables which are always masked.
+const DEFAULT_MASK: [&str; 6] = [
+    "*SECRET*",
+    "*TOKEN*",
+    "*PASSWORD*",
+    "*PASSWD*",
+    "*API_KEY*",
+    "*ACCESS_KEY*",
+];
+
+/// Settings for recording the environment variables runs are started with.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Environment {
+    /// Whether to record the environment at all (also enabled by `--capture-env`).
+    pub capture: bool,
+    /// Glob patterns for variables whose values are replaced by `***` when recorded, on top of
+    /// [`DEFAULT_MASK`].
+    pub mask: Vec<String>,
+    /// If given, glob patterns for the only variables whose values are recorded as-is; the values
+    /// of all others are masked.
+    pub allow: Option<Vec<String>>,
+}
+
+impl Environment {
+    /// Get the current environment, as it should be recorded (i.e. with sensitive values masked).
+    /// Names are matched case-insensitively, as e.g. `github_token` is just as secret.
+    pub fn capture(&self) -> Result<BTreeMap<String, String>> {
+        let compile = |patterns: Vec<&str>| {
+            patterns
+                .into_iter()
+                .map(glob::Pattern::new)
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .with_context(|| "Invalid pattern in `environment` in the configuration")
+        };
+        let mask = compile(
+            DEFAULT_MASK
+                .into_iter()
+                .chain(self.mask.iter().map(String::as_str))
+                .collect(),
+        )?;
+        let allow = self
+            .allow
+            .as_ref()
+            .map(|allow| compile(allow.iter().map(String::as_str).collect()))
+            .transpose()?;
+        let options = glob::MatchOptions {
+            case_sensitive: false,
+            ..Default::default()
+        };
+        let matches = |patterns: &[glob::Pattern], name: &str| {
+            patterns.iter().any(|p| p.matches_with(name, options))
+        };
+
+        Ok(std::env::vars_os()
+            .map(|(name, value)| {
+                let name = name.to_string_lossy().into_owned();
+                let is_masked = matches(&mask, &name)
+                    || allow.as_ref().is_some_and(|allow| !matches(allow, &name));
+                let value = match is_masked {
+                    true => MASKED_VALUE.to_string(),
+                    false => value.to_string_lossy().into_owned(),
+                };
+                (name, value)
+            })
+            .collect())
+    }
+}
+
+/// Settings for encrypting runs' output at rest.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Encryption {
+    /// Whether to encrypt the output of all runs (as if they were started with `--encrypt`).
+    pub always: bool,
+    /// Where the key is kept; by default, `key` in rum's configuration directory. It is generated
+    /// the first time it is needed.
+    pub key_file: Option<PathBuf>,
+}
+
+impl Encryption {
+    pub fn key_file(&self) -> Result<PathBuf> {
+        match &self.key_file {
+            Some(key_file) => Ok(key_file.clone()),
+            None => Ok(config_directory()?.join("key")),
+        }
+    }
+
+    /// Get the key to decrypt the output of a run with, if it was encrypted.
+    pub fn key_for(&self, data: &RunData) -> Result<Option<Key>> {
+        match data.encrypted {
+            true => Key::load(&self.key_file()?).map(Some),
+            false => Ok(None),
+        }
+    }
+}
+
+/// An OpenTelemetry collector to send a span to for each finished run.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Telemetry {
+    /// The collector's OTLP/HTTP endpoint, e.g. `http://localhost:4318`.
+    pub endpoint: String,
+    /// Extra headers to send along, e.g. for authentication.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+}
+
+fn default_service_name() -> String {
+    "rum".to_string()
+}
+
+/// An S3-compatible bucket to upload runs' logs and metadata to.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Archive {
+    /// The storage's endpoint, e.g. `https://s3.eu-west-1.amazonaws.com` or
+    /// `http://localhost:9000`.
+    pub endpoint: String,
+    pub bucket: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    /// What to put before the keys of runs' objects, e.g. `rum/`.
+    #[serde(default)]
+    pub prefix: String,
+    /// The credentials to upload with; by default, those in `AWS_ACCESS_KEY_ID` and
+    /// `AWS_SECRET_ACCESS_KEY`.
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    /// Whether to upload runs as soon as they finish (rather than only with `rum -archive`).
+    #[serde(default = "default_true")]
+    pub on_finish: bool,
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Archive {
+    /// Get the access key ID and secret access key to upload with.
+    pub fn credentials(&self) -> Result<(String, String)> {
+        let get = |configured: &Option<String>, variable: &str| {
+            configured
+                .clone()
+                .or_else(|| std::env::var(variable).ok())
+                .ok_or_else(|| {
+                    Error::msg(format!(
+                        "No credentials for the archive (set `archive.{}` in the configuration, \
+                         or {})",
+                        variable.trim_start_matches("AWS_").to_lowercase(),
+                        variable
+                    ))
+                })
+        };
+        Ok((
+            get(&self.access_key_id, "AWS_ACCESS_KEY_ID")?,
+            get(&self.secret_access_key, "AWS_SECRET_ACCESS_KEY")?,
+        ))
+    }
+}
+
+/// An SMTP server to send an email through whenever a run finishes (or fails), with its summary
+/// and last lines of output.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Email {
+    /// The server's host name, e.g. `smtp.example.com`.
+    pub server: String,
+    /// By default 465 if `security` is `tls`, and 587 otherwise.
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: EmailSecurity,
+    /// The credentials to log in with, if the server needs them; the password is by default the
+    /// one in `RUM_SMTP_PASSWORD`.
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// Which runs to send emails about.
+    #[serde(default)]
+    pub when: EmailWhen,
+    /// How many of the last lines of runs' output to include.
+    #[serde(default = "default_email_lines")]
+    pub lines: usize,
+}
+
+fn default_email_lines() -> usize {
+    50
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmailSecurity {
+    /// Connect in plain text, and then switch to TLS with `STARTTLS`.
+    #[default]
+    Starttls,
+    /// Connect with TLS right away.
+    Tls,
+    /// Never encrypt anything (e.g. for a relay on the same machine).
+    None,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmailWhen {
+    /// Only for runs which didn't succeed.
+    #[default]
+    Failed,
+    /// For every run, once it finishes.
+    Finished,
+}
+
+impl Email {
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(match self.security {
+            EmailSecurity::Tls => 465,
+            EmailSecurity::Starttls | EmailSecurity::None => 587,
+        })
+    }
+
+    /// Get the password to log in with, if any.
+    pub fn password(&self) -> Option<String> {
+        self.password
+            .clone()
+            .or_else(|| std::env::var("RUM_SMTP_PASSWORD").ok())
+    }
+}
+
+/// Caps on how many runs can be running at once, so that e.g. one person's sweep doesn't take a
+/// shared machine over. Runs started from within other runs (e.g. a pipeline's steps) don't
+/// count, as their parent already does.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quota {
+    /// How many runs each user can have running at once.
+    pub per_user: Option<usize>,
+    /// How many runs can be running at once in each project, i.e. the git repository (or else
+    /// the directory) they are started from.
+    pub per_project: Option<usize>,
+    /// How many runs with the same label can be running at once (unlabeled runs aren't limited).
+    pub per_label: Option<usize>,
+    /// Limits for specific labels, instead of `per_label`.
+    pub labels: HashMap<String, usize>,
+    /// What to do with runs started while a quota is used up.
+    pub on_exceed: OnExceed,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnExceed {
+    /// Queue them until enough runs finish.
+    #[default]
+    Queue,
+    /// Refuse to start them.
+    Reject,
+}
+
+/// Rules for automatically removing old runs. Only finished runs are ever removed.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Retention {
+    /// How long to keep runs which succeeded, after they finish.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub succeeded: Option<Duration>,
+    /// How long to keep runs which didn't succeed, after they finish.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub failed: Option<Duration>,
+    /// How many runs to keep at most; the ones which finished the longest ago are removed first.
+    pub max_runs: Option<usize>,
+    /// How long to keep removed runs in the trash (from where `rum -restore` can bring them
+    /// back); a week by default.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub trash: Option<Duration>,
+}
+
+/// How long removed runs are kept in the trash, unless configured otherwise.
+const DEFAULT_TRASH_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+impl Retention {
+    pub fn trash(&self) -> Duration {
+        self.trash.unwrap_or(DEFAULT_TRASH_RETENTION)
+    }
+}
+
+fn deserialize_duration<'de, D>(deserializer: D) -> std::result::Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|duration| parse_duration(&duration).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AutoLabel {
+    /// Leave runs unlabeled.
+    #[default]
+    None,
+    /// Use the name of the current directory.
+    Directory,
+    /// Use the name of the git repository containing the current directory (or the name of the
+    /// current directory, if it is not in a git repository).
+    Git,
+}
+
+pub fn config_directory() -> Result<PathBuf> {
+    Ok(
+        directories::ProjectDirs::from("com.github", "dccsillag", "rum")
+            .ok_or_else(|| Error::msg("Couldn't get project directories"))?
+            .config_dir()
+            .to_path_buf(),
+    )
+}
+
+/// Get the project `directory` is in: the git repository containing it, or else the directory
+/// itself.
+pub fn project_directory(directory: &Path) -> &Path {
+    directory
+        .ancestors()
+        .find(|d| d.join(".git").exists())
+        .unwrap_or(directory)
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        let config_file = config_directory()?.join("config.toml");
+        match std::fs::read_to_string(&config_file) {
+            Ok(contents) => toml::from_str(&contents)
+                .with_context(|| format!("Could not parse {:?}", &config_file)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Could not read {:?}", &config_file)),
+        }
+    }
+
+    /// Get the label for a run started from `directory` without an explicit label, if any.
+    pub fn auto_label_for(&self, directory: &Path) -> Option<String> {
+        let directory = match self.auto_label {
+            AutoLabel::None => return None,
+            AutoLabel::Directory => directory,
+            AutoLabel::Git => project_directory(directory),
+        };
+        directory
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(str::to_string)
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Error, Result};
+use nix::sys::resource::{getrlimit, setrlimit, Resource};
+
+/// Where the kernel is told what to do with core dumps.
+const CORE_PATTERN: &str = "/proc/sys/kernel/core_pattern";
+/// Whether the kernel appends the PID to the names of core files, if the pattern doesn't have it.
+const CORE_USES_PID: &str = "/proc/sys/kernel/core_uses_pid";
+
+/// How long to wait for systemd-coredump to have processed a core dump.
+const COREDUMPCTL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Let the current process (and whatever it starts from then on) dump core, as far as its hard
+/// limit allows.
+pub fn enable() -> nix::Result<()> {
+    let (_, hard) = getrlimit(Resource::RLIMIT_CORE)?;
+    setrlimit(Resource::RLIMIT_CORE, hard, hard)
+}
+
+/// Where the kernel sends core dumps.
+enum Destination {
+    /// Files named after a pattern, relative to the crashed process' working directory unless
+    /// absolute.
+    File(String),
+    /// The systemd journal, from where `coredumpctl` gets them.
+    SystemdCoredump,
+    /// Some other program, which we know nothing about.
+    Program(String),
+}
+
+fn destination() -> Result<Destination> {
+    let pattern = std::fs::read_to_string(CORE_PATTERN)
+        .with_context(|| format!("Could not read {}", CORE_PATTERN))?;
+    let pattern = pattern.trim_end_matches('\n');
+    Ok(match pattern.strip_prefix('|') {
+        Some(program) if program.contains("systemd-coredump") => Destination::SystemdCoredump,
+        Some(program) => Destination::Program(program.to_string()),
+        None => Destination::File(pattern.to_string()),
+    })
+}
+
+/// Check whether the core dumps of runs can be captured, so as to fail with a helpful message
+/// before even trying to start a run.
+pub fn check() -> Result<()> {
+    match destination()? {
+        Destination::File(_) => Ok(()),
+        Destination::SystemdCoredump => {
+            let is_installed = std::env::var_os("PATH").is_some_and(|path| {
+                std::env::split_paths(&path)
+                    .any(|directory| directory.join("coredumpctl").is_file())
+            });
+            match is_installed {
+                true => Ok(()),
+                false => Err(Error::msg(
+                    "Core dumps go to systemd-coredump, but `coredumpctl` isn't installed to get \
+                     them from there",
+                )),
+            }
+        }
+        Destination::Program(program) => Err(Error::msg(format!(
+            "Core dumps are piped to `{}` (see {}), from where rum can't get them",
+            program, CORE_PATTERN
+        ))),
+    }
+}
+
+/// Move the core dump of process `pid`, which was running in `directory` and crashed after
+/// `since`, to `destination`.
+pub fn capture(pid: u32, directory: &Path, since: SystemTime, destination: &Path) -> Result<()> {
+    match self::destination()? {
+        Destination::File(pattern) => {
+            let core_file = find_core_file(&pattern, pid, directory, since)?;
+            // Core files can be huge, so better not to copy them unless we have to
+            if std::fs::rename(&core_file, destination).is_err() {
+                std::fs::copy(&core_file, destination)
+                    .with_context(|| format!("Could not copy {:?}", core_file))?;
+                std::fs::remove_file(&core_file)?;
+            }
+            Ok(())
+        }
+        Destination::SystemdCoredump => {
+            // The core dump only shows up once systemd-coredump is done with it
+            let deadline = SystemTime::now() + COREDUMPCTL_TIMEOUT;
+            loop {
+                let status = Command::new("coredumpctl")
+                    .arg("dump")
+                    .arg("--quiet")
+                    .arg("--output")
+                    .arg(destination)
+                    .arg(pid.to_string())
+                    .stdout(std::process::Stdio::null())
+                    .stderr(std::process::Stdio::null())
+                    .status()?;
+                if status.success() {
+                    return Ok(());
+                }
+                if SystemTime::now() > deadline {
+                    return Err(Error::msg("coredumpctl has no core dump for the run"));
+                }
+                std::thread::sleep(Duration::from_secs(1));
+            }
+        }
+        Destination::Program(program) => Err(Error::msg(format!(
+            "Core dumps are piped to `{}`, from where rum can't get them",
+            program
+        ))),
+    }
+}
+
+/// Find the newest file, written since `since`, which the kernel could have named after `pattern`
+/// when process `pid` dumped core (see `core(5)`).
+fn find_core_file(pattern: &str, pid: u32, directory: &Path, since: SystemTime) -> Result<PathBuf> {
+    let mut glob = String::new();
+    let mut has_pid = false;
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => match chars.next() {
+                Some('%') => glob.push_str(&glob::Pattern::escape("%")),
+                // The ID of the process (or of its thread which crashed, which is the same for
+                // the main thread)
+                Some('p' | 'P' | 'i' | 'I') => {
+                    glob.push_str(&pid.to_string());
+                    has_pid = true;
+                }
+                // The program's name, the time, the host name and such could be anything
+                _ => glob.push('*'),
+            },
+            c => glob.push_str(&glob::Pattern::escape(&c.to_string())),
+        }
+    }
+    let uses_pid = std::fs::read_to_string(CORE_USES_PID).is_ok_and(|value| value.trim() != "0");
+    if uses_pid && !has_pid {
+        glob.push_str(&format!(".{pid}"));
+    }
+    if !pattern.starts_with('/') {
+        glob = format!(
+            "{}/{}",
+            glob::Pattern::escape(&directory.to_string_lossy()),
+            glob
+        );
+    }
+
+    glob::glob(&glob)?
+        .filter_map(|path| path.ok())
+        .filter_map(|path| {
+            let modified = std::fs::metadata(&path).ok()?.modified().ok()?;
+            (modified >= since).then_some((modified, path))
+        })
+        .max()
+        .map(|(_, path)| path)
+        .ok_or_else(|| Error::msg(format!("Found no core file matching {}", glob)))
+}
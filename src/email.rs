@@ -0,0 +1,280 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Context, Error, Result};
+use base64::Engine;
+use chrono::Utc;
+use rustls::{
+    crypto::ring, pki_types::ServerName, ClientConfig, ClientConnection, RootCertStore, StreamOwned,
+};
+
+use crate::{
+    config::{Email, EmailSecurity, EmailWhen},
+    encryption::Key,
+    runs::{Run, RunData, RunDataState},
+    utils::{format_datetime, format_duration},
+};
+
+/// How long to wait for the server before giving up.
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longer lines (in bytes) are cut, as SMTP only allows lines of up to 1000 bytes, counting the
+/// line break, the dot added to lines starting with one, and the `...` marking the cut.
+const MAX_LINE_LENGTH: usize = 990;
+
+/// Whether an email should be sent about a finished run.
+pub fn should_send(email: &Email, data: &RunData) -> bool {
+    match email.when {
+        EmailWhen::Finished => true,
+        EmailWhen::Failed => !data.state.is_success(),
+    }
+}
+
+/// Send an email about a finished run, with its summary and its last lines of output (unless it
+/// is encrypted, in which case `key` is what the output is encrypted with, which we'd rather not
+/// send in plain text to wherever).
+pub fn send_run_summary(email: &Email, run: &Run, data: &RunData, key: Option<&Key>) -> Result<()> {
+    let RunDataState::Done {
+        exit_code,
+        end_datetime,
+        ..
+    } = data.state
+    else {
+        return Ok(());
+    };
+
+    let host = host_name();
+    let what = data
+        .label
+        .clone()
+        .unwrap_or_else(|| shell_words::join(&data.command));
+    let subject = format!("[rum] {} on {}: {}", data.state.status(), host, what);
+
+    let mut body = vec![
+        run.summarize(data),
+        String::new(),
+        format!("Run:       {}", run.id),
+        format!("Command:   {}", shell_words::join(&data.command)),
+        format!(
+            "Directory: {}",
+            data.working_directory
+                .as_ref()
+                .map_or("?".into(), |directory| directory.to_string_lossy())
+        ),
+        format!("Host:      {}", host),
+        format!("Started:   {}", format_datetime(data.start_datetime)),
+        format!("Finished:  {}", format_datetime(end_datetime)),
+        format!(
+            "Took:      {}",
+            format_duration(end_datetime - data.start_datetime)
+        ),
+        format!("Exit code: {}", exit_code),
+        String::new(),
+    ];
+    match key {
+        Some(_) => body.push("(The output is encrypted, so it isn't included.)".to_string()),
+        None => {
+            let lines = run.read_last_lines(email.lines, None).unwrap_or_default();
+            body.push(format!("Last {} lines of output:", lines.len()));
+            body.push(String::new());
+            body.extend(lines.iter().map(|line| {
+                // What a terminal would show of lines rewritten with carriage returns (e.g.
+                // progress bars) is what comes after the last of them
+                line.trim_end_matches('\r')
+                    .rsplit('\r')
+                    .next()
+                    .unwrap()
+                    .to_string()
+            }));
+        }
+    }
+
+    send(email, &subject, &body)
+}
+
+fn host_name() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|name| name.trim().to_string())
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+/// A connection to an SMTP server, which may have switched to TLS.
+enum Stream {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.read(buf),
+            Stream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.write(buf),
+            Stream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Stream::Plain(stream) => stream.flush(),
+            Stream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+fn start_tls(server: &str, stream: TcpStream) -> Result<Stream> {
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let name = ServerName::try_from(server.to_string())
+        .map_err(|_| Error::msg(format!("Invalid SMTP server name: {}", server)))?;
+    let connection = ClientConnection::new(Arc::new(config), name)?;
+    Ok(Stream::Tls(Box::new(StreamOwned::new(connection, stream))))
+}
+
+struct Connection(BufReader<Stream>);
+
+impl Connection {
+    /// Read the server's reply, failing unless its code is `expected`.
+    fn reply(&mut self, expected: u16) -> Result<String> {
+        let mut reply = String::new();
+        loop {
+            let mut line = String::new();
+            if self.0.read_line(&mut line)? == 0 {
+                return Err(Error::msg("The SMTP server closed the connection"));
+            }
+            reply.push_str(&line);
+            // The last line of a reply has a space after the code, the others a dash
+            if line.as_bytes().get(3) != Some(&b'-') {
+                break;
+            }
+        }
+        match reply.get(..3).and_then(|code| code.parse::<u16>().ok()) {
+            Some(code) if code == expected => Ok(reply),
+            _ => Err(Error::msg(format!(
+                "The SMTP server replied: {}",
+                reply.trim_end()
+            ))),
+        }
+    }
+
+    fn command(&mut self, command: &str, expected: u16) -> Result<String> {
+        let stream = self.0.get_mut();
+        stream.write_all(command.as_bytes())?;
+        stream.write_all(b"\r\n")?;
+        stream.flush()?;
+        self.reply(expected)
+    }
+}
+
+/// Send a plain text email, as configured.
+fn send(email: &Email, subject: &str, body: &[String]) -> Result<()> {
+    let stream = TcpStream::connect((email.server.as_str(), email.port()))
+        .with_context(|| format!("Could not connect to {}:{}", email.server, email.port()))?;
+    stream.set_read_timeout(Some(SMTP_TIMEOUT))?;
+    stream.set_write_timeout(Some(SMTP_TIMEOUT))?;
+    let stream = match email.security {
+        EmailSecurity::Tls => start_tls(&email.server, stream)?,
+        EmailSecurity::Starttls | EmailSecurity::None => Stream::Plain(stream),
+    };
+
+    let mut connection = Connection(BufReader::new(stream));
+    let ehlo = format!("EHLO {}", host_name());
+    connection.reply(220)?;
+    connection.command(&ehlo, 250)?;
+    if email.security == EmailSecurity::Starttls {
+        connection.command("STARTTLS", 220)?;
+        // Nothing is left unread in the buffer, as the server waits for the handshake
+        let Stream::Plain(stream) = connection.0.into_inner() else {
+            unreachable!()
+        };
+        connection = Connection(BufReader::new(start_tls(&email.server, stream)?));
+        connection.command(&ehlo, 250)?;
+    }
+    if let Some(username) = &email.username {
+        let password = email.password().ok_or_else(|| {
+            Error::msg(
+                "No password for the SMTP server (set `email.password` in the configuration, or \
+                 RUM_SMTP_PASSWORD)",
+            )
+        })?;
+        let credentials = base64::engine::general_purpose::STANDARD
+            .encode(format!("\0{}\0{}", username, password));
+        connection.command(&format!("AUTH PLAIN {}", credentials), 235)?;
+    }
+
+    connection.command(&format!("MAIL FROM:<{}>", email.from), 250)?;
+    for recipient in &email.to {
+        connection.command(&format!("RCPT TO:<{}>", recipient), 250)?;
+    }
+    connection.command("DATA", 354)?;
+    let mut message = vec![
+        format!("From: {}", email.from),
+        format!("To: {}", email.to.join(", ")),
+        format!("Subject: {}", encode_header(subject)),
+        format!("Date: {}", Utc::now().to_rfc2822()),
+        "MIME-Version: 1.0".to_string(),
+        "Content-Type: text/plain; charset=utf-8".to_string(),
+        "Content-Transfer-Encoding: 8bit".to_string(),
+        String::new(),
+    ];
+    // Line breaks within lines (e.g. in a command's arguments) are made into proper ones, and
+    // lines starting with a dot get another, so that none is mistaken for the end of the message
+    message.extend(
+        body.iter()
+            .flat_map(|line| line.split(['\r', '\n']))
+            .map(|line| {
+                let line = truncate(line);
+                match line.starts_with('.') {
+                    true => format!(".{}", line),
+                    false => line,
+                }
+            }),
+    );
+    message.push(".".to_string());
+    connection.command(&message.join("\r\n"), 250)?;
+    let _ = connection.command("QUIT", 221);
+    Ok(())
+}
+
+/// Cut `line` to at most [`MAX_LINE_LENGTH`] bytes (without splitting a character).
+fn truncate(line: &str) -> String {
+    if line.len() <= MAX_LINE_LENGTH {
+        return line.to_string();
+    }
+    let mut end = MAX_LINE_LENGTH;
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &line[..end])
+}
+
+/// Encode a header's value so that it can have non-ASCII characters (see RFC 2047). Control
+/// characters become spaces, so that e.g. a line break in a label can't add headers of its own.
+fn encode_header(value: &str) -> String {
+    let value = value.replace(|c: char| c.is_control(), " ");
+    match value.is_ascii() {
+        true => value,
+        false => format!(
+            "=?utf-8?b?{}?=",
+            base64::engine::general_purpose::STANDARD.encode(value)
+        ),
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    io::{Read, Seek, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Error, Result};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+
+/// The size of the header of each frame: the length of its ciphertext, and its nonce.
+const HEADER_SIZE: usize = 4 + 24;
+
+/// The size of the part of the nonces which is the same for all frames of a file; the rest is the
+/// frame's index (4 bytes) and whether it is the last frame (1 byte), as in the STREAM
+/// construction, so that frames can't be dropped, reordered, duplicated or moved between files
+/// without decryption failing.
+const PREFIX_SIZE: usize = 24 - 4 - 1;
+
+fn frame_nonce(prefix: &[u8; PREFIX_SIZE], index: u32, last: bool) -> XNonce {
+    let mut nonce = XNonce::default();
+    nonce[..PREFIX_SIZE].copy_from_slice(prefix);
+    nonce[PREFIX_SIZE..PREFIX_SIZE + 4].copy_from_slice(&index.to_be_bytes());
+    nonce[PREFIX_SIZE + 4] = last.into();
+    nonce
+}
+
+/// A key to encrypt runs' output with.
+#[derive(Clone)]
+pub struct Key(chacha20poly1305::Key);
+
+impl std::fmt::Debug for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Keep the key out of error messages and such
+        f.write_str("Key(..)")
+    }
+}
+
+impl Key {
+    /// Read the key from `path`, where it is stored hex-encoded.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read the encryption key from {:?}", path))?;
+        let invalid = || Error::msg(format!("Invalid encryption key in {:?}", path));
+        let contents = contents.trim();
+        if contents.len() != 64 || !contents.is_ascii() {
+            return Err(invalid());
+        }
+        let bytes = (0..32)
+            .map(|i| u8::from_str_radix(&contents[2 * i..2 * i + 2], 16))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+        Ok(Self(*chacha20poly1305::Key::from_slice(&bytes)))
+    }
+
+    /// Read the key from `path`, generating a new one (readable only by the current user) if
+    /// there is none yet.
+    pub fn load_or_generate(path: &Path) -> Result<Self> {
+        if path.exists() {
+            return Self::load(path);
+        }
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        let key = XChaCha20Poly1305::generate_key(&mut OsRng);
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)
+            .with_context(|| format!("Could not create the encryption key in {:?}", path))?;
+        let hex = key.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        writeln!(file, "{}", hex)?;
+        Ok(Self(key))
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&self.0)
+    }
+}
+
+/// Encrypts whatever is written to it into a sequence of frames (one per write), so that it can
+/// be decrypted while it is still being written. Once dropped, it writes an empty last frame, so
+/// that a file which was cut short can be told apart from a complete one.
+pub struct Encryptor<W: Write> {
+    inner: W,
+    cipher: XChaCha20Poly1305,
+    prefix: [u8; PREFIX_SIZE],
+    index: u32,
+}
+
+impl<W: Write> Encryptor<W> {
+    pub fn new(inner: W, key: &Key) -> Self {
+        let mut prefix = [0; PREFIX_SIZE];
+        OsRng.fill_bytes(&mut prefix);
+        Self {
+            inner,
+            cipher: key.cipher(),
+            prefix,
+            index: 0,
+        }
+    }
+
+    fn write_frame(&mut self, plaintext: &[u8], last: bool) -> std::io::Result<()> {
+        let nonce = frame_nonce(&self.prefix, self.index, last);
+        self.index = self
+            .index
+            .checked_add(1)
+            .ok_or_else(|| std::io::Error::other("Too much output to encrypt"))?;
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| std::io::Error::other("Could not encrypt output"))?;
+        let mut frame = Vec::with_capacity(HEADER_SIZE + ciphertext.len());
+        frame.extend_from_slice(&(ciphertext.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&nonce);
+        frame.extend_from_slice(&ciphertext);
+        // Frames must be written whole, or the rest of the file couldn't be decrypted
+        self.inner.write_all(&frame)
+    }
+}
+
+impl<W: Write> Write for Encryptor<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.write_frame(buf, false)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for Encryptor<W> {
+    fn drop(&mut self) {
+        // Without the last frame, the output is reported as truncated, which it then likely is
+        let _ = self.write_frame(&[], true);
+        let _ = self.inner.flush();
+    }
+}
+
+/// Decrypts the frames of a file, checking that they come in the order they were written in.
+struct Decryptor {
+    cipher: XChaCha20Poly1305,
+    prefix: Option<[u8; PREFIX_SIZE]>,
+    index: u32,
+    /// Whether the last frame was decrypted.
+    finished: bool,
+}
+
+impl Decryptor {
+    fn new(key: &Key) -> Self {
+        Self {
+            cipher: key.cipher(),
+            prefix: None,
+            index: 0,
+            finished: false,
+        }
+    }
+
+    /// Decrypt the complete frames at the start of `bytes`; returns the plaintext, along with how
+    /// many bytes were decrypted.
+    fn decrypt_frames(&mut self, bytes: &[u8]) -> Result<(Vec<u8>, usize)> {
+        let mut plaintext = Vec::new();
+        let mut position = 0;
+        while bytes.len() - position >= HEADER_SIZE {
+            if self.finished {
+                return Err(Error::msg(
+                    "Could not decrypt output (there is more of it after its end)",
+                ));
+            }
+            let header = &bytes[position..position + HEADER_SIZE];
+            let length = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+            let Some(ciphertext) =
+                bytes.get(position + HEADER_SIZE..position + HEADER_SIZE + length)
+            else {
+                break;
+            };
+            let prefix = *self
+                .prefix
+                .get_or_insert_with(|| header[4..4 + PREFIX_SIZE].try_into().unwrap());
+            let last = header[HEADER_SIZE - 1] == 1;
+            // Any other nonce than the expected one makes decryption fail, as would tampering
+            let nonce = frame_nonce(&prefix, self.index, last);
+            plaintext.extend(self.cipher.decrypt(&nonce, ciphertext).map_err(|_| {
+                Error::msg("Could not decrypt output (wrong key, or it was tampered with?)")
+            })?);
+            self.index += 1;
+            self.finished = last;
+            position += HEADER_SIZE + length;
+        }
+        Ok((plaintext, position))
+    }
+}
+
+fn truncated(path: &Path) -> Error {
+    Error::msg(format!(
+        "The encrypted output in {:?} ends early (it was cut short, or the run's monitor died)",
+        path
+    ))
+}
+
+/// Decrypt a whole file of frames. Unless the file may still be being written (`finished` being
+/// `false`), it has to end with its last frame.
+pub fn decrypt_file(path: &Path, key: &Key, finished: bool) -> Result<Vec<u8>> {
+    let bytes = std::fs::read(path).with_context(|| format!("Could not read {:?}", path))?;
+    let mut decryptor = Decryptor::new(key);
+    let (plaintext, decrypted) = decryptor.decrypt_frames(&bytes)?;
+    if finished && (!decryptor.finished || decrypted < bytes.len()) {
+        return Err(truncated(path));
+    }
+    Ok(plaintext)
+}
+
+/// Follow a file of frames as it is written, passing the decrypted text to `on_new_text`, until
+/// `on_iter` returns `true`. Once `is_finished` (i.e. nothing more will be written), the file has
+/// to end with its last frame.
+pub fn follow<F, G, H>(
+    path: PathBuf,
+    key: &Key,
+    poll_interval: Duration,
+    mut on_new_text: F,
+    mut on_iter: G,
+    mut is_finished: H,
+) -> Result<()>
+where
+    F: FnMut(&str) -> Result<()>,
+    G: FnMut() -> Result<bool>,
+    H: FnMut() -> bool,
+{
+    let mut decryptor = Decryptor::new(key);
+    let mut position = 0;
+    let mut pending = Vec::new();
+    loop {
+        // Checked before reading, so that the last frame can't be written in between
+        let finished = !decryptor.finished && is_finished();
+        if let Ok(mut file) = std::fs::File::open(&path) {
+            file.seek(std::io::SeekFrom::Start(position))?;
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            let (plaintext, decrypted) = decryptor.decrypt_frames(&bytes)?;
+            position += decrypted as u64;
+            if finished && !decryptor.finished {
+                return Err(truncated(&path));
+            }
+
+            pending.extend(plaintext);
+            let complete = match std::str::from_utf8(&pending) {
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                _ => pending.len(),
+            };
+            if complete > 0 {
+                on_new_text(&String::from_utf8_lossy(&pending[..complete]))?;
+                pending.drain(..complete);
+            }
+        }
+
+        if on_iter()? {
+            return Ok(());
+        }
+        std::thread::sleep(poll_interval.min(Duration::from_millis(100)));
+    }
+}
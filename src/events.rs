@@ -0,0 +1,169 @@
+use std::{
+    io::{BufRead, Write},
+    path::Path,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use nix::unistd::{getuid, User};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::runs::RunId;
+
+/// Something which happened to a run, as recorded in its journal (`events.jsonl` in its
+/// directory), so that one can tell e.g. whether a run which died was signalled, and by whom.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Created,
+    /// The run had to wait for a lock before starting.
+    Queued {
+        lock: String,
+    },
+    Started {
+        pid: i32,
+    },
+    /// An existing process was adopted as the run.
+    Adopted {
+        pid: i32,
+    },
+    /// The run's monitor died, and the run is being monitored again.
+    Readopted,
+    Signalled {
+        signal: String,
+    },
+    /// The run was cancelled while it was queued.
+    Cancelled,
+    Finished {
+        exit_code: i32,
+    },
+    /// The run was started again (by `rum -daemon`), as another run.
+    Retried {
+        as_run: RunId,
+    },
+    /// Someone tried to remove the run, but it hadn't finished yet.
+    RemoveRefused,
+    Removed,
+    Restored,
+    /// The run was deleted for good (e.g. by the retention policy).
+    Deleted,
+    /// The run's files were uploaded to the archive.
+    Archived {
+        location: String,
+    },
+}
+
+impl Event {
+    /// Whether the event is also recorded in the audit log (i.e. whether it is something someone
+    /// did to the run, rather than something which just happened to it).
+    pub fn is_audited(&self) -> bool {
+        !matches!(
+            self,
+            Event::Created | Event::Readopted | Event::Finished { .. }
+        )
+    }
+}
+
+impl std::fmt::Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Event::Created => write!(f, "created"),
+            Event::Queued { lock } => write!(f, "queued, waiting for lock '{lock}'"),
+            Event::Started { pid } => write!(f, "started (PID {pid})"),
+            Event::Adopted { pid } => write!(f, "adopted process {pid}"),
+            Event::Readopted => write!(f, "monitored again, after its monitor died"),
+            Event::Signalled { signal } => write!(f, "sent {signal}"),
+            Event::Cancelled => write!(f, "cancelled"),
+            Event::Finished { exit_code } => write!(f, "finished (exit code {exit_code})"),
+            Event::Retried { as_run } => write!(f, "retried as run '{as_run}'"),
+            Event::RemoveRefused => write!(f, "not removed, as it hadn't finished"),
+            Event::Removed => write!(f, "moved to the trash"),
+            Event::Restored => write!(f, "restored from the trash"),
+            Event::Deleted => write!(f, "deleted"),
+            Event::Archived { location } => write!(f, "archived to {location}"),
+        }
+    }
+}
+
+/// An entry in a run's journal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub datetime: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: Event,
+    /// The rum command which recorded the event (e.g. `rum -stop 5d1b`).
+    #[serde(default)]
+    pub by: Option<String>,
+    /// The user who ran that command.
+    #[serde(default)]
+    pub user: Option<String>,
+}
+
+impl Entry {
+    /// An entry for `event` happening now, caused by the current rum command.
+    pub fn now(event: Event) -> Self {
+        Self {
+            datetime: Utc::now(),
+            event,
+            by: Some(invocation()),
+            user: Some(current_user()),
+        }
+    }
+}
+
+/// An entry in the audit log (`audit.jsonl` in the data directory), which records what was done
+/// to all runs, and by whom.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub run: RunId,
+    #[serde(flatten)]
+    pub entry: Entry,
+}
+
+/// Get the name of the user running rum (or their UID, if they have no name).
+pub fn current_user() -> String {
+    let uid = getuid();
+    match User::from_uid(uid) {
+        Ok(Some(user)) => user.name,
+        _ => uid.to_string(),
+    }
+}
+
+/// Describe the rum command currently running, as whoever caused an event.
+pub fn invocation() -> String {
+    let mut args = std::env::args();
+    let program = args.next().map(|program| {
+        Path::new(&program)
+            .file_name()
+            .map_or(program.clone(), |name| name.to_string_lossy().into_owned())
+    });
+    shell_words::join(program.into_iter().chain(args))
+}
+
+/// Append an entry to the journal (or audit log) at `path`.
+pub fn append<T: Serialize>(path: &Path, entry: &T) -> Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    // A single write, so that entries written concurrently never get interleaved
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Read the journal (or audit log) at `path`, skipping entries which can't be parsed; a missing
+/// journal is an empty one.
+pub fn read<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(std::io::BufReader::new(file)
+        .lines()
+        .map_while(|line| line.ok())
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect())
+}
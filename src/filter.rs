@@ -0,0 +1,362 @@
+use anyhow::{Error, Result};
+use chrono::Utc;
+
+use crate::{
+    runs::{Run, RunData, RunDataState},
+    utils::{parse_duration, parse_size},
+};
+
+/// A filter over runs, e.g. `status=failed and label~sweep and started>2d`.
+///
+/// Filters are made of conditions of the form `<field><operator><value>`, combined with `and`,
+/// `or`, `not` and parentheses. The following fields are supported:
+///
+/// - `id`, `label`, `user` (who started the run), `command`: compared as text (`=`, `!=`, and
+///   `~`/`!~` for "contains");
+/// - `status`: one of `running`, `queued`, `stalled`, `finished`, `done`, `failed`, `killed`,
+///   `crashed` or `interrupted` (by the system shutting down);
+/// - `exit`: the exit code, compared as a number;
+/// - `started`, `finished`: how long ago the run started/finished, compared as a duration (so
+///   `started>2d` means "started more than two days ago");
+/// - `duration`: how long the run ran for (or has been running for), compared as a duration;
+/// - `size`: how much disk space the run takes up, compared as a size.
+#[derive(Debug, Clone)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Condition {
+        field: Field,
+        operator: Operator,
+        value: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Id,
+    Label,
+    User,
+    Command,
+    Status,
+    Exit,
+    Started,
+    Finished,
+    Duration,
+    Size,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Equal,
+    NotEqual,
+    Contains,
+    NotContains,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+const OPERATORS: [(&str, Operator); 8] = [
+    ("!=", Operator::NotEqual),
+    ("!~", Operator::NotContains),
+    (">=", Operator::GreaterOrEqual),
+    ("<=", Operator::LessOrEqual),
+    ("=", Operator::Equal),
+    ("~", Operator::Contains),
+    (">", Operator::Greater),
+    ("<", Operator::Less),
+];
+
+impl Filter {
+    pub fn parse(filter: &str) -> Result<Self> {
+        let tokens = tokenize(filter)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+        };
+        let filter = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(filter),
+            Some(token) => Err(Error::msg(format!("Unexpected '{}' in filter", token))),
+        }
+    }
+
+    pub fn matches(&self, run: &Run, data: &RunData) -> bool {
+        match self {
+            Filter::And(a, b) => a.matches(run, data) && b.matches(run, data),
+            Filter::Or(a, b) => a.matches(run, data) || b.matches(run, data),
+            Filter::Not(a) => !a.matches(run, data),
+            Filter::Condition {
+                field,
+                operator,
+                value,
+            } => condition_matches(*field, *operator, value, run, data),
+        }
+    }
+}
+
+fn condition_matches(
+    field: Field,
+    operator: Operator,
+    value: &str,
+    run: &Run,
+    data: &RunData,
+) -> bool {
+    let now = Utc::now();
+    let end_datetime = match data.state {
+        RunDataState::Done { end_datetime, .. } => Some(end_datetime),
+        RunDataState::Running { .. } | RunDataState::Queued { .. } => None,
+    };
+
+    match field {
+        Field::Id => compare_text(operator, &run.id, value, |id, prefix| id.starts_with(prefix)),
+        Field::Label => compare_text(
+            operator,
+            data.label.as_deref().unwrap_or(""),
+            value,
+            str::eq,
+        ),
+        Field::User => compare_text(
+            operator,
+            data.owner.as_deref().unwrap_or(""),
+            value,
+            str::eq,
+        ),
+        Field::Command => compare_text(operator, &shell_words::join(&data.command), value, str::eq),
+        Field::Status => {
+            let is = match (value, &data.state) {
+                ("running", RunDataState::Running { .. }) => true,
+                ("queued", RunDataState::Queued { .. }) => true,
+                ("stalled", _) => run.is_stalled(data),
+                ("finished", RunDataState::Done { .. }) => true,
+                ("done", state) => state.is_success(),
+                ("killed", RunDataState::Done { exit_code, .. }) => *exit_code == -1,
+                ("crashed", RunDataState::Done { exit_code, .. }) => *exit_code == -2,
+                ("interrupted", RunDataState::Done { exit_code, .. }) => *exit_code == -4,
+                ("failed", state) => state.is_failure(),
+                _ => false,
+            };
+            match operator {
+                Operator::NotEqual | Operator::NotContains => !is,
+                _ => is,
+            }
+        }
+        Field::Exit => match (&data.state, value.parse::<i64>()) {
+            (RunDataState::Done { exit_code, .. }, Ok(value)) => {
+                compare_ordered(operator, *exit_code as i64, value)
+            }
+            _ => false,
+        },
+        Field::Started => compare_duration(operator, now - data.start_datetime, value),
+        Field::Finished => match end_datetime {
+            Some(end_datetime) => compare_duration(operator, now - end_datetime, value),
+            None => false,
+        },
+        Field::Duration => compare_duration(
+            operator,
+            end_datetime.unwrap_or(now) - data.start_datetime,
+            value,
+        ),
+        Field::Size => match (run.get_disk_usage(), parse_size(value)) {
+            (Ok(size), Ok(value)) => compare_ordered(operator, size, value),
+            _ => false,
+        },
+    }
+}
+
+fn compare_text(operator: Operator, text: &str, value: &str, equal: fn(&str, &str) -> bool) -> bool {
+    match operator {
+        Operator::Equal => equal(text, value),
+        Operator::NotEqual => !equal(text, value),
+        Operator::Contains => text.contains(value),
+        Operator::NotContains => !text.contains(value),
+        _ => compare_ordered(operator, text, value),
+    }
+}
+
+fn compare_duration(operator: Operator, duration: chrono::Duration, value: &str) -> bool {
+    match parse_duration(value) {
+        Ok(value) => compare_ordered(operator, duration.to_std().unwrap_or_default(), value),
+        Err(_) => false,
+    }
+}
+
+fn compare_ordered<T: PartialOrd>(operator: Operator, a: T, b: T) -> bool {
+    match operator {
+        Operator::Equal | Operator::Contains => a == b,
+        Operator::NotEqual | Operator::NotContains => a != b,
+        Operator::Greater => a > b,
+        Operator::GreaterOrEqual => a >= b,
+        Operator::Less => a < b,
+        Operator::LessOrEqual => a <= b,
+    }
+}
+
+/// Split a filter into words (with quotes and backslashes working as in a shell) and parentheses,
+/// which needn't be surrounded by spaces, so that e.g. `(status=done or status=failed)` works (and
+/// values with parentheses in them have to be quoted).
+fn tokenize(filter: &str) -> Result<Vec<String>> {
+    let unterminated = || Error::msg("Missing closing quote in filter");
+    let mut tokens = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = filter.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' | ')' => {
+                tokens.extend(word.take());
+                tokens.push(c.to_string());
+            }
+            c if c.is_whitespace() => tokens.extend(word.take()),
+            '\\' => {
+                let escaped = chars
+                    .next()
+                    .ok_or_else(|| Error::msg("Unexpected '\\' at the end of filter"))?;
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or_else(unterminated)? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or_else(unterminated)? {
+                        '"' => break,
+                        '\\' => word.push(chars.next().ok_or_else(unterminated)?),
+                        c => word.push(c),
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    tokens.extend(word);
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [String],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<&str> {
+        let token = self.tokens.get(self.position).map(String::as_str);
+        self.position += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Filter> {
+        let mut filter = self.parse_and()?;
+        while self.peek() == Some("or") {
+            self.next();
+            filter = Filter::Or(Box::new(filter), Box::new(self.parse_and()?));
+        }
+        Ok(filter)
+    }
+
+    fn parse_and(&mut self) -> Result<Filter> {
+        let mut filter = self.parse_not()?;
+        while self.peek() == Some("and") {
+            self.next();
+            filter = Filter::And(Box::new(filter), Box::new(self.parse_not()?));
+        }
+        Ok(filter)
+    }
+
+    fn parse_not(&mut self) -> Result<Filter> {
+        if self.peek() == Some("not") {
+            self.next();
+            return Ok(Filter::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_atom()
+    }
+
+    fn parse_atom(&mut self) -> Result<Filter> {
+        match self.next() {
+            None => Err(Error::msg("Unexpected end of filter")),
+            Some("(") => {
+                let filter = self.parse_or()?;
+                match self.next() {
+                    Some(")") => Ok(filter),
+                    _ => Err(Error::msg("Expected ')' in filter")),
+                }
+            }
+            Some(condition) => parse_condition(condition),
+        }
+    }
+}
+
+fn parse_condition(condition: &str) -> Result<Filter> {
+    let operator_start = condition
+        .find(|c| "=!~<>".contains(c))
+        .ok_or_else(|| Error::msg(format!("Expected a condition, got '{}'", condition)))?;
+    let (field, rest) = condition.split_at(operator_start);
+    let (operator, value) = OPERATORS
+        .iter()
+        .find_map(|(symbol, operator)| rest.strip_prefix(symbol).map(|value| (*operator, value)))
+        .ok_or_else(|| Error::msg(format!("Invalid operator in '{}'", condition)))?;
+
+    let field = match field {
+        "id" => Field::Id,
+        "label" => Field::Label,
+        "user" => Field::User,
+        "command" => Field::Command,
+        "status" => Field::Status,
+        "exit" => Field::Exit,
+        "started" => Field::Started,
+        "finished" => Field::Finished,
+        "duration" => Field::Duration,
+        "size" => Field::Size,
+        _ => return Err(Error::msg(format!("Unknown field '{}' in filter", field))),
+    };
+
+    // Catch mistakes early rather than silently matching nothing
+    match field {
+        Field::Started | Field::Finished | Field::Duration => {
+            parse_duration(value)?;
+        }
+        Field::Size => {
+            parse_size(value)?;
+        }
+        Field::Exit => {
+            value
+                .parse::<i64>()
+                .map_err(|_| Error::msg(format!("Invalid exit code '{}'", value)))?;
+        }
+        Field::Status
+            if ![
+                "running",
+                "queued",
+                "stalled",
+                "finished",
+                "done",
+                "failed",
+                "killed",
+                "crashed",
+                "interrupted",
+            ]
+            .contains(&value) =>
+        {
+            return Err(Error::msg(format!("Unknown status '{}'", value)));
+        }
+        _ => (),
+    }
+
+    Ok(Filter::Condition {
+        field,
+        operator,
+        value: value.to_string(),
+    })
+}
@@ -0,0 +1,86 @@
+use std::{
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    process::{Child, Command, Stdio},
+};
+
+use anyhow::Result;
+
+use crate::runs::{Run, RunData, RunDataState};
+
+/// A point in a run's life at which a hook can be run, named after the hook's file in the hooks
+/// directory (`hooks` in rum's configuration directory).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    OnStart,
+    OnFinish,
+    /// Run (after `on_finish`) when a run finishes without succeeding.
+    OnFail,
+}
+
+impl Hook {
+    fn file_name(self) -> &'static str {
+        match self {
+            Hook::OnStart => "on_start",
+            Hook::OnFinish => "on_finish",
+            Hook::OnFail => "on_fail",
+        }
+    }
+
+    fn event(self) -> &'static str {
+        match self {
+            Hook::OnStart => "start",
+            Hook::OnFinish => "finish",
+            Hook::OnFail => "fail",
+        }
+    }
+}
+
+/// Start the hook in `directory` for `hook`, if there is one (and it is executable), giving it
+/// the details of `run` through the environment. Its output goes to `hooks.log` in the run's
+/// directory.
+pub fn start_hook(
+    directory: &Path,
+    hook: Hook,
+    run: &Run,
+    data: &RunData,
+) -> Result<Option<Child>> {
+    let path = directory.join(hook.file_name());
+    let is_executable = std::fs::metadata(&path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0);
+    if !is_executable {
+        return Ok(None);
+    }
+
+    let log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(run.get_hooks_log_file())?;
+    let exit_code = match data.state {
+        RunDataState::Done { exit_code, .. } => exit_code.to_string(),
+        _ => String::new(),
+    };
+    let child = Command::new(&path)
+        .env("RUM_EVENT", hook.event())
+        .env("RUM_RUN_ID", &run.id)
+        .env("RUM_RUN_NAME", data.name.as_deref().unwrap_or(""))
+        .env("RUM_RUN_DIRECTORY", &run.run_directory)
+        .env("RUM_OUTPUT_FILE", run.get_output_file())
+        .env("RUM_STATUS", data.state.status())
+        .env("RUM_EXIT_CODE", exit_code)
+        .env("RUM_LABEL", data.label.as_deref().unwrap_or(""))
+        .env("RUM_COMMAND", shell_words::join(&data.command))
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .spawn()?;
+    Ok(Some(child))
+}
+
+/// Run the hook for `hook`, if there is one, and wait for it to finish.
+pub fn run_hook(directory: &Path, hook: Hook, run: &Run, data: &RunData) -> Result<()> {
+    if let Some(mut child) = start_hook(directory, hook, run, data)? {
+        child.wait()?;
+    }
+    Ok(())
+}
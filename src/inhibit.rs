@@ -0,0 +1,53 @@
+use std::process::{Child, Command, Stdio};
+
+use anyhow::{Error, Result};
+
+use crate::runs::Run;
+
+/// Keeps the system from going to sleep (suspending, hibernating, or idling into either) for as
+/// long as it is held, through a logind inhibitor lock (see `systemd-inhibit`).
+///
+/// Each run holds its own, so the system stays awake as long as any run is running. The lock is
+/// released once the inhibitor is dropped, or if whoever holds it dies: `systemd-inhibit` holds it
+/// for as long as the command it runs, which reads from us until there's nothing left to read.
+pub struct SleepInhibitor {
+    process: Child,
+}
+
+/// Check whether sleep can be inhibited at all, so as to fail with a helpful message before even
+/// trying to start a run.
+pub fn check() -> Result<()> {
+    let is_installed = std::env::var_os("PATH").is_some_and(|path| {
+        std::env::split_paths(&path).any(|directory| directory.join("systemd-inhibit").is_file())
+    });
+    match is_installed {
+        true => Ok(()),
+        false => Err(Error::msg(
+            "--inhibit-sleep needs systemd (`systemd-inhibit`) to be installed",
+        )),
+    }
+}
+
+impl SleepInhibitor {
+    pub fn take(run: &Run) -> std::io::Result<Self> {
+        let process = Command::new("systemd-inhibit")
+            .arg("--what=sleep:idle")
+            .arg("--who=rum")
+            .arg(format!("--why=Run {} is running", &run.id[..8]))
+            .arg("--mode=block")
+            .arg("cat")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        Ok(Self { process })
+    }
+}
+
+impl Drop for SleepInhibitor {
+    fn drop(&mut self) {
+        // Closing its input makes `cat` exit, and the lock go with it
+        drop(self.process.stdin.take());
+        let _ = self.process.wait();
+    }
+}
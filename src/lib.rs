@@ -0,0 +1,27 @@
+pub mod actions;
+pub mod annotations;
+pub mod archive;
+pub mod config;
+pub mod core_dump;
+pub mod email;
+pub mod encryption;
+pub mod events;
+pub mod filter;
+pub mod hooks;
+pub mod inhibit;
+pub mod limits;
+pub mod metrics;
+pub mod output;
+pub mod pipeline;
+pub mod plugins;
+pub mod policy;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod quota;
+pub mod runs;
+pub mod sandbox;
+pub mod session;
+pub mod system_log;
+pub mod telemetry;
+pub mod theme;
+pub mod utils;
@@ -0,0 +1,136 @@
+use anyhow::{Error, Result};
+use nix::{
+    libc::RLIM_INFINITY,
+    sched::{sched_setaffinity, CpuSet},
+    sys::resource::Resource,
+    unistd::Pid,
+};
+use serde::{Deserialize, Serialize};
+
+/// The resource limits which can be set with `--ulimit`, by the names `ulimit` knows them by.
+const RESOURCES: [(&str, Resource); 13] = [
+    ("as", Resource::RLIMIT_AS),
+    ("core", Resource::RLIMIT_CORE),
+    ("cpu", Resource::RLIMIT_CPU),
+    ("data", Resource::RLIMIT_DATA),
+    ("fsize", Resource::RLIMIT_FSIZE),
+    ("locks", Resource::RLIMIT_LOCKS),
+    ("memlock", Resource::RLIMIT_MEMLOCK),
+    ("msgqueue", Resource::RLIMIT_MSGQUEUE),
+    ("nice", Resource::RLIMIT_NICE),
+    ("nofile", Resource::RLIMIT_NOFILE),
+    ("nproc", Resource::RLIMIT_NPROC),
+    ("rtprio", Resource::RLIMIT_RTPRIO),
+    ("stack", Resource::RLIMIT_STACK),
+];
+
+/// A resource limit to set for a run, e.g. `nofile=65536` or `core=0:unlimited`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ulimit {
+    pub name: String,
+    /// The soft limit, or `None` for no limit.
+    pub soft: Option<u64>,
+    /// The hard limit, or `None` for no limit.
+    pub hard: Option<u64>,
+}
+
+impl Ulimit {
+    /// Parse a limit of the form `<name>=<limit>` (setting both the soft and hard limits) or
+    /// `<name>=<soft>:<hard>`, where limits are numbers or `unlimited`.
+    pub fn parse(ulimit: &str) -> Result<Self> {
+        let (name, limits) = ulimit
+            .split_once('=')
+            .ok_or_else(|| Error::msg(format!("Expected <name>=<limit>, got '{}'", ulimit)))?;
+        if !RESOURCES.iter().any(|(known, _)| *known == name) {
+            return Err(Error::msg(format!(
+                "Unknown resource '{}' (expected one of: {})",
+                name,
+                RESOURCES.map(|(name, _)| name).join(", "),
+            )));
+        }
+        let parse_limit = |limit: &str| match limit {
+            "unlimited" => Ok(None),
+            limit => limit
+                .parse()
+                .map(Some)
+                .map_err(|_| Error::msg(format!("Invalid limit '{}'", limit))),
+        };
+        let (soft, hard) = match limits.split_once(':') {
+            Some((soft, hard)) => (parse_limit(soft)?, parse_limit(hard)?),
+            None => (parse_limit(limits)?, parse_limit(limits)?),
+        };
+        if let (Some(soft), Some(hard)) = (soft, hard) {
+            if soft > hard {
+                return Err(Error::msg(format!(
+                    "The soft limit can't be higher than the hard one in '{}'",
+                    ulimit
+                )));
+            }
+        }
+        Ok(Self {
+            name: name.to_string(),
+            soft,
+            hard,
+        })
+    }
+
+    /// Apply the limit to the current process (and thus to whatever it starts from then on).
+    pub fn apply(&self) -> nix::Result<()> {
+        let (_, resource) = RESOURCES
+            .iter()
+            .find(|(name, _)| *name == self.name)
+            .ok_or(nix::Error::EINVAL)?;
+        nix::sys::resource::setrlimit(
+            *resource,
+            self.soft.unwrap_or(RLIM_INFINITY),
+            self.hard.unwrap_or(RLIM_INFINITY),
+        )
+    }
+}
+
+/// Parse a file mode creation mask, given in octal (e.g. `022`).
+pub fn parse_umask(umask: &str) -> Result<u32> {
+    u32::from_str_radix(umask, 8)
+        .ok()
+        .filter(|umask| *umask <= 0o777)
+        .ok_or_else(|| Error::msg(format!("Invalid umask '{}' (expected e.g. 022)", umask)))
+}
+
+/// A set of CPUs to restrict a run to, e.g. `0-3,8,10-11`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CpuList(pub Vec<usize>);
+
+impl CpuList {
+    /// Parse a list of CPUs, as in `/sys/devices/system/cpu/online` (e.g. `0-3,8,10-11`).
+    pub fn parse(cpus: &str) -> Result<Self> {
+        let invalid = || Error::msg(format!("Invalid CPU list '{}' (expected e.g. 0-3,8)", cpus));
+        let mut list = Vec::new();
+        for range in cpus.split(',') {
+            let (first, last) = match range.split_once('-') {
+                Some((first, last)) => (first, last),
+                None => (range, range),
+            };
+            let (first, last): (usize, usize) = (
+                first.trim().parse().map_err(|_| invalid())?,
+                last.trim().parse().map_err(|_| invalid())?,
+            );
+            if first > last || last >= CpuSet::count() {
+                return Err(invalid());
+            }
+            list.extend(first..=last);
+        }
+        list.sort_unstable();
+        list.dedup();
+        Ok(Self(list))
+    }
+
+    /// Restrict the current process (and thus whatever it starts from then on) to these CPUs.
+    pub fn apply(&self) -> nix::Result<()> {
+        let mut cpu_set = CpuSet::new();
+        for cpu in &self.0 {
+            cpu_set.set(*cpu)?;
+        }
+        sched_setaffinity(Pid::from_raw(0), &cpu_set)
+    }
+}
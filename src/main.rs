@@ -1,12 +1,22 @@
-pub mod actions;
-pub mod runs;
-pub mod utils;
+use std::{path::PathBuf, time::Duration};
 
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{ColorChoice, CommandFactory, Parser};
 use nix::sys::signal;
 
-use runs::Runs;
+use rum::{
+    actions::{
+        self, compare::CompareOptions, list::ListOptions, open::ViewOptions, remove::RemoveOptions,
+        show_path::RunPath,
+    },
+    annotations::Annotate,
+    config::Config,
+    filter::Filter,
+    plugins,
+    runs::{Run, RunDataState, Runs, StartOptions},
+    theme,
+    utils::{self, parse_duration, DatetimeFormat},
+};
 
 #[derive(Parser)]
 #[clap(
@@ -18,6 +28,30 @@ use runs::Runs;
 struct Args {
     #[clap(subcommand)]
     subcommand: Subcommand,
+
+    /// Use the global store, even if there is a project-local one (a `.rum` directory in the
+    /// current directory or one of its ancestors)
+    #[clap(long, short = 'G', global = true)]
+    global: bool,
+
+    /// When to use colors: `auto` uses them only when writing to a terminal (and `NO_COLOR` isn't
+    /// set)
+    #[clap(
+        long,
+        value_enum,
+        value_name = "WHEN",
+        default_value = "auto",
+        global = true
+    )]
+    color: ColorChoice,
+
+    /// How to show dates and times: long, iso, short, relative, or a strftime format (e.g.
+    /// '%d/%m %H:%M')
+    #[clap(long, value_name = "FORMAT", global = true)]
+    datetime_format: Option<DatetimeFormat>,
+
+    #[clap(flatten, next_help_heading = "Start options")]
+    start_options: StartOptions,
 }
 
 #[derive(Parser)]
@@ -29,7 +63,10 @@ enum Subcommand {
         long_flag = "list",
         display_order = 0
     )]
-    List,
+    List {
+        #[clap(flatten)]
+        options: ListOptions,
+    },
 
     /// Show information about a run
     #[clap(
@@ -39,8 +76,15 @@ enum Subcommand {
         display_order = 1
     )]
     Info {
-        /// Which run to show information on
-        run: String,
+        /// Which runs to show information on (picked interactively if not given)
+        runs: Vec<String>,
+        /// Also show information on all runs matching this filter
+        #[clap(long = "where", value_name = "FILTER", value_parser = Filter::parse)]
+        filter: Option<Filter>,
+        /// Annotate the CI log the information goes to, with a group for each run and an error
+        /// or warning for each run which failed or is unhealthy
+        #[clap(long, value_name = "FORMAT")]
+        annotate: Option<Annotate>,
     },
 
     /// View a run
@@ -51,8 +95,11 @@ enum Subcommand {
         display_order = 2
     )]
     View {
-        /// Which run to view
-        run: String,
+        /// Which run to view (picked interactively if not given)
+        run: Option<String>,
+
+        #[clap(flatten)]
+        options: ViewOptions,
     },
 
     /// Remove a run
@@ -63,11 +110,13 @@ enum Subcommand {
         display_order = 3
     )]
     Remove {
-        /// Which runs to remove
+        /// Which runs to remove (picked interactively if not given)
         runs: Vec<String>,
-        /// Do not prompt for confirmation of whether to remove the runs.
-        #[clap(long)]
-        no_confirm: bool,
+        /// Also remove all runs matching this filter
+        #[clap(long = "where", value_name = "FILTER", value_parser = Filter::parse)]
+        filter: Option<Filter>,
+        #[clap(flatten)]
+        options: RemoveOptions,
     },
 
     /// Interrupt (SIGINT, i.e., Ctrl+C) a run
@@ -78,8 +127,17 @@ enum Subcommand {
         display_order = 4
     )]
     Interrupt {
-        /// Which run to interrupt
-        run: String,
+        /// Which run to interrupt (picked interactively if not given)
+        run: Option<String>,
+        /// Interrupt all running runs matching this filter
+        #[clap(long = "where", value_name = "FILTER", value_parser = Filter::parse, conflicts_with = "run")]
+        filter: Option<Filter>,
+        /// Also signal processes which left the run's process group (e.g. daemonized children)
+        #[clap(long)]
+        tree: bool,
+        /// Also signal runs which belong to other users
+        #[clap(long)]
+        force: bool,
     },
 
     /// Terminate (SIGTERM, i.e., kill <PID>) a run
@@ -90,8 +148,17 @@ enum Subcommand {
         display_order = 5
     )]
     Terminate {
-        /// Which run to terminate
-        run: String,
+        /// Which run to terminate (picked interactively if not given)
+        run: Option<String>,
+        /// Terminate all running runs matching this filter
+        #[clap(long = "where", value_name = "FILTER", value_parser = Filter::parse, conflicts_with = "run")]
+        filter: Option<Filter>,
+        /// Also signal processes which left the run's process group (e.g. daemonized children)
+        #[clap(long)]
+        tree: bool,
+        /// Also signal runs which belong to other users
+        #[clap(long)]
+        force: bool,
     },
 
     /// Kill (SIGKILL, i.e., kill -9 <PID>) a run
@@ -102,38 +169,630 @@ enum Subcommand {
         display_order = 6
     )]
     Kill {
-        /// Which run to kill
-        run: String,
+        /// Which run to kill (picked interactively if not given)
+        run: Option<String>,
+        /// Kill all running runs matching this filter
+        #[clap(long = "where", value_name = "FILTER", value_parser = Filter::parse, conflicts_with = "run")]
+        filter: Option<Filter>,
+        /// Also signal processes which left the run's process group (e.g. daemonized children)
+        #[clap(long)]
+        tree: bool,
+        /// Also signal runs which belong to other users
+        #[clap(long)]
+        force: bool,
+    },
+
+    /// Print the path to a run's directory or files
+    #[clap(name = "-path", long_flag = "path", display_order = 7)]
+    Path {
+        /// Which run to print the path of (picked interactively if not given)
+        run: Option<String>,
+        /// Print the path to the run's directory (default)
+        #[clap(long, group = "which")]
+        dir: bool,
+        /// Print the path to the run's output log
+        #[clap(long, group = "which")]
+        output: bool,
+        /// Print the path to the run's data file
+        #[clap(long, group = "which")]
+        data: bool,
+    },
+
+    /// Continuously show running runs and the resources they are using
+    #[clap(name = "-top", long_flag = "top", display_order = 8)]
+    Top {
+        /// How often to refresh
+        #[clap(long, value_name = "DURATION", value_parser = parse_duration, default_value = "2s")]
+        interval: Duration,
+    },
+
+    /// Start tracking an already-running process as a run
+    #[clap(name = "-adopt", long_flag = "adopt", display_order = 9)]
+    Adopt {
+        /// The PID of the process to adopt
+        pid: i32,
+        /// A log file the process writes its output to
+        #[clap(long)]
+        output: Option<PathBuf>,
+        /// A label for the run
+        #[clap(long)]
+        label: Option<String>,
+    },
+
+    /// Resume monitoring a run whose monitor process died
+    #[clap(name = "-readopt", long_flag = "readopt", display_order = 10)]
+    Readopt {
+        /// The run to monitor again
+        run: Option<String>,
+    },
+
+    /// List past commands, most recent first
+    #[clap(name = "-history", long_flag = "history", display_order = 11)]
+    History {
+        /// Only list commands containing this
+        pattern: Option<String>,
+    },
+
+    /// Start the most recently run command again
+    #[clap(name = "-again", long_flag = "again", display_order = 12)]
+    Again {
+        /// Start the most recently run command containing this, instead
+        pattern: Option<String>,
+    },
+
+    /// Start a new run with the same command, label, environment and directory as another one,
+    /// e.g. `rum -clone <run> -- --lr 0.01`
+    #[clap(name = "-clone", long_flag = "clone", display_order = 33)]
+    Clone {
+        /// Which run to clone (picked interactively if not given)
+        run: Option<String>,
+        /// Arguments to give the command: those it already has (e.g. `--lr 0.1`, `--lr=0.1` or
+        /// `lr=0.1`) get the new values, others are appended
+        #[clap(last = true)]
+        args: Vec<String>,
+    },
+
+    /// Remove old runs now, according to the configured retention policy
+    #[clap(name = "-prune", long_flag = "prune", display_order = 13)]
+    Prune {
+        /// Only show which runs would be removed, without removing anything
+        #[clap(long)]
+        dry_run: bool,
+        /// Also remove runs which belong to other users
+        #[clap(long)]
+        force: bool,
+    },
+
+    /// Restore a removed run from the trash (or list the runs in it, if no run is given)
+    #[clap(name = "-restore", long_flag = "restore", display_order = 21)]
+    Restore {
+        /// Which run to restore
+        run: Option<String>,
+    },
+
+    /// Plot the resources used by a run over time
+    #[clap(name = "-plot", long_flag = "plot", display_order = 14)]
+    Plot {
+        /// The run to plot
+        run: Option<String>,
+    },
+
+    /// Compare the runs with a given label, e.g. those of a parameter sweep
+    #[clap(name = "-compare", long_flag = "compare", display_order = 15)]
+    Compare {
+        /// The label of the runs to compare
+        group: String,
+        #[clap(flatten)]
+        options: CompareOptions,
+    },
+
+    /// Export the runs' metadata as CSV
+    #[clap(name = "-export-csv", long_flag = "export-csv", display_order = 16)]
+    ExportCsv {
+        /// Only export runs matching this filter (e.g. `status=failed and started<1d`)
+        #[clap(long = "where", value_name = "FILTER", value_parser = Filter::parse)]
+        filter: Option<Filter>,
+    },
+
+    /// Export the runs' results as a JUnit XML report, e.g. for CI systems to show
+    #[clap(name = "-export-junit", long_flag = "export-junit", display_order = 31)]
+    ExportJunit {
+        /// Only export the runs started from within this one (e.g. a pipeline's steps)
+        run: Option<String>,
+        /// Only export runs matching this filter (e.g. `label=sweep and started<1d`)
+        #[clap(long = "where", value_name = "FILTER", value_parser = Filter::parse)]
+        filter: Option<Filter>,
+        /// How many of the last lines of failed runs' output to include
+        #[clap(long, default_value_t = 50)]
+        lines: usize,
+    },
+
+    /// Keep an eye on runs, applying the watchdog and retention policies
+    #[clap(name = "-daemon", long_flag = "daemon", display_order = 17)]
+    Daemon {
+        /// How often to check on the runs
+        #[clap(long, value_name = "DURATION", value_parser = parse_duration, default_value = "30s")]
+        interval: Duration,
+        /// Start the runs which were still queued when the system shut down again
+        #[clap(long)]
+        resume: bool,
+        /// Print a systemd user unit running the daemon (with these options) instead, e.g. to save
+        /// as `~/.config/systemd/user/rum.service`
+        #[clap(long)]
+        systemd_unit: bool,
+    },
+
+    /// Stop a run, escalating from SIGINT to SIGTERM to SIGKILL until it stops
+    #[clap(name = "-stop", long_flag = "stop", display_order = 18)]
+    Stop {
+        /// Which run to stop (picked interactively if not given)
+        run: Option<String>,
+        /// How long to give the run to stop before escalating to the next signal
+        #[clap(long, value_name = "DURATION", value_parser = parse_duration, default_value = "30s")]
+        grace: Duration,
+        /// Also signal processes which left the run's process group (e.g. daemonized children)
+        #[clap(long)]
+        tree: bool,
+        /// Also signal runs which belong to other users
+        #[clap(long)]
+        force: bool,
+    },
+
+    /// Print a completion script for the given shell (e.g. `rum -completions zsh > _rum`)
+    #[clap(name = "-completions", long_flag = "completions", display_order = 19)]
+    Completions {
+        /// The shell to print the completion script for
+        shell: clap_complete::Shell,
+    },
+
+    /// Print rum's man page, or write man pages for rum and all its subcommands into a directory
+    #[clap(name = "-manpage", long_flag = "manpage", display_order = 20)]
+    Manpage {
+        /// Where to write the man pages to
+        #[clap(long)]
+        dir: Option<PathBuf>,
+    },
+
+    /// Print a compact summary of running and recently failed runs, for shell prompts
+    #[clap(
+        name = "-prompt-status",
+        long_flag = "prompt-status",
+        display_order = 22
+    )]
+    PromptStatus {
+        /// How recently runs must have failed to be counted
+        #[clap(long, value_name = "DURATION", value_parser = parse_duration, default_value = "1h")]
+        since: Duration,
+    },
+
+    /// Print a single line summarizing the runs, for status bars (e.g. tmux's `status-right`)
+    #[clap(name = "-status-line", long_flag = "status-line", display_order = 23)]
+    StatusLine {
+        /// What to show; `{running}`, `{queued}`, `{failed}`, `{oldest}` (the label or name of the
+        /// run which has been running the longest) and `{oldest_duration}` are filled in
+        #[clap(long, default_value = actions::status::DEFAULT_STATUS_LINE_FORMAT)]
+        format: String,
+        /// How recently runs must have failed to be counted
+        #[clap(long, value_name = "DURATION", value_parser = parse_duration, default_value = "1h")]
+        since: Duration,
+    },
+
+    /// Show who started, signalled or removed runs, and when
+    #[clap(name = "-audit", long_flag = "audit", display_order = 24)]
+    Audit {
+        /// Only show what was done within this long (e.g. `2d`)
+        #[clap(long, value_name = "DURATION", value_parser = parse_duration)]
+        since: Option<Duration>,
+    },
+
+    /// Print the events of all runs (started, signalled, finished, ...) as JSON lines
+    #[clap(name = "-events", long_flag = "events", display_order = 25)]
+    Events {
+        /// Print new events as they happen (until interrupted), instead of past ones
+        #[clap(long, short = 'f')]
+        follow: bool,
+    },
+
+    /// Run a pipeline: steps with dependencies between them, as defined in a YAML (`.yaml` or
+    /// `.yml`) or else TOML file, each started as a run once the steps it needs have succeeded
+    #[clap(name = "-pipeline", long_flag = "pipeline", display_order = 26)]
+    Pipeline {
+        /// The pipeline file (e.g. `pipeline.yaml`, or `pipeline.toml`)
+        file: PathBuf,
+        /// Run the steps right here, rather than in a run of their own (this is what that run does)
+        #[clap(long, hide = true)]
+        run_steps: bool,
+    },
+
+    /// Upload a run's logs and metadata to the archive, and print URLs to share its output with
+    #[clap(name = "-archive", long_flag = "archive", display_order = 27)]
+    Archive {
+        /// Which run to archive (picked interactively if not given)
+        run: Option<String>,
+        /// How long the printed URLs stay valid for (at most 7 days)
+        #[clap(long, value_name = "DURATION", value_parser = parse_duration, default_value = "7d")]
+        expires: Duration,
+    },
+
+    /// Attach the terminal to an interactive run (see `--interactive`); detach with Ctrl+]
+    #[clap(name = "-attach", long_flag = "attach", display_order = 28)]
+    Attach {
+        /// Which run to attach to (picked interactively if not given)
+        run: Option<String>,
+    },
+
+    /// Run a command several times, each as a run of its own (nested under a run of the
+    /// benchmark), and report how long the attempts took
+    #[clap(name = "-bench", long_flag = "bench", display_order = 29)]
+    Bench {
+        /// How many times to run the command
+        #[clap(long, short = 'n', value_name = "N", default_value = "10", value_parser = clap::value_parser!(u32).range(1..))]
+        runs: u32,
+        /// Run the attempts right here, rather than in a run of their own (this is what that run
+        /// does)
+        #[clap(long, hide = true)]
+        run_attempts: bool,
+        /// The command to benchmark
+        #[clap(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+
+    /// Show how long a finished run took, and the CPU time and memory it used, like `time`
+    #[clap(name = "-time", long_flag = "time", display_order = 30)]
+    Time {
+        /// Which run to show (picked interactively if not given)
+        run: Option<String>,
+        /// Print the report as JSON
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Check the runs for problems (corrupt data, stray files, runs stuck as running,
+    /// permissions), and fix them
+    #[clap(name = "-doctor", long_flag = "doctor", display_order = 32)]
+    Doctor {
+        /// Repair what can be repaired, and move what can't to the quarantine
+        #[clap(long)]
+        fix: bool,
     },
 
     #[clap(external_subcommand)]
     Start(Vec<String>),
 }
 
+/// Get the given run, or let the user pick one if none was given.
+fn get_or_pick_run(runs: &Runs, run: Option<String>) -> Result<Run> {
+    match run {
+        Some(run) => actions::pick::get_run_or_pick_among_matches(runs, &run),
+        None => actions::pick::pick_run(runs),
+    }
+}
+
+/// Send a signal to all running runs matching `filter` if given, or otherwise to the given run;
+/// runs of other users are left alone, unless `force`.
+fn send_signal(
+    runs: &Runs,
+    run: Option<String>,
+    filter: Option<Filter>,
+    signal: signal::Signal,
+    tree: bool,
+    force: bool,
+) -> Result<()> {
+    match filter {
+        Some(filter) => {
+            actions::send_signal::send_signal_to_matching(runs, &filter, signal, tree, force)
+        }
+        None => {
+            let run = get_or_pick_run(runs, run)?;
+            run.check_owner(&run.get_data()?, "signal", force)?;
+            actions::send_signal::send_signal(&run, signal, tree)
+        }
+    }
+}
+
+/// View a run which was just started if `--open` was given, or stream its output until it
+/// finishes if `--fg` was.
+fn open_if_asked(run: &Run, options: &StartOptions, config: &Config) -> Result<()> {
+    // Interactive runs are attached to right away, when there's a terminal to attach
+    if options.interactive && termion::is_tty(&std::io::stdin()) {
+        return match run.get_data()?.state {
+            RunDataState::Queued { .. } => Ok(()),
+            _ => actions::attach::attach_run(run),
+        };
+    }
+    if !options.fg && !options.open {
+        return Ok(());
+    }
+    if options.fg {
+        let exit_code = actions::open::wait_for_run(run)?;
+        if let Some(annotate) = options.annotate {
+            let data = run.get_data()?;
+            let key = config.encryption.key_for(&data)?;
+            print!("{}", annotate.end_group());
+            if let Some(annotation) = annotate.annotate_run(run, &data, key.as_ref(), false) {
+                print!("{annotation}");
+            }
+        }
+        std::process::exit(exit_code);
+    }
+    let key = config.encryption.key_for(&run.get_data()?)?;
+    actions::open::open_run(run, &ViewOptions::default(), key.as_ref())
+}
+
 fn main() -> Result<()> {
+    // Subcommands rum doesn't know may be provided by plugins
+    let plugin = plugins::Plugin::find(
+        &Args::command(),
+        &std::env::args_os().skip(1).collect::<Vec<_>>(),
+    );
+    if let Some(plugin) = plugin {
+        return Err(plugin.exec());
+    }
     let args = Args::parse();
+    match args.color {
+        ColorChoice::Always => colored::control::set_override(true),
+        ColorChoice::Never => colored::control::set_override(false),
+        ColorChoice::Auto => (),
+    }
 
-    let runs = Runs::new().with_context(|| "Could not acquire runs")?;
+    // Completions and man pages are static, so there's no need to touch the configuration or the
+    // runs
+    match args.subcommand {
+        Subcommand::Completions { shell } => {
+            clap_complete::generate(shell, &mut Args::command(), "rum", &mut std::io::stdout());
+            return Ok(());
+        }
+        Subcommand::Manpage { dir: Some(dir) } => {
+            std::fs::create_dir_all(&dir)?;
+            return clap_mangen::generate_to(Args::command(), &dir)
+                .with_context(|| format!("Could not write man pages to {:?}", dir));
+        }
+        Subcommand::Manpage { dir: None } => {
+            return clap_mangen::Man::new(Args::command())
+                .render(&mut std::io::stdout())
+                .with_context(|| "Could not write the man page");
+        }
+        _ => (),
+    }
+
+    let config = Config::load()?;
+    theme::set(config.theme.clone());
+    utils::set_datetime_format(
+        args.datetime_format
+            .clone()
+            .unwrap_or(config.datetime_format.clone()),
+    );
+    let runs = Runs::new(args.global).with_context(|| "Could not acquire runs")?;
+
+    // Failing to clean up old runs shouldn't prevent us from doing what was asked; and when asked
+    // to only show what would be removed, nothing should be
+    let is_dry_run = matches!(
+        args.subcommand,
+        Subcommand::Prune { dry_run: true, .. }
+            | Subcommand::Remove {
+                options: RemoveOptions { dry_run: true, .. },
+                ..
+            }
+    );
+    // Prompts and status lines are shown all the time, and so must never be slowed down by a prune
+    let is_prompt = matches!(
+        args.subcommand,
+        Subcommand::PromptStatus { .. } | Subcommand::StatusLine { .. }
+    );
+    if !is_dry_run && !is_prompt {
+        let _ = actions::prune::prune_runs_automatically(&runs, &config.retention);
+    }
+    // Unlike pruning, this is cheap, and prompts would otherwise keep counting such runs as running
+    let _ = runs.mark_interrupted_by_reboot();
 
     match args.subcommand {
         Subcommand::Start(command) => {
-            actions::start::start_run(&runs, command, /*TODO label*/ None)
+            let run = actions::start::start_run(&runs, &config, command, &args.start_options)?;
+            open_if_asked(&run, &args.start_options, &config)
+        }
+        Subcommand::List { options } => actions::list::list_runs(&runs, &options),
+        Subcommand::Info {
+            runs: to_show,
+            filter,
+            annotate,
+        } => {
+            if to_show.is_empty() && filter.is_none() {
+                let run = actions::pick::pick_run(&runs)?;
+                return match annotate {
+                    Some(annotate) => {
+                        actions::show_info::show_runs_info_annotated(vec![run], annotate, &config)
+                    }
+                    None => actions::show_info::show_run_info(&run),
+                };
+            }
+            let mut to_show = to_show
+                .iter()
+                .map(|run| actions::pick::get_run_or_pick_among_matches(&runs, run))
+                .collect::<Result<Vec<_>>>()?;
+            if let Some(filter) = filter {
+                to_show.extend(runs.get_matching(&filter)?);
+            }
+            match annotate {
+                Some(annotate) => {
+                    actions::show_info::show_runs_info_annotated(to_show, annotate, &config)
+                }
+                None => actions::show_info::show_runs_info(to_show),
+            }
+        }
+        Subcommand::View { run, options } => {
+            let run = get_or_pick_run(&runs, run)?;
+            let key = config.encryption.key_for(&run.get_data()?)?;
+            actions::open::open_run(&run, &options, key.as_ref())
         }
-        Subcommand::List => actions::list::list_runs(&runs),
-        Subcommand::Info { run } => actions::show_info::show_run_info(&runs.get_run(&run)?),
-        Subcommand::View { run } => actions::open::open_run(&runs.get_run(&run)?),
         Subcommand::Remove {
             runs: to_remove,
-            no_confirm,
-        } => actions::remove::remove_runs(&runs, &to_remove, !no_confirm),
-        Subcommand::Interrupt { run } => {
-            actions::send_signal::send_signal(&runs.get_run(&run)?, signal::Signal::SIGINT)
+            filter,
+            options,
+        } => actions::remove::remove_runs(&runs, &to_remove, filter.as_ref(), &options),
+        Subcommand::Path {
+            run,
+            dir: _,
+            output,
+            data,
+        } => actions::show_path::show_run_path(
+            &get_or_pick_run(&runs, run)?,
+            if output {
+                RunPath::Output
+            } else if data {
+                RunPath::Data
+            } else {
+                RunPath::Directory
+            },
+        ),
+        Subcommand::Adopt { pid, output, label } => {
+            actions::adopt::adopt_process(&runs, pid, label, output)
+        }
+        Subcommand::Readopt { run } => {
+            actions::adopt::readopt_run(&get_or_pick_run(&runs, run)?)
+        }
+        Subcommand::History { pattern } => {
+            actions::history::show_history(&runs, pattern.as_deref())
+        }
+        Subcommand::Again { pattern } => {
+            let run = actions::history::run_again(
+                &runs,
+                &config,
+                pattern.as_deref(),
+                &args.start_options,
+            )?;
+            open_if_asked(&run, &args.start_options, &config)
+        }
+        Subcommand::Clone { run, args: extra } => {
+            let run = actions::clone::clone_run(
+                &runs,
+                &config,
+                &get_or_pick_run(&runs, run)?,
+                &extra,
+                &args.start_options,
+            )?;
+            open_if_asked(&run, &args.start_options, &config)
+        }
+        Subcommand::Prune { dry_run, force } => {
+            actions::prune::prune_runs(&runs, &config.retention, dry_run, force)
+        }
+        Subcommand::Restore { run } => actions::restore::restore_run(&runs, run),
+        Subcommand::Plot { run } => actions::plot::plot_run(&get_or_pick_run(&runs, run)?),
+        Subcommand::Compare { group, options } => {
+            actions::compare::compare_runs(&runs, &group, &options, &config.encryption)
+        }
+        Subcommand::ExportCsv { filter } => actions::export::export_csv(&runs, filter.as_ref()),
+        Subcommand::ExportJunit { run, filter, lines } => {
+            let run = run
+                .map(|run| actions::pick::get_run_or_pick_among_matches(&runs, &run))
+                .transpose()?;
+            actions::export::export_junit(&runs, &config, run.as_ref(), filter.as_ref(), lines)
+        }
+        Subcommand::Completions { .. } | Subcommand::Manpage { .. } => {
+            unreachable!("handled above")
+        }
+        Subcommand::PromptStatus { since } => actions::status::show_prompt_status(&runs, since),
+        Subcommand::StatusLine { format, since } => {
+            actions::status::show_status_line(&runs, &format, since)
+        }
+        Subcommand::Audit { since } => actions::audit::show_audit_log(&runs, since),
+        Subcommand::Events { follow } => actions::stream_events::stream_events(&runs, follow),
+        Subcommand::Pipeline {
+            file,
+            run_steps: true,
+        } => actions::pipeline::run_steps(&runs, &config, &file),
+        Subcommand::Pipeline {
+            file,
+            run_steps: false,
+        } => {
+            let run = actions::pipeline::start_pipeline(
+                &runs,
+                &config,
+                &file,
+                args.global,
+                &args.start_options,
+            )?;
+            open_if_asked(&run, &args.start_options, &config)
+        }
+        Subcommand::Archive { run, expires } => {
+            actions::archive::archive_run(&config, &get_or_pick_run(&runs, run)?, expires)
+        }
+        Subcommand::Bench {
+            runs: attempts,
+            run_attempts: true,
+            command,
+        } => actions::bench::run_attempts(&runs, &config, attempts, command),
+        Subcommand::Bench {
+            runs: attempts,
+            run_attempts: false,
+            command,
+        } => {
+            // The statistics are what one is after, so wait for them (unless asked to view the run)
+            let options = StartOptions {
+                fg: !args.start_options.open,
+                ..args.start_options
+            };
+            let run = actions::bench::start_bench(
+                &runs,
+                &config,
+                attempts,
+                command,
+                args.global,
+                &options,
+            )?;
+            open_if_asked(&run, &options, &config)
+        }
+        Subcommand::Time { run, json } => {
+            actions::time::time_run(&get_or_pick_run(&runs, run)?, json)
         }
-        Subcommand::Terminate { run } => {
-            actions::send_signal::send_signal(&runs.get_run(&run)?, signal::Signal::SIGTERM)
+        Subcommand::Doctor { fix } => actions::doctor::run_doctor(&runs, fix),
+        Subcommand::Attach { run } => actions::attach::attach_run(&get_or_pick_run(&runs, run)?),
+        Subcommand::Daemon {
+            interval,
+            resume,
+            systemd_unit: true,
+        } => {
+            print!(
+                "{}",
+                actions::daemon::systemd_unit(
+                    &std::env::current_exe()?,
+                    interval,
+                    resume,
+                    args.global
+                )
+            );
+            Ok(())
         }
-        Subcommand::Kill { run } => {
-            actions::send_signal::send_signal(&runs.get_run(&run)?, signal::Signal::SIGKILL)
+        Subcommand::Daemon {
+            interval, resume, ..
+        } => actions::daemon::run_daemon(&runs, &config, interval, resume),
+        Subcommand::Top { interval } => actions::top::show_top(&runs, interval),
+        Subcommand::Interrupt {
+            run,
+            filter,
+            tree,
+            force,
+        } => send_signal(&runs, run, filter, signal::Signal::SIGINT, tree, force),
+        Subcommand::Terminate {
+            run,
+            filter,
+            tree,
+            force,
+        } => send_signal(&runs, run, filter, signal::Signal::SIGTERM, tree, force),
+        Subcommand::Stop {
+            run,
+            grace,
+            tree,
+            force,
+        } => {
+            let run = get_or_pick_run(&runs, run)?;
+            run.check_owner(&run.get_data()?, "stop", force)?;
+            actions::send_signal::stop_run(&run, grace, tree)
         }
+        Subcommand::Kill {
+            run,
+            filter,
+            tree,
+            force,
+        } => send_signal(&runs, run, filter, signal::Signal::SIGKILL, tree, force),
     }
 }
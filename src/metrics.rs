@@ -0,0 +1,83 @@
+use std::{
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, RecvTimeoutError, Sender},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use nix::unistd::Pid;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::proc::{all_processes, Usage};
+
+/// How often the resources used by a run are sampled.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The resources being used by a run at some point in time.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Sample {
+    pub datetime: DateTime<Utc>,
+    /// Total CPU time (user + system) used so far by the run's live processes, in seconds.
+    pub cpu_time: f64,
+    /// Resident set size, in bytes.
+    pub rss: u64,
+    pub processes: usize,
+}
+
+/// Samples the resources used by a process group in a background thread, appending the samples
+/// to a JSON Lines file.
+pub struct MetricsRecorder {
+    stop: Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl MetricsRecorder {
+    pub fn start(metrics_file: PathBuf, pgid: Pid) -> Self {
+        let (stop, stopped) = channel();
+        let thread = std::thread::spawn(move || {
+            let Ok(mut file) = std::fs::File::create(metrics_file) else {
+                return;
+            };
+            loop {
+                // Metrics are a nicety; we don't want to bother the run over them
+                if let Ok(processes) = all_processes() {
+                    let usage = Usage::of(processes.iter().filter(|p| p.pgid == pgid.as_raw()));
+                    let sample = Sample {
+                        datetime: Utc::now(),
+                        cpu_time: usage.cpu_time,
+                        rss: usage.rss,
+                        processes: usage.processes,
+                    };
+                    if let Ok(line) = serde_json::to_string(&sample) {
+                        let _ = writeln!(file, "{}", line);
+                    }
+                }
+                match stopped.recv_timeout(SAMPLE_INTERVAL) {
+                    Err(RecvTimeoutError::Timeout) => (),
+                    _ => break,
+                }
+            }
+        });
+        Self { stop, thread }
+    }
+
+    pub fn stop(self) {
+        let _ = self.stop.send(());
+        let _ = self.thread.join();
+    }
+}
+
+/// Read the samples recorded for a run, skipping any which can't be read (e.g. a partially
+/// written last line).
+pub fn read_samples(metrics_file: &Path) -> Result<Vec<Sample>> {
+    let file = std::fs::File::open(metrics_file)
+        .with_context(|| "No resource usage was recorded for this run")?;
+    Ok(std::io::BufReader::new(file)
+        .lines()
+        .map_while(|line| line.ok())
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect())
+}
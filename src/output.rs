@@ -0,0 +1,440 @@
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+
+use crate::system_log::SystemLog;
+
+/// How a run's output is laid out on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLayout {
+    /// Everything goes to a single `output.log`.
+    Single,
+    /// One `output-YYYY-MM-DD.log` segment per (local) day.
+    Daily,
+    /// Numbered `output-NNNNNN.log` segments, of which only the last `max_bytes` worth are kept.
+    Ring { max_bytes: u64 },
+}
+
+/// Writes the output of a run into its run directory, rotating segments as needed.
+pub struct LogWriter {
+    run_directory: PathBuf,
+    layout: LogLayout,
+    /// Stop recording output once this many bytes have been written.
+    limit: Option<u64>,
+
+    segment_index: u64,
+    current_name: String,
+    current: File,
+    current_size: u64,
+    total_size: u64,
+}
+
+impl LogWriter {
+    pub fn new(run_directory: &Path, layout: LogLayout, limit: Option<u64>) -> std::io::Result<Self> {
+        let current_name = Self::segment_name(layout, 0);
+        let current = File::create(run_directory.join(&current_name))?;
+        Ok(Self {
+            run_directory: run_directory.to_path_buf(),
+            layout,
+            limit,
+            segment_index: 0,
+            current_name,
+            current,
+            current_size: 0,
+            total_size: 0,
+        })
+    }
+
+    fn segment_name(layout: LogLayout, index: u64) -> String {
+        match layout {
+            LogLayout::Single => "output.log".to_string(),
+            LogLayout::Daily => format!("output-{}.log", Local::now().format("%Y-%m-%d")),
+            LogLayout::Ring { .. } => format!("output-{:06}.log", index),
+        }
+    }
+
+    /// How many bytes can still go into the current segment.
+    fn segment_room(&self) -> u64 {
+        match self.layout {
+            // Two segments, each at most half of the maximum, are kept around
+            LogLayout::Ring { max_bytes } => (max_bytes / 2).max(1).saturating_sub(self.current_size),
+            _ => u64::MAX,
+        }
+    }
+
+    fn rotate_if_needed(&mut self) -> std::io::Result<()> {
+        let index = match self.layout {
+            LogLayout::Ring { .. } if self.segment_room() == 0 => self.segment_index + 1,
+            _ => self.segment_index,
+        };
+        let name = Self::segment_name(self.layout, index);
+        if name != self.current_name {
+            self.current.flush()?;
+            self.current = File::create(self.run_directory.join(&name))?;
+            self.current_name = name;
+            self.current_size = 0;
+
+            if let LogLayout::Ring { .. } = self.layout {
+                if let Some(expired) = index.checked_sub(2) {
+                    let expired = self.run_directory.join(Self::segment_name(self.layout, expired));
+                    match std::fs::remove_file(expired) {
+                        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                        _ => (),
+                    }
+                }
+            }
+            self.segment_index = index;
+        }
+        Ok(())
+    }
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Some(limit) = self.limit {
+            if self.total_size >= limit {
+                // Silently discard anything past the limit
+                return Ok(buf.len());
+            }
+        }
+
+        self.rotate_if_needed()?;
+
+        let mut len = buf.len().min(self.segment_room().try_into().unwrap_or(usize::MAX));
+        if let Some(limit) = self.limit {
+            len = len.min((limit - self.total_size).try_into().unwrap_or(usize::MAX));
+        }
+
+        let written = self.current.write(&buf[..len])?;
+        self.current_size += written as u64;
+        self.total_size += written as u64;
+
+        if self.limit.is_some_and(|limit| self.total_size >= limit) {
+            writeln!(self.current, "\n[rum: output limit reached; discarding further output]")?;
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.current.flush()
+    }
+}
+
+/// Returns whether `file_name` is one of the files a run's output is written to.
+pub fn is_output_file_name(file_name: &str) -> bool {
+    file_name == "output.log"
+        || file_name == "output.log.enc"
+        || (file_name.starts_with("output-") && file_name.ends_with(".log"))
+}
+
+/// Masks whatever matches any of a set of patterns (e.g. API keys) in the output going through it,
+/// line by line, before passing it on to `inner`.
+pub struct Redactor<W: Write> {
+    inner: W,
+    patterns: Vec<regex::bytes::Regex>,
+    /// The current (incomplete) line, which can't be redacted until we've seen all of it.
+    pending: Vec<u8>,
+}
+
+/// How much of a single line is held back at most, waiting for the line to end.
+const MAX_PENDING: usize = 64 * 1024;
+
+impl<W: Write> Redactor<W> {
+    pub fn new(inner: W, patterns: Vec<regex::bytes::Regex>) -> Self {
+        Self {
+            inner,
+            patterns,
+            pending: Vec::new(),
+        }
+    }
+
+    fn write_redacted(&mut self, mut text: Vec<u8>) -> std::io::Result<()> {
+        for pattern in &self.patterns {
+            text = pattern.replace_all(&text, &b"[REDACTED]"[..]).into_owned();
+        }
+        self.inner.write_all(&text)
+    }
+
+    /// Pass on whatever is left of the output; to be called once it ends.
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        let rest = std::mem::take(&mut self.pending);
+        self.write_redacted(rest)?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Write for Redactor<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.patterns.is_empty() {
+            return self.inner.write(buf);
+        }
+        self.pending.extend_from_slice(buf);
+        // Carriage returns end "lines" too, so that progress bars still show up in a timely manner
+        let complete = match self.pending.iter().rposition(|&b| b == b'\n' || b == b'\r') {
+            Some(end) => end + 1,
+            None if self.pending.len() > MAX_PENDING => self.pending.len(),
+            None => return Ok(buf.len()),
+        };
+        let lines = self.pending.drain(..complete).collect();
+        self.write_redacted(lines)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Passes the output going through it on to `inner`, copying it into each of `copies` as well.
+/// Copies which can't be written to anymore (e.g. a terminal which was closed) are dropped, without
+/// affecting the rest.
+pub struct Tee<W: Write> {
+    inner: W,
+    copies: Vec<Box<dyn Write>>,
+}
+
+impl<W: Write> Tee<W> {
+    pub fn new(inner: W, copies: Vec<Box<dyn Write>>) -> Self {
+        Self { inner, copies }
+    }
+}
+
+impl<W: Write> Write for Tee<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.copies
+            .retain_mut(|copy| copy.write_all(&buf[..written]).is_ok());
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.copies.retain_mut(|copy| copy.flush().is_ok());
+        self.inner.flush()
+    }
+}
+
+/// Passes the output going through it on to `inner`, and also sends each line of it to system
+/// logs (see `--log-to`).
+pub struct Forwarder<W: Write> {
+    inner: W,
+    logs: Vec<SystemLog>,
+    /// The current (incomplete) line, which is only sent once it ends.
+    pending: Vec<u8>,
+}
+
+impl<W: Write> Forwarder<W> {
+    pub fn new(inner: W, logs: Vec<SystemLog>) -> Self {
+        Self {
+            inner,
+            logs,
+            pending: Vec::new(),
+        }
+    }
+
+    fn send(&self, line: &[u8]) {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        for log in &self.logs {
+            // The output log is what matters; a log which can't keep up just misses lines
+            let _ = log.send(line);
+        }
+    }
+}
+
+impl<W: Write> Write for Forwarder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write_all(buf)?;
+        self.pending.extend_from_slice(buf);
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line = self.pending.drain(..=end).collect::<Vec<_>>();
+            self.send(&line[..end]);
+        }
+        if self.pending.len() > MAX_PENDING {
+            let line = std::mem::take(&mut self.pending);
+            self.send(&line);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for Forwarder<W> {
+    fn drop(&mut self) {
+        if !self.pending.is_empty() {
+            let line = std::mem::take(&mut self.pending);
+            self.send(&line);
+        }
+    }
+}
+
+/// Keeps extremely chatty runs from flooding the disk (and whoever reads the output): beyond
+/// `max_lines` lines in a second, only every `sample_every`-th line is passed on to `inner`, and
+/// the next line which is gets preceded by a note on how many lines were dropped.
+pub struct RateLimiter<W: Write> {
+    inner: W,
+    max_lines: u64,
+    sample_every: u64,
+
+    window_start: Instant,
+    lines_in_window: u64,
+    dropped: u64,
+    /// Whether the current line is being passed on, if it has started.
+    passing: Option<bool>,
+}
+
+impl<W: Write> RateLimiter<W> {
+    pub fn new(inner: W, max_lines: u64, sample_every: u64) -> Self {
+        Self {
+            inner,
+            max_lines,
+            sample_every: sample_every.max(1),
+            window_start: Instant::now(),
+            lines_in_window: 0,
+            dropped: 0,
+            passing: None,
+        }
+    }
+
+    /// Decide whether to pass on a line which is starting.
+    fn admit_line(&mut self) -> std::io::Result<bool> {
+        if self.window_start.elapsed() >= Duration::from_secs(1) {
+            self.window_start = Instant::now();
+            self.lines_in_window = 0;
+        }
+        self.lines_in_window += 1;
+        let over_limit = self.lines_in_window.saturating_sub(self.max_lines);
+        let admit = over_limit.is_multiple_of(self.sample_every);
+        match admit {
+            true => self.write_dropped_note()?,
+            false => self.dropped += 1,
+        }
+        Ok(admit)
+    }
+
+    fn write_dropped_note(&mut self) -> std::io::Result<()> {
+        if self.dropped > 0 {
+            writeln!(
+                self.inner,
+                "[rum: dropped {} lines, over {} lines per second]",
+                self.dropped, self.max_lines
+            )?;
+            self.dropped = 0;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for RateLimiter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let passing = match self.passing {
+                Some(passing) => passing,
+                None => self.admit_line()?,
+            };
+            let line = match rest.iter().position(|&b| b == b'\n') {
+                Some(end) => {
+                    self.passing = None;
+                    &rest[..=end]
+                }
+                None => {
+                    self.passing = Some(passing);
+                    rest
+                }
+            };
+            if passing {
+                self.inner.write_all(line)?;
+            }
+            rest = &rest[line.len()..];
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for RateLimiter<W> {
+    fn drop(&mut self) {
+        // Don't leave out that the last lines were dropped
+        if self.passing == Some(true) {
+            let _ = writeln!(self.inner);
+        }
+        let _ = self.write_dropped_note();
+    }
+}
+
+/// Where in an escape sequence `AnsiStripper` is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EscapeState {
+    /// Not in an escape sequence.
+    Text,
+    /// Right after an ESC.
+    Escape,
+    /// In a control sequence (e.g. colors, `ESC [ 3 1 m`), which ends with a byte in `@`..=`~`.
+    Csi,
+    /// In an operating system command (e.g. a window title or a hyperlink), which ends with a
+    /// BEL or an ST (`ESC \`).
+    Osc,
+    /// Right after an ESC in an operating system command.
+    OscEscape,
+}
+
+/// Strips ANSI escape sequences (colors, cursor movement, hyperlinks, ...) from the output going
+/// through it, so that what is recorded is plain text. Sequences may be split across writes.
+pub struct AnsiStripper<W: Write> {
+    inner: W,
+    state: EscapeState,
+}
+
+impl<W: Write> AnsiStripper<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            state: EscapeState::Text,
+        }
+    }
+}
+
+impl<W: Write> Write for AnsiStripper<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        const ESC: u8 = 0x1b;
+        const BEL: u8 = 0x07;
+        let mut text = Vec::with_capacity(buf.len());
+        for &b in buf {
+            self.state = match (self.state, b) {
+                (EscapeState::Text, ESC) => EscapeState::Escape,
+                (EscapeState::Text, b) => {
+                    text.push(b);
+                    EscapeState::Text
+                }
+                (EscapeState::Escape, b'[') => EscapeState::Csi,
+                (EscapeState::Escape, b']') => EscapeState::Osc,
+                // Other sequences are just ESC and a single character
+                (EscapeState::Escape, _) => EscapeState::Text,
+                (EscapeState::Csi, 0x40..=0x7e) => EscapeState::Text,
+                (EscapeState::Csi, _) => EscapeState::Csi,
+                (EscapeState::Osc, BEL) => EscapeState::Text,
+                (EscapeState::Osc, ESC) => EscapeState::OscEscape,
+                (EscapeState::Osc, _) => EscapeState::Osc,
+                (EscapeState::OscEscape, b'\\') => EscapeState::Text,
+                (EscapeState::OscEscape, _) => EscapeState::Osc,
+            };
+        }
+        self.inner.write_all(&text)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
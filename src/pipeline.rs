@@ -0,0 +1,154 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::Path,
+    time::SystemTime,
+};
+
+use anyhow::{Context, Error, Result};
+use serde::Deserialize;
+
+/// A pipeline, as defined in a pipeline file (see `rum -pipeline`): named steps, each with a
+/// command and the steps which must succeed before it can start.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pipeline {
+    pub steps: BTreeMap<String, Step>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    pub command: StepCommand,
+    /// The steps which must succeed before this one starts.
+    #[serde(default)]
+    pub needs: Vec<String>,
+    /// Glob patterns for the files the step reads.
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    /// Glob patterns for the files the step writes; if they are all newer than its inputs, the
+    /// step is up to date, and needn't be run.
+    #[serde(default)]
+    pub outputs: Vec<String>,
+}
+
+impl Step {
+    /// Whether the step's outputs all exist and are newer than all of its inputs, like `make`
+    /// decides whether to rebuild a target. Steps without outputs are never up to date.
+    pub fn is_up_to_date(&self) -> Result<bool> {
+        if self.outputs.is_empty() {
+            return Ok(false);
+        }
+        let mut oldest_output = None;
+        for pattern in &self.outputs {
+            match modification_times(pattern)?.into_iter().min() {
+                Some(time) => {
+                    oldest_output = Some(oldest_output.map_or(time, |t: SystemTime| t.min(time)))
+                }
+                None => return Ok(false),
+            }
+        }
+        let mut newest_input = None;
+        for pattern in &self.inputs {
+            newest_input = newest_input.max(modification_times(pattern)?.into_iter().max());
+        }
+        Ok(newest_input <= oldest_output)
+    }
+}
+
+/// Get when each of the files matching `pattern` was last modified.
+fn modification_times(pattern: &str) -> Result<Vec<SystemTime>> {
+    glob::glob(pattern)
+        .with_context(|| format!("Invalid pattern '{pattern}'"))?
+        .map(|path| {
+            let path = path?;
+            std::fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .with_context(|| format!("Could not read {:?}", path))
+        })
+        .collect()
+}
+
+/// A step's command: either a shell command line, or a program along with its arguments.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum StepCommand {
+    Shell(String),
+    Program(Vec<String>),
+}
+
+impl StepCommand {
+    pub fn to_command(&self) -> Vec<String> {
+        match self {
+            StepCommand::Shell(command) => vec!["sh".into(), "-c".into(), command.clone()],
+            StepCommand::Program(command) => command.clone(),
+        }
+    }
+}
+
+impl Pipeline {
+    /// Read a pipeline from `path` (as YAML if it ends in `.yaml` or `.yml`, or else as TOML),
+    /// making sure that it can actually be run.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read the pipeline from {:?}", path))?;
+        let pipeline: Self = match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml" | "yml") => serde_yaml_ng::from_str(&contents).map_err(Error::from),
+            _ => toml::from_str(&contents).map_err(Error::from),
+        }
+        .with_context(|| format!("Invalid pipeline in {:?}", path))?;
+        pipeline
+            .check()
+            .with_context(|| format!("Invalid pipeline in {:?}", path))?;
+        Ok(pipeline)
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.steps.is_empty() {
+            return Err(Error::msg("The pipeline has no steps"));
+        }
+        for (name, step) in &self.steps {
+            if step.command.to_command().is_empty() {
+                return Err(Error::msg(format!("Step '{name}' has an empty command")));
+            }
+            if let Some(need) = step.needs.iter().find(|n| !self.steps.contains_key(*n)) {
+                return Err(Error::msg(format!(
+                    "Step '{name}' needs step '{need}', which doesn't exist"
+                )));
+            }
+        }
+        self.order().map(|_| ())
+    }
+
+    /// Get the steps in an order in which each comes after all of the steps it needs, or an error
+    /// if some steps (indirectly) need themselves.
+    pub fn order(&self) -> Result<Vec<&str>> {
+        let mut ordered = Vec::new();
+        let mut done = HashSet::new();
+        while done.len() < self.steps.len() {
+            let ready = self
+                .steps
+                .iter()
+                .filter(|(name, step)| {
+                    !done.contains(name.as_str())
+                        && step.needs.iter().all(|n| done.contains(n.as_str()))
+                })
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>();
+            if ready.is_empty() {
+                let cycle = self
+                    .steps
+                    .keys()
+                    .filter(|name| !done.contains(name.as_str()))
+                    .map(|name| format!("'{name}'"))
+                    .collect::<Vec<_>>();
+                return Err(Error::msg(format!(
+                    "Steps {} can never start, as some of them need each other",
+                    cycle.join(", ")
+                )));
+            }
+            done.extend(ready.iter().copied());
+            ordered.extend(ready);
+        }
+        Ok(ordered)
+    }
+}
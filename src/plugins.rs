@@ -0,0 +1,102 @@
+use std::{
+    ffi::OsString,
+    os::unix::{fs::PermissionsExt, process::CommandExt},
+    path::PathBuf,
+};
+
+use anyhow::{Error, Result};
+
+use crate::{config::config_directory, runs::Runs};
+
+/// A plugin, i.e. an executable named `rum-<name>` on the `PATH`, invoked as `rum -<name>` (or
+/// `rum --<name>`) when that isn't one of rum's own subcommands. (A bare `rum <name>` always
+/// starts `<name>` as a run.)
+pub struct Plugin {
+    pub name: String,
+    pub path: PathBuf,
+    /// The arguments given to rum before the plugin's name (e.g. `--global`).
+    pub rum_args: Vec<OsString>,
+    /// The arguments for the plugin itself.
+    pub args: Vec<OsString>,
+}
+
+impl Plugin {
+    /// Find the plugin invoked by `args` (rum's arguments, without the program's name), if any.
+    /// Only the options before the command to start (if any) are looked at, and rum's own
+    /// subcommands and options (from `command`) always take precedence.
+    pub fn find(command: &clap::Command, args: &[OsString]) -> Option<Self> {
+        let mut index = 0;
+        while let Some(arg) = args.get(index) {
+            let arg = arg.to_str()?;
+            let (name, is_long) = match arg.strip_prefix("--") {
+                Some(name) => (name, true),
+                None => (arg.strip_prefix('-')?, false),
+            };
+            if name.is_empty() {
+                return None;
+            }
+
+            let is_subcommand = command.get_subcommands().any(|subcommand| {
+                subcommand.get_name() == arg
+                    || subcommand.get_long_flag() == Some(name)
+                    || (!is_long && subcommand.get_short_flag() == name.chars().next())
+            });
+            if is_subcommand {
+                return None;
+            }
+            let option = command.get_arguments().find(|option| match is_long {
+                true => option.get_long() == name.split('=').next(),
+                false => name.chars().count() == 1 && option.get_short() == name.chars().next(),
+            });
+            if let Some(option) = option {
+                let has_inline_value = is_long && name.contains('=');
+                if option.get_action().takes_values() && !has_inline_value {
+                    index += 1;
+                }
+                index += 1;
+                continue;
+            }
+
+            return Some(Self {
+                name: name.to_string(),
+                path: find_executable(&format!("rum-{}", name))?,
+                rum_args: args[..index].to_vec(),
+                args: args[index + 1..].to_vec(),
+            });
+        }
+        None
+    }
+
+    /// Run the plugin in place of rum, telling it where rum keeps its data and configuration
+    /// (`RUM_DATA_DIRECTORY`, `RUM_CONFIG_DIRECTORY`) and how to run rum itself (`RUM`). This only
+    /// returns if the plugin couldn't be run.
+    pub fn exec(self) -> Error {
+        let result = (|| -> Result<std::io::Error> {
+            let global = self
+                .rum_args
+                .iter()
+                .any(|arg| arg == "--global" || arg == "-G");
+            let runs = Runs::new(global)?;
+            Ok(std::process::Command::new(&self.path)
+                .args(&self.args)
+                .env("RUM_DATA_DIRECTORY", runs.get_data_directory())
+                .env("RUM_CONFIG_DIRECTORY", config_directory()?)
+                .env("RUM", std::env::current_exe()?)
+                .exec())
+        })();
+        match result {
+            Ok(e) => Error::from(e).context(format!("Could not run plugin {:?}", self.path)),
+            Err(e) => e.context(format!("Could not run plugin '{}'", self.name)),
+        }
+    }
+}
+
+fn find_executable(name: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|directory| directory.join(name))
+        .find(|path| {
+            std::fs::metadata(path).is_ok_and(|metadata| {
+                metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+            })
+        })
+}
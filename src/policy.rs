@@ -0,0 +1,91 @@
+use std::path::Path;
+
+use anyhow::{Context, Error, Result};
+use rhai::{
+    serde::{from_dynamic, to_dynamic},
+    Dynamic, Engine, EvalAltResult, Scope,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    encryption::Key,
+    runs::{Run, RunData, RunDataState},
+};
+
+/// How many of the last lines of a run's output are given to the policy.
+const OUTPUT_LINES: usize = 50;
+
+/// How many operations a policy may take before it is stopped.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// What a policy (see `watchdog.policy` in the configuration) decided to do about a run. Each
+/// field is optional, so e.g. `#{}` means doing nothing, and `#{ retry: true }` retrying the run.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Decision {
+    /// Start the run again (if it is done, and hasn't been retried too many times already).
+    pub retry: bool,
+    /// Terminate the run (if it is still running).
+    pub terminate: bool,
+    /// Label the run with this instead.
+    pub label: Option<String>,
+    /// Log this message in the daemon's log.
+    pub log: Option<String>,
+}
+
+fn to_error(error: Box<EvalAltResult>) -> Error {
+    Error::msg(error.to_string())
+}
+
+/// Ask the `policy` script (written in [Rhai](https://rhai.rs)) what to do about `event` on
+/// `run`. The script gets the `event`, the `run`'s metadata and its last lines of `output` as
+/// variables, and is expected to evaluate to a [`Decision`] as an object map (or to `()`, to do
+/// nothing).
+pub fn decide(
+    policy: &Path,
+    event: &str,
+    run: &Run,
+    data: &RunData,
+    key: Option<&Key>,
+) -> Result<Decision> {
+    let (exit_code, end_datetime) = match data.state {
+        RunDataState::Done {
+            exit_code,
+            end_datetime,
+            ..
+        } => (Some(exit_code), Some(end_datetime)),
+        _ => (None, None),
+    };
+    let output = run.read_last_lines(OUTPUT_LINES, key).unwrap_or_default();
+    let run = json!({
+        "id": run.id,
+        "name": data.name,
+        "label": data.label,
+        "command": data.command,
+        "status": data.state.status(),
+        "exit_code": exit_code,
+        "start_datetime": data.start_datetime,
+        "end_datetime": end_datetime,
+        "working_directory": data.working_directory,
+        "directory": run.run_directory,
+    });
+
+    let mut engine = Engine::new();
+    // The daemon waits for the policy, so it had better not loop forever
+    engine.set_max_operations(MAX_OPERATIONS);
+    let mut scope = Scope::new();
+    scope.push_constant("event", event.to_string());
+    scope.push_constant_dynamic("run", to_dynamic(&run).map_err(to_error)?);
+    scope.push_constant_dynamic("output", to_dynamic(&output).map_err(to_error)?);
+    let decision = engine
+        .eval_file_with_scope::<Dynamic>(&mut scope, policy.to_path_buf())
+        .map_err(to_error)
+        .with_context(|| format!("The policy {:?} failed", policy))?;
+    if decision.is_unit() {
+        return Ok(Decision::default());
+    }
+    from_dynamic(&decision)
+        .map_err(to_error)
+        .with_context(|| format!("The policy returned an invalid decision: {decision}"))
+}
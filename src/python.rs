@@ -0,0 +1,176 @@
+//! Python bindings for the run store, so that notebooks and scripts can query and start runs
+//! without parsing rum's output. For example:
+//!
+//! ```python
+//! import rum
+//!
+//! store = rum.Store()
+//! run_id = store.start(["python", "train.py", "--lr", "0.1"], label="sweep")
+//! for run in store.list("label=sweep and status=failed"):
+//!     print(run["id"], store.output(run["id"], lines=5))
+//! ```
+
+use std::{io::Read, str::FromStr};
+
+use anyhow::{Error, Result};
+use nix::sys::signal::Signal;
+use pyo3::{exceptions::PyRuntimeError, prelude::*};
+
+use crate::{
+    actions::{send_signal::send_signal, start::start_run},
+    config::Config,
+    filter::Filter,
+    runs::{Run, RunDataState, Runs, StartOptions},
+};
+
+fn to_py_error(error: Error) -> PyErr {
+    PyRuntimeError::new_err(format!("{error:#}"))
+}
+
+/// Turn something serializable into the Python object its JSON would be read as.
+fn to_python(py: Python<'_>, value: &serde_json::Value) -> PyResult<PyObject> {
+    Ok(py
+        .import("json")?
+        .call_method1("loads", (value.to_string(),))?
+        .unbind())
+}
+
+/// The metadata of a run, as recorded in its data file, along with its `id`, `status`,
+/// `exit_code` (if it is done) and `directory`.
+fn describe(run: &Run) -> Result<serde_json::Value> {
+    let data = run.get_data()?;
+    let mut description = serde_json::to_value(&data)?;
+    if let serde_json::Value::Object(fields) = &mut description {
+        fields.insert("id".to_string(), run.id.clone().into());
+        fields.insert("status".to_string(), data.state.status().into());
+        if let RunDataState::Done { exit_code, .. } = data.state {
+            fields.insert("exit_code".to_string(), exit_code.into());
+        }
+        fields.insert(
+            "directory".to_string(),
+            run.run_directory.to_string_lossy().into(),
+        );
+    }
+    Ok(description)
+}
+
+/// A store of runs: the project-local one if there is one (unless `global_`), or else the global
+/// one, as for the `rum` command.
+#[pyclass(module = "rum")]
+struct Store {
+    runs: Runs,
+    config: Config,
+}
+
+impl Store {
+    fn get_run(&self, run: &str) -> Result<Run> {
+        self.runs.get_run(&run.to_string())
+    }
+}
+
+#[pymethods]
+impl Store {
+    #[new]
+    #[pyo3(signature = (global_ = false))]
+    fn new(global_: bool) -> PyResult<Self> {
+        Ok(Self {
+            runs: Runs::new(global_).map_err(to_py_error)?,
+            config: Config::load().map_err(to_py_error)?,
+        })
+    }
+
+    /// List the runs (only those matching the filter `where`, e.g. `status=failed and
+    /// started<1d`, if given), most recently started first, as dictionaries as for `info`.
+    #[pyo3(signature = (r#where = None))]
+    fn list(&self, py: Python<'_>, r#where: Option<&str>) -> PyResult<Vec<PyObject>> {
+        let runs = match r#where {
+            Some(filter) => {
+                Filter::parse(filter).and_then(|filter| self.runs.get_matching(&filter))
+            }
+            None => self.runs.get_all(),
+        }
+        .map_err(to_py_error)?;
+        let mut descriptions = runs
+            .iter()
+            // Runs which are being created or removed can't be described
+            .filter_map(|run| describe(run).ok())
+            .collect::<Vec<_>>();
+        descriptions.sort_by(|a, b| {
+            b["start_datetime"]
+                .as_str()
+                .cmp(&a["start_datetime"].as_str())
+        });
+        descriptions
+            .iter()
+            .map(|description| to_python(py, description))
+            .collect()
+    }
+
+    /// Get the metadata of a run (given by anything `rum` accepts: an ID or a prefix of one, a
+    /// name, `%N`, `last`, ...), as a dictionary.
+    fn info(&self, py: Python<'_>, run: &str) -> PyResult<PyObject> {
+        let description = self
+            .get_run(run)
+            .and_then(|run| describe(&run))
+            .map_err(to_py_error)?;
+        to_python(py, &description)
+    }
+
+    /// Start a run of `command` (a list of arguments), as `rum` would, and return its ID.
+    #[pyo3(signature = (command, label = None, lock = None))]
+    fn start(
+        &self,
+        py: Python<'_>,
+        command: Vec<String>,
+        label: Option<String>,
+        lock: Option<String>,
+    ) -> PyResult<String> {
+        let options = StartOptions {
+            label,
+            lock,
+            id_only: true,
+            ..Default::default()
+        };
+        py.allow_threads(|| start_run(&self.runs, &self.config, command, &options))
+            .map(|run| run.id)
+            .map_err(to_py_error)
+    }
+
+    /// Send a signal (e.g. `SIGINT` or `SIGTERM`, the default) to a run; queued runs are
+    /// cancelled instead. With `tree`, processes which left the run's process group are also
+    /// signalled.
+    #[pyo3(signature = (run, signal = "SIGTERM", tree = false))]
+    fn signal(&self, run: &str, signal: &str, tree: bool) -> PyResult<()> {
+        let signal = Signal::from_str(signal)
+            .map_err(|_| to_py_error(Error::msg(format!("Unknown signal '{signal}'"))))?;
+        self.get_run(run)
+            .and_then(|run| {
+                run.check_owner(&run.get_data()?, "signal", false)?;
+                send_signal(&run, signal, tree)
+            })
+            .map_err(to_py_error)
+    }
+
+    /// Read a run's output (only its last `lines` lines, if given), decrypting it if need be.
+    #[pyo3(signature = (run, lines = None))]
+    fn output(&self, run: &str, lines: Option<usize>) -> PyResult<String> {
+        (|| {
+            let run = self.get_run(run)?;
+            let key = self.config.encryption.key_for(&run.get_data()?)?;
+            match lines {
+                Some(lines) => Ok(run.read_last_lines(lines, key.as_ref())?.join("\n")),
+                None => {
+                    let mut output = Vec::new();
+                    run.read_output(key.as_ref())?.read_to_end(&mut output)?;
+                    Ok(String::from_utf8_lossy(&output).into_owned())
+                }
+            }
+        })()
+        .map_err(to_py_error)
+    }
+}
+
+#[pymodule]
+fn rum(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Store>()
+}
@@ -0,0 +1,78 @@
+use std::{
+    fs::{File, TryLockError},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::config::{project_directory, Quota};
+
+/// How often runs waiting for a quota check whether a slot has been freed.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The slots of a quota, one lock file each: a run takes one for as long as it runs, and can only
+/// start once one is free.
+#[derive(Debug, Clone)]
+pub struct Slots {
+    /// What the quota is for, e.g. `quota:user=alice`; runs waiting for it are queued as waiting
+    /// for this lock.
+    pub name: String,
+    pub limit: usize,
+    files: Vec<PathBuf>,
+}
+
+impl Slots {
+    fn new(directory: &Path, kind: &str, key: &str, limit: usize) -> Self {
+        // Keys (e.g. project directories) can have slashes, which file names can't
+        let file_key = key.replace('%', "%25").replace('/', "%2F");
+        Self {
+            name: format!("quota:{kind}={key}"),
+            limit,
+            files: (0..limit)
+                .map(|i| directory.join(format!("{kind}={file_key}.{i}.lock")))
+                .collect(),
+        }
+    }
+
+    /// Take a free slot, if there is one; it is held until the returned file is closed.
+    pub fn try_take(&self) -> std::io::Result<Option<File>> {
+        for path in &self.files {
+            let file = File::options()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(path)?;
+            match file.try_lock() {
+                Ok(()) => return Ok(Some(file)),
+                Err(TryLockError::WouldBlock) => continue,
+                Err(TryLockError::Error(e)) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Get the slots of the quotas which apply to a run started by `user` from `directory` with
+/// `label`, whose lock files are kept in `locks_directory`.
+pub fn slots_for(
+    quota: &Quota,
+    locks_directory: &Path,
+    user: &str,
+    directory: &Path,
+    label: Option<&str>,
+) -> Vec<Slots> {
+    let mut slots = Vec::new();
+    // Always in the same order, so that runs waiting for several quotas can't deadlock
+    if let Some(limit) = quota.per_user {
+        slots.push(Slots::new(locks_directory, "user", user, limit));
+    }
+    if let Some(limit) = quota.per_project {
+        let project = project_directory(directory).to_string_lossy();
+        slots.push(Slots::new(locks_directory, "project", &project, limit));
+    }
+    if let Some(label) = label {
+        if let Some(limit) = quota.labels.get(label).copied().or(quota.per_label) {
+            slots.push(Slots::new(locks_directory, "label", label, limit));
+        }
+    }
+    slots
+}
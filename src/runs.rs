@@ -1,41 +1,577 @@
 use std::{
-    os::unix::prelude::{AsRawFd, FromRawFd},
-    path::PathBuf,
-    process::Child,
+    collections::{BTreeMap, VecDeque},
+    fs::{File, TryLockError},
+    io::{BufRead, PipeWriter, Read, Write},
+    os::{
+        fd::{AsFd, AsRawFd, OwnedFd},
+        unix::process::{CommandExt, ExitStatusExt},
+    },
+    path::{Path, PathBuf},
+    process::{Child, ExitStatus, Stdio},
 };
 
 use anyhow::{Context, Error, Result};
 use chrono::{DateTime, Utc};
-use fork::{close_fd, fork, Fork};
-use nix::unistd::{getpgid, setpgid, Pid};
+use fork::{fork, Fork};
+use nix::{
+    sys::signal::{kill, signal, SigHandler, Signal},
+    unistd::{dup2, getgid, getpid, getuid, setsid, Pid},
+};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use uuid::Uuid;
 
+use crate::{
+    annotations::Annotate,
+    archive,
+    config::{Archive, Email, Telemetry},
+    core_dump, email,
+    encryption::{self, Encryptor, Key},
+    events::{self, Event},
+    filter::Filter,
+    hooks::{self, Hook},
+    inhibit::SleepInhibitor,
+    limits::{parse_umask, CpuList, Ulimit},
+    metrics::MetricsRecorder,
+    output::{
+        is_output_file_name, AnsiStripper, Forwarder, LogLayout, LogWriter, RateLimiter, Redactor,
+        Tee,
+    },
+    quota::{self, Slots},
+    sandbox::{self, Sandbox, SandboxPreset},
+    session::{self, Attachments},
+    system_log::LogTarget,
+    telemetry,
+    utils::{directory_size, names::generate_name, parse_duration, parse_size, proc},
+};
+
 pub type RunId = String;
 
+/// The environment variable holding the ID of the run a process belongs to, so that runs started
+/// from within other runs know which run they were started from.
+pub const RUN_ID_ENV: &str = "RUM_RUN_ID";
+
+/// Options controlling how a new run is started.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct StartOptions {
+    /// A label for the run
+    #[clap(long)]
+    pub label: Option<String>,
+
+    /// Split the run's output into one log file per day
+    #[clap(long)]
+    pub split_logs: bool,
+
+    /// Maximum amount of output to keep on disk (e.g. 100M)
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    pub log_max: Option<u64>,
+
+    /// What to do once the output reaches `--log-max`
+    #[clap(long, value_enum, requires = "log_max", conflicts_with = "split_logs")]
+    pub log_mode: Option<LogMode>,
+
+    /// Strip ANSI escape sequences (colors and such) from the output before recording it; with
+    /// `--fg`, the terminal still shows them
+    #[clap(long)]
+    pub strip_ansi: bool,
+
+    /// Record at most this many lines of output per second; beyond that, only every
+    /// `--sample-every`-th line is, with a note on how many were dropped
+    #[clap(long, value_name = "LINES")]
+    pub rate_limit: Option<u64>,
+
+    /// Which lines to record once over `--rate-limit`
+    #[clap(long, value_name = "N", default_value = "100", requires = "rate_limit")]
+    pub sample_every: u64,
+
+    /// Also copy the output into this file (can be given multiple times)
+    #[clap(long, value_name = "PATH")]
+    pub tee: Vec<PathBuf>,
+
+    /// Tell the run that its terminal is this many columns wide (through `COLUMNS`), so that it
+    /// doesn't wrap or truncate its output for a narrow (or missing) terminal
+    #[clap(long, value_name = "COLUMNS")]
+    pub width: Option<u16>,
+
+    /// Tell the run that its terminal is this many lines high (through `LINES`)
+    #[clap(long, value_name = "LINES")]
+    pub height: Option<u16>,
+
+    /// Write the output to this file (rather than into the run's directory, where it is linked to
+    /// from)
+    #[clap(long, value_name = "PATH", conflicts_with_all = ["split_logs", "log_max", "encrypt"])]
+    pub log_file: Option<PathBuf>,
+
+    /// Also send each line of output to a system log (can be given multiple times)
+    #[clap(long, value_enum, value_name = "LOG")]
+    pub log_to: Vec<LogTarget>,
+
+    /// Archive the current directory into the run's directory before starting
+    #[clap(long)]
+    pub snapshot: bool,
+
+    /// Copy files matching this glob pattern into the run's directory once it finishes
+    #[clap(long = "artifact", value_name = "PATTERN")]
+    pub artifacts: Vec<String>,
+
+    /// Don't start the run if the same command is already running
+    #[clap(long)]
+    pub unique: bool,
+
+    /// Consider the run failed if a line of its output matches this regex, even if it exits
+    /// successfully
+    #[clap(long, value_name = "REGEX")]
+    pub fail_on_regex: Option<Regex>,
+
+    /// How long the run is expected to take; it is flagged as overdue if it takes longer
+    #[clap(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub expect: Option<std::time::Duration>,
+
+    /// Consider the run stalled if it produces no output for this long
+    #[clap(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub stall_after: Option<std::time::Duration>,
+
+    /// Only start the run once no other run holding the same lock is running
+    #[clap(long, value_name = "NAME")]
+    pub lock: Option<String>,
+
+    /// Only print the run's ID to standard output (e.g. for `ID=$(rum --id-only ...)`); other
+    /// messages go to standard error
+    #[clap(long, visible_alias = "quiet")]
+    pub id_only: bool,
+
+    /// View the run right away, as with `-view`
+    #[clap(long, conflicts_with = "fg")]
+    pub open: bool,
+
+    /// Stream the run's output to the terminal until it finishes, and exit with its exit code;
+    /// Ctrl+C interrupts the run, and standard input is fed to it
+    #[clap(long)]
+    pub fg: bool,
+
+    /// With `--fg`, annotate the CI log the output goes to: the output is put in a group of its
+    /// own, and the run failing is reported as an error
+    #[clap(long, value_name = "FORMAT", requires = "fg")]
+    pub annotate: Option<Annotate>,
+
+    /// Run the command in a terminal of its own, which one can attach to (right away, and later
+    /// with `-attach`) to interact with it, and detach from with Ctrl+]
+    #[clap(long, conflicts_with_all = ["fg", "open", "stdin", "save_stdin"])]
+    pub interactive: bool,
+
+    /// Adjust how likely the run is to be picked by the kernel when out of memory, from -1000
+    /// (never) to 1000 (first); lowering it usually requires privileges
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(i32).range(-1000..=1000), allow_hyphen_values = true)]
+    pub oom_score_adj: Option<i32>,
+
+    /// Set a resource limit for the run, e.g. `nofile=65536` or `core=0` (or `<name>=<soft>:<hard>`;
+    /// see `ulimit -a` for what can be limited); can be given multiple times
+    #[clap(long = "ulimit", value_name = "NAME=LIMIT", value_parser = Ulimit::parse)]
+    pub ulimits: Vec<Ulimit>,
+
+    /// Let the run dump core, and move the core file into the run's directory if it does
+    #[clap(long)]
+    pub core_dumps: bool,
+
+    /// Keep the system from going to sleep (e.g. a laptop from suspending) while the run is
+    /// running
+    #[clap(long)]
+    pub inhibit_sleep: bool,
+
+    /// Set the run's file mode creation mask, in octal (e.g. `022`)
+    #[clap(long, value_name = "MASK", value_parser = parse_umask)]
+    pub umask: Option<u32>,
+
+    /// Only let the run use these CPUs, e.g. `0-7` or `0,2,4-6`
+    #[clap(long, visible_alias = "cpuset", value_name = "LIST", value_parser = CpuList::parse)]
+    pub cpus: Option<CpuList>,
+
+    /// Run the command without network access (only loopback), in a network namespace of its own
+    #[clap(long)]
+    pub no_network: bool,
+
+    /// Run the command in a sandbox (with bubblewrap), restricting what it can see and change
+    #[clap(long, value_enum, value_name = "PRESET")]
+    pub sandbox: Option<SandboxPreset>,
+
+    /// Record the environment variables the run is started with (see `environment` in the
+    /// configuration for masking sensitive values)
+    #[clap(long)]
+    pub capture_env: bool,
+
+    /// Feed standard input to the run (e.g. `generate-data | rum --stdin process-data`)
+    #[clap(long)]
+    pub stdin: bool,
+
+    /// Feed standard input to the run, and also save a copy of it as `input.log` in the run's
+    /// directory
+    #[clap(long)]
+    pub save_stdin: bool,
+
+    /// Encrypt the run's output at rest (see `encryption` in the configuration)
+    #[clap(long, conflicts_with_all = ["split_logs", "log_max"])]
+    pub encrypt: bool,
+
+    /// The key to encrypt the run's output with, if it is to be encrypted (see `--encrypt`).
+    #[clap(skip)]
+    pub encryption_key: Option<Key>,
+
+    /// The environment to record for the run, already masked (see `--capture-env`).
+    #[clap(skip)]
+    pub environment: Option<BTreeMap<String, String>>,
+
+    /// Environment variables to set for the run, on top of those it inherits (e.g. those of the
+    /// run it is a clone of).
+    #[clap(skip)]
+    pub env: BTreeMap<String, String>,
+
+    /// Patterns to mask in the run's output before it is recorded (from the configuration).
+    #[clap(skip)]
+    pub redact: Vec<regex::bytes::Regex>,
+
+    /// Where to send a trace of the run to once it finishes (from the configuration).
+    #[clap(skip)]
+    pub telemetry: Option<Telemetry>,
+
+    /// Where to archive the run to once it finishes (from the configuration).
+    #[clap(skip)]
+    pub archive: Option<Archive>,
+
+    /// Where to send an email about the run to once it finishes (from the configuration).
+    #[clap(skip)]
+    pub email: Option<Email>,
+
+    /// The directory with the hooks to run as the run starts and finishes, if any (see
+    /// [`hooks::Hook`]).
+    #[clap(skip)]
+    pub hooks_directory: Option<PathBuf>,
+
+    /// The run this one was started from within, if any (see [`RUN_ID_ENV`]).
+    #[clap(skip)]
+    pub parent: Option<RunId>,
+
+    /// The directory to start the run from, if not the current one (e.g. that of the run it is a
+    /// retry of).
+    #[clap(skip)]
+    pub working_directory: Option<PathBuf>,
+
+    /// The quotas the run has to take a slot of before starting (from the configuration).
+    #[clap(skip)]
+    pub quota: Vec<Slots>,
+
+    /// Whether to refuse to start the run, rather than queue it, if a quota is used up.
+    #[clap(skip)]
+    pub reject_over_quota: bool,
+
+    /// The directory of the store the run is in, which is left out of snapshots (e.g. a
+    /// project-local `.rum`).
+    #[clap(skip)]
+    pub store_directory: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogMode {
+    /// Stop recording output (default)
+    Truncate,
+    /// Keep only the most recent output
+    Ring,
+}
+
+impl StartOptions {
+    fn log_layout(&self) -> LogLayout {
+        match (self.log_mode, self.log_max) {
+            (Some(LogMode::Ring), Some(max_bytes)) => LogLayout::Ring { max_bytes },
+            _ if self.split_logs => LogLayout::Daily,
+            _ => LogLayout::Single,
+        }
+    }
+
+    /// Whether the run gets our standard input, rather than nothing.
+    fn feeds_stdin(&self) -> bool {
+        self.stdin || self.save_stdin || self.fg
+    }
+
+    fn log_limit(&self) -> Option<u64> {
+        match self.log_mode {
+            Some(LogMode::Ring) => None,
+            _ => self.log_max,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RunData {
     pub label: Option<String>,
+    /// A memorable name for the run (e.g. `brave-turing`), which can be used in place of its ID.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The run this one was started from within, if any.
+    #[serde(default)]
+    pub parent: Option<RunId>,
     pub command: Vec<String>,
     pub start_datetime: DateTime<Utc>,
+    /// Glob patterns for files to be collected as artifacts once the run finishes.
+    #[serde(default)]
+    pub artifacts: Vec<String>,
+    /// How long the run is expected to take (see `--expect`).
+    #[serde(default)]
+    pub expected_duration: Option<std::time::Duration>,
+    /// How long the run can go without producing output before being considered stalled.
+    #[serde(default)]
+    pub stall_after: Option<std::time::Duration>,
+    /// The directory the run was started from.
+    #[serde(default)]
+    pub working_directory: Option<PathBuf>,
+    /// The run's OOM score adjustment (see `--oom-score-adj`), if it was set.
+    #[serde(default)]
+    pub oom_score_adj: Option<i32>,
+    /// The resource limits which were set for the run (see `--ulimit`).
+    #[serde(default)]
+    pub ulimits: Vec<Ulimit>,
+    /// The run's file mode creation mask (see `--umask`), if it was set.
+    #[serde(default)]
+    pub umask: Option<u32>,
+    /// The CPUs the run was restricted to (see `--cpus`), if any.
+    #[serde(default)]
+    pub cpus: Option<CpuList>,
+    /// Whether the run was cut off from the network (see `--no-network`).
+    #[serde(default)]
+    pub no_network: bool,
+    /// How the run was sandboxed (see `--sandbox`), if it was.
+    #[serde(default)]
+    pub sandbox: Option<Sandbox>,
+    /// The environment variables the run was started with, if they were recorded (with sensitive
+    /// values masked).
+    #[serde(default)]
+    pub environment: Option<BTreeMap<String, String>>,
+    /// The system logs the run's output is also sent to (see `--log-to`).
+    #[serde(default)]
+    pub log_to: Vec<LogTarget>,
+    /// Whether the run's output is encrypted (see `--encrypt`).
+    #[serde(default)]
+    pub encrypted: bool,
+    /// The lock the run had to take before starting, if any (see `--lock`).
+    #[serde(default)]
+    pub lock: Option<String>,
+    /// The ID of the boot the run was started (or adopted) in, to tell whether it could still be
+    /// running.
+    #[serde(default)]
+    pub boot_id: Option<String>,
+    /// The user who started (or adopted) the run, which matters in stores shared by several
+    /// users; missing for runs from before it was recorded.
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// The version of the format the data was written in (see [`SCHEMA_VERSION`]); 0 for runs
+    /// from before the format was versioned.
+    #[serde(default)]
+    pub schema_version: u32,
 
     pub state: RunDataState,
 }
 
+/// The version of the format of runs' data written by this version of rum. Whenever the format
+/// changes in a way which older data can't simply be read as (e.g. a field being renamed, or a
+/// state being split in two), bump it and add a migration to [`MIGRATIONS`]; new fields with
+/// defaults need neither.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// How to bring runs' data (as JSON) from each version of the format to the next: the `n`th
+/// migration takes it from version `n` to version `n + 1`.
+type Migration = fn(&mut serde_json::Value) -> Result<()>;
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [
+    // Everything added before the format was versioned has defaults, so only the version itself
+    // is missing
+    |_| Ok(()),
+];
+
+/// The error for runs' data written by a newer version of rum, in a format we don't know.
+#[derive(Error, Debug)]
+#[error(
+    "Run data is in a newer format (version {version}, while this version of rum only reads up \
+     to {SCHEMA_VERSION}); upgrade rum to read it"
+)]
+pub struct NewerSchema {
+    pub version: u32,
+}
+
+/// Bring runs' data (as JSON) from whatever version of the format it was written in to the
+/// current one, so that it can be read as [`RunData`].
+fn migrate(mut data: serde_json::Value) -> Result<serde_json::Value> {
+    let version = data
+        .get("schema_version")
+        .and_then(|version| version.as_u64())
+        .unwrap_or(0) as u32;
+    if version > SCHEMA_VERSION {
+        return Err(NewerSchema { version }.into());
+    }
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(&mut data)
+            .with_context(|| format!("Could not migrate run data from format version {from}"))?;
+    }
+    if let Some(object) = data.as_object_mut() {
+        object.insert("schema_version".to_string(), SCHEMA_VERSION.into());
+    }
+    Ok(data)
+}
+
+impl RunData {
+    /// Get how long this run is expected to take: either what was given with `--expect`, or
+    /// otherwise as long as the longest successful run of the same command in `history`.
+    pub fn get_expected_duration(&self, history: &[RunData]) -> Option<std::time::Duration> {
+        self.expected_duration.or_else(|| {
+            history
+                .iter()
+                .filter(|other| other.command == self.command && other.state.is_success())
+                .filter_map(|other| match other.state {
+                    RunDataState::Done { end_datetime, .. } => {
+                        (end_datetime - other.start_datetime).to_std().ok()
+                    }
+                    _ => None,
+                })
+                .max()
+        })
+    }
+
+    /// Whether this run is still running, but has taken longer than expected.
+    pub fn is_overdue(&self, history: &[RunData]) -> bool {
+        let RunDataState::Running { .. } = self.state else {
+            return false;
+        };
+        let elapsed = (Utc::now() - self.start_datetime)
+            .to_std()
+            .unwrap_or_default();
+        self.get_expected_duration(history)
+            .is_some_and(|expected| elapsed > expected)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum RunDataState {
     Running {
         #[serde(with = "serde_nix_pid")]
         pgid: Pid,
+        /// The run's main process.
+        #[serde(default, with = "serde_nix_pid::option")]
+        pid: Option<Pid>,
+        /// The process waiting for the run to finish, so as to record it as done.
+        #[serde(default, with = "serde_nix_pid::option")]
+        monitor: Option<Pid>,
+    },
+    /// Waiting for another run to release a lock before starting.
+    Queued {
+        lock: String,
+        #[serde(with = "serde_nix_pid")]
+        monitor: Pid,
     },
     Done {
         end_datetime: DateTime<Utc>,
+        /// The process' exit code; or -1 if it was killed by a signal, -2 if we failed to wait
+        /// for it, -3 if we couldn't know it (e.g. for adopted processes), and -4 if the system
+        /// shut down while it was running.
         exit_code: i32,
+        #[serde(default)]
+        resource_usage: Option<ResourceUsage>,
+        /// The first line of output matching `--fail-on-regex`, if any; if so, the run is
+        /// considered to have failed, even if it exited successfully.
+        #[serde(default)]
+        failed_output: Option<String>,
     },
 }
 
+impl RunDataState {
+    /// Whether this is a run which finished successfully.
+    pub fn is_success(&self) -> bool {
+        matches!(
+            self,
+            RunDataState::Done {
+                exit_code: 0,
+                failed_output: None,
+                ..
+            }
+        )
+    }
+
+    /// Whether this is a run which finished unsuccessfully (but wasn't killed or anything).
+    pub fn is_failure(&self) -> bool {
+        match self {
+            RunDataState::Done {
+                exit_code,
+                failed_output,
+                ..
+            } => *exit_code > 0 || failed_output.is_some(),
+            _ => false,
+        }
+    }
+
+    /// A short description of the state, e.g. `running` or `failed:1`.
+    pub fn status(&self) -> String {
+        match self {
+            RunDataState::Running { .. } => "running".to_string(),
+            RunDataState::Queued { .. } => "queued".to_string(),
+            RunDataState::Done {
+                exit_code: 0,
+                failed_output: Some(_),
+                ..
+            } => "failed:output".to_string(),
+            RunDataState::Done { exit_code: 0, .. } => "done".to_string(),
+            RunDataState::Done { exit_code: -1, .. } => "killed".to_string(),
+            RunDataState::Done { exit_code: -2, .. } => "crashed".to_string(),
+            RunDataState::Done { exit_code: -3, .. } => "finished".to_string(),
+            RunDataState::Done { exit_code: -4, .. } => "interrupted".to_string(),
+            RunDataState::Done { exit_code, .. } => format!("failed:{exit_code}"),
+        }
+    }
+}
+
+/// Resources used by a finished run's process (and the descendants it waited for).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// Peak resident set size, in bytes.
+    pub max_rss: u64,
+    /// Time spent in user mode, in seconds.
+    pub user_time: f64,
+    /// Time spent in kernel mode, in seconds.
+    pub system_time: f64,
+}
+
+impl ResourceUsage {
+    /// Wait for `process` to finish, and get how it exited along with the resources it (and the
+    /// processes it waited for) used; unlike with `getrusage(RUSAGE_CHILDREN)`, the monitor's
+    /// other children (e.g. hooks) aren't counted.
+    fn wait_for(process: &mut Child) -> std::io::Result<(ExitStatus, Self)> {
+        // As `Child::wait` does, so that the process doesn't wait for input forever
+        drop(process.stdin.take());
+        let pid = process.id() as nix::libc::pid_t;
+        let mut status = 0;
+        let mut usage = std::mem::MaybeUninit::<nix::libc::rusage>::zeroed();
+        loop {
+            // SAFETY: both pointers are valid for writes for the duration of the call
+            let result = unsafe { nix::libc::wait4(pid, &mut status, 0, usage.as_mut_ptr()) };
+            if result != -1 {
+                break;
+            }
+            let error = std::io::Error::last_os_error();
+            if error.kind() != std::io::ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+        // SAFETY: wait4 succeeded, so it filled `usage` in (and it was zeroed anyway)
+        let usage = unsafe { usage.assume_init() };
+        let seconds = |t: nix::libc::timeval| t.tv_sec as f64 + t.tv_usec as f64 / 1e6;
+        Ok((
+            ExitStatus::from_raw(status),
+            Self {
+                // ru_maxrss is in kilobytes
+                max_rss: usage.ru_maxrss as u64 * 1024,
+                user_time: seconds(usage.ru_utime),
+                system_time: seconds(usage.ru_stime),
+            },
+        ))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Run {
     pub id: RunId,
@@ -43,23 +579,92 @@ pub struct Run {
 }
 
 pub struct Runs {
+    data_directory: PathBuf,
     run_directory: PathBuf,
 }
 
+/// The error for a query (e.g. an ID prefix) which matches several runs; it lists them, so that
+/// the right one can be told apart.
+#[derive(Error, Debug)]
+#[error("Multiple runs match '{query}':{}", describe_candidates(.candidates))]
+pub struct AmbiguousQuery {
+    pub query: String,
+    /// The runs the query matches, most recently started first.
+    pub candidates: Vec<Run>,
+}
+
+impl AmbiguousQuery {
+    fn new(query: &str, mut candidates: Vec<Run>) -> Self {
+        candidates.sort_by_key(|run| {
+            std::cmp::Reverse(run.get_data().ok().map(|data| data.start_datetime))
+        });
+        Self {
+            query: query.to_string(),
+            candidates,
+        }
+    }
+}
+
+fn describe_candidates(candidates: &[Run]) -> String {
+    candidates
+        .iter()
+        .map(|run| match run.get_data() {
+            Ok(data) => format!("\n  {}", run.summarize(&data)),
+            Err(_) => format!("\n  {} [unreadable]", run.id),
+        })
+        .collect()
+}
+
+/// The log of what was done to all runs in a store (see [`events::AuditEntry`]).
+const AUDIT_LOG_FILE: &str = "audit.jsonl";
+
+/// The file marking a run in the trash, whose modification time is when it was trashed.
+const TRASHED_MARKER: &str = ".trashed";
+
+/// How long a run can go on being marked as running after its process is gone before it is
+/// considered stuck (its monitor normally marks it as done right away).
+const STUCK_AFTER: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How long (in seconds) the indices shown by `rum -list` can be used to refer to runs.
+const LIST_INDICES_LIFETIME: i64 = 60 * 60;
+
+/// The runs shown by the last `rum -list`, so that they can be referred to by index (`%N`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ListIndices {
+    datetime: DateTime<Utc>,
+    ids: Vec<RunId>,
+}
+
 fn ensure_dir_exists(path: PathBuf) -> Result<PathBuf> {
     std::fs::create_dir_all(&path)?;
     Ok(path)
 }
 
-impl Runs {
-    pub fn new() -> Result<Self> {
-        let project_dirs = directories::ProjectDirs::from("com.github", "dccsillag", "rum")
-            .ok_or_else(|| Error::msg("Couldn't get project directories"))?;
+/// Find a project-local store, i.e. a `.rum` directory in the current directory or one of its
+/// ancestors.
+fn find_local_data_dir() -> Option<PathBuf> {
+    std::env::current_dir()
+        .ok()?
+        .ancestors()
+        .map(|directory| directory.join(".rum"))
+        .find(|directory| directory.is_dir())
+}
 
-        let data_dir = project_dirs.data_local_dir().to_path_buf();
+impl Runs {
+    /// Get the runs in the project-local store if there is one (and `global` is false), or in the
+    /// global store otherwise.
+    pub fn new(global: bool) -> Result<Self> {
+        let data_dir = match find_local_data_dir() {
+            Some(local_data_dir) if !global => local_data_dir,
+            _ => directories::ProjectDirs::from("com.github", "dccsillag", "rum")
+                .ok_or_else(|| Error::msg("Couldn't get project directories"))?
+                .data_local_dir()
+                .to_path_buf(),
+        };
 
         Ok(Self {
             run_directory: ensure_dir_exists(data_dir.join("runs"))?,
+            data_directory: data_dir,
         })
     }
 
@@ -72,7 +677,16 @@ impl Runs {
             .map(|x| (x.file_name().to_str().unwrap().to_string(), x.path())))
     }
 
+    /// Get a run from (a prefix of) its ID or its name, from a selector such as `@last` (see
+    /// [`Runs::get_run_by_selector`]), or from its index in the last listing (e.g. `%2`).
     pub fn get_run(&self, id: &RunId) -> Result<Run> {
+        if let Some(selector) = id.strip_prefix('@') {
+            return self.get_run_by_selector(selector);
+        }
+        if let Some(index) = id.strip_prefix('%') {
+            return self.get_run_by_list_index(index);
+        }
+
         let matching_ids = self
             .run_paths_iter()?
             .filter(|(run_id, _)| run_id.starts_with(id))
@@ -83,15 +697,192 @@ impl Runs {
             .collect::<Vec<_>>();
 
         match &matching_ids[..] {
-            [] => Err(Error::msg(format!("No matching ID for query '{}'", id))),
+            [] => self.get_run_by_name(id),
             [run] => Ok(run.clone()),
+            _ => Err(AmbiguousQuery::new(id, matching_ids).into()),
+        }
+    }
+
+    /// Get a run from its (full) name, e.g. `brave-turing`.
+    fn get_run_by_name(&self, name: &str) -> Result<Run> {
+        let matching = self
+            .get_all()?
+            .into_iter()
+            .filter(|run| {
+                run.get_data()
+                    .is_ok_and(|data| data.name.as_deref() == Some(name))
+            })
+            .collect::<Vec<_>>();
+        match &matching[..] {
+            [] => Err(Error::msg(format!(
+                "No matching ID or name for query '{}'",
+                name
+            ))),
+            [run] => Ok(run.clone()),
+            _ => Err(AmbiguousQuery::new(name, matching).into()),
+        }
+    }
+
+    /// Resolve one of the following selectors:
+    ///
+    /// - `last`: the most recently started run;
+    /// - `last-failed`: the most recently started run which finished with a non-zero exit code;
+    /// - `running`: the run which is currently running, if there is exactly one.
+    pub fn get_run_by_selector(&self, selector: &str) -> Result<Run> {
+        let mut runs = self
+            .get_all()?
+            .into_iter()
+            .filter_map(|run| run.get_data().ok().map(|data| (run, data)))
+            .collect::<Vec<_>>();
+        runs.sort_by_key(|(_, data)| data.start_datetime);
+
+        let no_match = || Error::msg(format!("No run matches '@{}'", selector));
+        match selector {
+            "last" => runs.pop().map(|(run, _)| run).ok_or_else(no_match),
+            "last-failed" => runs
+                .into_iter()
+                .rev()
+                .find(|(_, data)| {
+                    matches!(data.state, RunDataState::Done { .. }) && !data.state.is_success()
+                })
+                .map(|(run, _)| run)
+                .ok_or_else(no_match),
+            "running" => {
+                let running = runs
+                    .into_iter()
+                    .filter(|(_, data)| matches!(data.state, RunDataState::Running { .. }))
+                    .map(|(run, _)| run)
+                    .collect::<Vec<_>>();
+                match &running[..] {
+                    [] => Err(no_match()),
+                    [run] => Ok(run.clone()),
+                    _ => Err(AmbiguousQuery::new(&format!("@{}", selector), running).into()),
+                }
+            }
             _ => Err(Error::msg(format!(
-                "Multiple matching IDs for query '{}'",
-                id
+                "Unknown selector '@{}' (expected one of @last, @last-failed, @running)",
+                selector
             ))),
         }
     }
 
+    /// Get the log of what was done to all runs, and by whom.
+    pub fn get_audit_log(&self) -> Result<Vec<events::AuditEntry>> {
+        events::read(&self.data_directory.join(AUDIT_LOG_FILE))
+    }
+
+    /// Get the directory holding everything in this store (runs, locks, the trash, ...).
+    pub fn get_data_directory(&self) -> &Path {
+        &self.data_directory
+    }
+
+    /// Get the directory holding the runs (one subdirectory each).
+    pub fn get_runs_directory(&self) -> &Path {
+        &self.run_directory
+    }
+
+    /// Get the file holding the ID of the boot rum was last used in.
+    fn get_last_boot_file(&self) -> PathBuf {
+        self.data_directory.join("last_boot")
+    }
+
+    /// Mark the runs which were running (or queued) when the system shut down as interrupted,
+    /// the first time this is called after a reboot; otherwise, they would be left running
+    /// forever, as nothing is monitoring them anymore.
+    pub fn mark_interrupted_by_reboot(&self) -> Result<()> {
+        let boot_id = proc::boot_id()?;
+        let last_boot_file = self.get_last_boot_file();
+        match std::fs::read_to_string(&last_boot_file) {
+            Ok(last_boot_id) if last_boot_id.trim() == boot_id => return Ok(()),
+            // Rum was never used before, so there's nothing to mark
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            _ => {
+                let boot_time = proc::boot_time()?;
+                for run in self.get_all()? {
+                    let Ok(data) = run.get_data() else {
+                        continue;
+                    };
+                    let is_from_earlier_boot = match &data.boot_id {
+                        Some(run_boot_id) => *run_boot_id != boot_id,
+                        // Runs from before we recorded boot IDs
+                        None => data.start_datetime < boot_time,
+                    };
+                    if is_from_earlier_boot
+                        && matches!(
+                            data.state,
+                            RunDataState::Running { .. } | RunDataState::Queued { .. }
+                        )
+                    {
+                        run.mark_done(-4)?;
+                    }
+                }
+            }
+        }
+        std::fs::write(last_boot_file, boot_id)?;
+        Ok(())
+    }
+
+    /// Get the file whose modification time is when the retention policy was last applied.
+    pub fn get_last_prune_file(&self) -> PathBuf {
+        self.data_directory.join("last_prune")
+    }
+
+    fn get_list_indices_file(&self) -> PathBuf {
+        self.data_directory.join("list_indices.json")
+    }
+
+    /// Remember the order in which runs were listed, so that they can be referred to by index.
+    pub fn save_list_indices(&self, ids: Vec<RunId>) -> Result<()> {
+        serde_json::to_writer(
+            std::fs::File::create(self.get_list_indices_file())?,
+            &ListIndices {
+                datetime: Utc::now(),
+                ids,
+            },
+        )?;
+        Ok(())
+    }
+
+    fn get_run_by_list_index(&self, index: &str) -> Result<Run> {
+        let index: usize = index
+            .parse()
+            .map_err(|_| Error::msg(format!("Invalid run index '%{}'", index)))?;
+        let expired = || Error::msg("Run indices have expired; list the runs again");
+
+        let list_indices: ListIndices = std::fs::File::open(self.get_list_indices_file())
+            .ok()
+            .and_then(|file| serde_json::from_reader(file).ok())
+            .ok_or_else(expired)?;
+        if (Utc::now() - list_indices.datetime).num_seconds() > LIST_INDICES_LIFETIME {
+            return Err(expired());
+        }
+
+        let id = index
+            .checked_sub(1)
+            .and_then(|i| list_indices.ids.get(i))
+            .ok_or_else(|| Error::msg(format!("No run with index %{}", index)))?;
+        let run_directory = self.run_directory.join(id);
+        if !run_directory.exists() {
+            return Err(Error::msg(format!(
+                "Run %{} ({}) no longer exists",
+                index, id
+            )));
+        }
+        Ok(Run {
+            id: id.clone(),
+            run_directory,
+        })
+    }
+
+    /// Get all the (readable) runs matching `filter`.
+    pub fn get_matching(&self, filter: &Filter) -> Result<Vec<Run>> {
+        Ok(self
+            .get_all()?
+            .into_iter()
+            .filter(|run| run.get_data().is_ok_and(|data| filter.matches(run, &data)))
+            .collect())
+    }
+
     pub fn get_all(&self) -> Result<Vec<Run>> {
         Ok(self
             .run_paths_iter()?
@@ -104,24 +895,141 @@ impl Runs {
 
     pub fn new_run(&self) -> Result<Run> {
         let id = Uuid::new_v4().to_string();
-        Ok(Run {
+        let run = Run {
             run_directory: ensure_dir_exists(self.run_directory.join(&id))?,
             id,
-        })
+        };
+        run.record_event(Event::Created);
+        Ok(run)
     }
 
     pub fn remove_run(&self, run: Run) -> Result<()> {
+        run.record_event(Event::Deleted);
         std::fs::remove_dir_all(run.run_directory)?;
         Ok(())
     }
+
+    fn get_trash_directory(&self) -> Result<PathBuf> {
+        ensure_dir_exists(self.data_directory.join("trash"))
+    }
+
+    /// Move a run to the trash, from where it can still be restored (see [`Runs::restore_run`])
+    /// until the trash is emptied.
+    pub fn trash_run(&self, run: Run) -> Result<()> {
+        run.record_event(Event::Removed);
+        let trashed_directory = self.get_trash_directory()?.join(&run.id);
+        std::fs::rename(&run.run_directory, &trashed_directory)
+            .with_context(|| format!("Could not move run '{}' to the trash", run.id))?;
+        // Its modification time tells when the run was trashed
+        File::create(trashed_directory.join(TRASHED_MARKER))?;
+        Ok(())
+    }
+
+    /// Get the runs in the trash, along with when they were trashed.
+    pub fn get_trashed(&self) -> Result<Vec<(Run, DateTime<Utc>)>> {
+        let mut trashed = self
+            .get_trash_directory()?
+            .read_dir()
+            .with_context(|| "Could not open the trash")?
+            .filter_map(|x| x.ok())
+            .map(|x| {
+                let trashed_datetime = std::fs::metadata(x.path().join(TRASHED_MARKER))
+                    .and_then(|m| m.modified())
+                    .map(DateTime::<Utc>::from)
+                    .unwrap_or_else(|_| Utc::now());
+                let run = Run {
+                    id: x.file_name().to_str().unwrap().to_string(),
+                    run_directory: x.path(),
+                };
+                (run, trashed_datetime)
+            })
+            .collect::<Vec<_>>();
+        trashed.sort_by_key(|(_, trashed_datetime)| *trashed_datetime);
+        Ok(trashed)
+    }
+
+    /// Move a run from the trash back to the other runs, given (a prefix of) its ID.
+    pub fn restore_run(&self, id: &RunId) -> Result<Run> {
+        let matching = self
+            .get_trashed()?
+            .into_iter()
+            .filter(|(run, _)| run.id.starts_with(id))
+            .collect::<Vec<_>>();
+        let trashed = match &matching[..] {
+            [] => return Err(Error::msg(format!("No run in the trash matches '{}'", id))),
+            [(run, _)] => run,
+            _ => {
+                return Err(Error::msg(format!(
+                    "Multiple runs in the trash match '{}'",
+                    id
+                )))
+            }
+        };
+
+        let run_directory = self.run_directory.join(&trashed.id);
+        std::fs::rename(&trashed.run_directory, &run_directory)
+            .with_context(|| format!("Could not restore run '{}'", trashed.id))?;
+        let _ = std::fs::remove_file(run_directory.join(TRASHED_MARKER));
+        let run = Run {
+            id: trashed.id.clone(),
+            run_directory,
+        };
+        run.record_event(Event::Restored);
+        Ok(run)
+    }
+
+    /// Move something which doesn't belong in the store (e.g. a run which can't be read) out of
+    /// the way, to the quarantine, where it can still be looked into. Returns where it went.
+    pub fn quarantine(&self, path: &Path) -> Result<PathBuf> {
+        let quarantine_directory = ensure_dir_exists(self.data_directory.join("quarantine"))?;
+        let name = path
+            .file_name()
+            .ok_or_else(|| Error::msg(format!("Cannot quarantine {:?}", path)))?;
+        let mut destination = quarantine_directory.join(name);
+        if destination.exists() {
+            destination = quarantine_directory.join(format!(
+                "{}.{}",
+                name.to_string_lossy(),
+                Utc::now().timestamp()
+            ));
+        }
+        std::fs::rename(path, &destination)
+            .with_context(|| format!("Could not move {:?} to the quarantine", path))?;
+        Ok(destination)
+    }
+
+    /// Get the file backing the lock with the given name (see `--lock`).
+    pub fn get_lock_file(&self, name: &str) -> Result<PathBuf> {
+        if name.is_empty() || name.contains('/') || name.starts_with('.') {
+            return Err(Error::msg(format!("Invalid lock name '{}'", name)));
+        }
+        Ok(ensure_dir_exists(self.data_directory.join("locks"))?.join(format!("{}.lock", name)))
+    }
+
+    /// Get the directory holding the files backing the quotas' slots (see [`quota::Slots`]).
+    pub fn get_quota_directory(&self) -> Result<PathBuf> {
+        ensure_dir_exists(self.data_directory.join("locks").join("quota"))
+    }
 }
 
+/// A pipe the run's output goes through, to be copied into the log by the monitor (when it can't
+/// be written to the log directly, e.g. because it is encrypted).
+type OutputPipe = (Box<dyn Read>, Box<dyn Write>);
+
 #[derive(Serialize, Deserialize, Error, Debug, Clone)]
 enum ForkedError {
     #[error("couldn't create output file: {message}")]
     CouldntCreateOutputFile { message: String },
-    #[error("couldn't set process group: {0}")]
-    CouldntSetProcessGroup(String),
+    #[error("couldn't connect to {log}: {message}")]
+    CouldntConnectToLog { log: String, message: String },
+    #[error("couldn't detach from the terminal: {0}")]
+    CouldntDetach(String),
+    #[error("couldn't enter {directory}: {message}")]
+    CouldntEnterDirectory { directory: String, message: String },
+    #[error("couldn't take lock '{lock}': {message}")]
+    CouldntTakeLock { lock: String, message: String },
+    #[error("quota '{quota}' is used up (at most {limit} running at once)")]
+    QuotaExceeded { quota: String, limit: usize },
     #[error("couldn't save run data: {message}")]
     CouldntSetData { message: String },
     #[error("failed to spawn process: {command}: {message}")]
@@ -129,7 +1037,29 @@ enum ForkedError {
 }
 
 impl Run {
-    fn get_data_file(&self) -> PathBuf {
+    /// Describe the run on a single line, as its short ID, name, status, label and command.
+    pub fn summarize(&self, data: &RunData) -> String {
+        let label = data
+            .label
+            .as_ref()
+            .map(|label| format!("({label}) "))
+            .unwrap_or_default();
+        let name = data
+            .name
+            .as_ref()
+            .map(|name| format!(" {name}"))
+            .unwrap_or_default();
+        format!(
+            "{}{} [{}] {}{}",
+            &self.id[..8],
+            name,
+            data.state.status(),
+            label,
+            shell_words::join(&data.command)
+        )
+    }
+
+    pub fn get_data_file(&self) -> PathBuf {
         self.run_directory.join("data.json")
     }
 
@@ -137,20 +1067,302 @@ impl Run {
         self.run_directory.join("output.log")
     }
 
+    /// Get the file holding the run's output, if it is encrypted (see `--encrypt`).
+    pub fn get_encrypted_output_file(&self) -> PathBuf {
+        self.run_directory.join("output.log.enc")
+    }
+
+    /// Get the file where the standard input fed to the run is saved, with `--save-stdin`.
+    pub fn get_input_file(&self) -> PathBuf {
+        self.run_directory.join("input.log")
+    }
+
+    /// Get the socket through which one attaches to the run, if it is interactive (see
+    /// `--interactive`) and still running.
+    pub fn get_attach_socket(&self) -> PathBuf {
+        self.run_directory.join("attach.sock")
+    }
+
+    /// Get where the run's core dump is kept, if it dumped core (see `--core-dumps`).
+    pub fn get_core_file(&self) -> PathBuf {
+        self.run_directory.join("core")
+    }
+
+    fn capture_core_dump(&self, pid: u32) -> Result<()> {
+        let data = self.get_data()?;
+        let directory = match data.working_directory {
+            Some(directory) => directory,
+            None => std::env::current_dir()?,
+        };
+        // The start time is only recorded once the process has started, by which time it may
+        // already have crashed
+        let since = data.start_datetime - chrono::Duration::seconds(5);
+        core_dump::capture(pid, &directory, since.into(), &self.get_core_file())
+    }
+
+    /// Get how much disk space this run takes up, in bytes.
+    pub fn get_disk_usage(&self) -> Result<u64> {
+        directory_size(&self.run_directory)
+    }
+
+    pub fn get_artifacts_directory(&self) -> PathBuf {
+        self.run_directory.join("artifacts")
+    }
+
+    /// Get the artifacts collected from this run, relative to its artifacts directory.
+    pub fn get_artifacts(&self) -> Result<Vec<PathBuf>> {
+        let artifacts_directory = self.get_artifacts_directory();
+        if !artifacts_directory.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut artifacts = Vec::new();
+        let mut to_visit = vec![artifacts_directory.clone()];
+        while let Some(directory) = to_visit.pop() {
+            for entry in directory.read_dir()? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    to_visit.push(path);
+                } else {
+                    artifacts.push(path.strip_prefix(&artifacts_directory)?.to_path_buf());
+                }
+            }
+        }
+        artifacts.sort();
+        Ok(artifacts)
+    }
+
+    /// Copy the files matching `patterns` into the artifacts directory, keeping their paths
+    /// relative to the current directory.
+    fn collect_artifacts(&self, patterns: &[String]) -> Result<()> {
+        let current_directory = std::env::current_dir()?;
+        for pattern in patterns {
+            for path in glob::glob(pattern)?.filter_map(|p| p.ok()) {
+                if !path.is_file() {
+                    continue;
+                }
+                let relative_path = path
+                    .strip_prefix(&current_directory)
+                    .unwrap_or(&path)
+                    .components()
+                    .filter(|c| matches!(c, std::path::Component::Normal(_)))
+                    .collect::<PathBuf>();
+                let destination = self.get_artifacts_directory().join(relative_path);
+                if let Some(parent) = destination.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::copy(&path, &destination)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the first line of the run's output matching `regex`.
+    fn find_in_output(&self, regex: &Regex, key: Option<&Key>) -> Result<Option<String>> {
+        for line in self.read_output(key)?.split(b'\n') {
+            let line = String::from_utf8_lossy(&line?).into_owned();
+            if regex.is_match(&line) {
+                return Ok(Some(line));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Read the run's whole output, decrypting it with `key` if it is encrypted.
+    pub fn read_output(&self, key: Option<&Key>) -> Result<Box<dyn BufRead>> {
+        if let Some(key) = key {
+            let finished = self
+                .get_data()
+                .is_ok_and(|data| matches!(data.state, RunDataState::Done { .. }));
+            let output =
+                encryption::decrypt_file(&self.get_encrypted_output_file(), key, finished)?;
+            return Ok(Box::new(std::io::Cursor::new(output)));
+        }
+        let mut reader: Box<dyn Read> = Box::new(std::io::empty());
+        for file in self.get_output_files()? {
+            reader = Box::new(reader.chain(File::open(file)?));
+        }
+        Ok(Box::new(std::io::BufReader::new(reader)))
+    }
+
+    /// Read the last `count` lines of the run's output, decrypting it with `key` if it is
+    /// encrypted.
+    pub fn read_last_lines(&self, count: usize, key: Option<&Key>) -> Result<Vec<String>> {
+        let mut lines = VecDeque::with_capacity(count);
+        for line in self.read_output(key)?.split(b'\n') {
+            if lines.len() == count {
+                lines.pop_front();
+            }
+            lines.push_back(String::from_utf8_lossy(&line?).into_owned());
+        }
+        Ok(lines.into())
+    }
+
+    /// Get when the run last produced output, if it ever did.
+    pub fn get_last_output_time(&self) -> Result<Option<DateTime<Utc>>> {
+        Ok(self
+            .get_output_files()?
+            .iter()
+            .filter(|f| f.metadata().is_ok_and(|m| m.len() > 0))
+            .filter_map(|f| f.metadata().and_then(|m| m.modified()).ok())
+            .max()
+            .map(DateTime::<Utc>::from))
+    }
+
+    /// Whether the run is running, but hasn't produced any output for longer than its
+    /// `--stall-after`.
+    pub fn is_stalled(&self, data: &RunData) -> bool {
+        self.stalled_for(data).is_some()
+    }
+
+    /// For how long the run has been stalled (i.e. beyond its `--stall-after`), if it is.
+    pub fn stalled_for(&self, data: &RunData) -> Option<std::time::Duration> {
+        let (RunDataState::Running { .. }, Some(stall_after)) = (&data.state, data.stall_after)
+        else {
+            return None;
+        };
+        let last_output = self
+            .get_last_output_time()
+            .ok()
+            .flatten()
+            .unwrap_or(data.start_datetime)
+            .max(data.start_datetime);
+        let silent_for = (Utc::now() - last_output).to_std().unwrap_or_default();
+        silent_for.checked_sub(stall_after).filter(|d| !d.is_zero())
+    }
+
+    /// Get the file where the daemon keeps track of what it did about this run.
+    pub fn get_watchdog_file(&self) -> PathBuf {
+        self.run_directory.join("watchdog.json")
+    }
+
+    pub fn get_hooks_log_file(&self) -> PathBuf {
+        self.run_directory.join("hooks.log")
+    }
+
+    pub fn get_events_file(&self) -> PathBuf {
+        self.run_directory.join("events.jsonl")
+    }
+
+    /// Get the audit log of the store this run is in. Runs (including those in the trash) are
+    /// kept in subdirectories of the store's data directory, where the audit log is.
+    fn get_audit_log_file(&self) -> Option<PathBuf> {
+        Some(self.run_directory.parent()?.parent()?.join(AUDIT_LOG_FILE))
+    }
+
+    /// Record `event` in the run's journal (and in the audit log, if it is audited), as caused by
+    /// the current rum command.
+    pub fn record_event(&self, event: Event) {
+        let entry = events::Entry::now(event);
+        // The journal is only informative, so failing to write to it mustn't stop anything
+        if entry.event.is_audited() {
+            if let Some(audit_log_file) = self.get_audit_log_file() {
+                let entry = events::AuditEntry {
+                    run: self.id.clone(),
+                    entry: entry.clone(),
+                };
+                let _ = events::append(&audit_log_file, &entry);
+            }
+        }
+        let _ = events::append(&self.get_events_file(), &entry);
+    }
+
+    pub fn get_events(&self) -> Result<Vec<events::Entry>> {
+        events::read(&self.get_events_file())
+    }
+
+    pub fn get_metrics_file(&self) -> PathBuf {
+        self.run_directory.join("metrics.jsonl")
+    }
+
+    pub fn get_snapshot_file(&self) -> PathBuf {
+        self.run_directory.join("snapshot.tar.gz")
+    }
+
+    /// Archive `current_directory` (respecting `.gitignore` and the like) into the run's
+    /// directory, leaving out `store_directory` (and so other runs, and their snapshots).
+    fn snapshot_working_directory(
+        &self,
+        current_directory: &Path,
+        store_directory: Option<&Path>,
+    ) -> Result<()> {
+        let mut archive = tar::Builder::new(flate2::write::GzEncoder::new(
+            std::fs::File::create(self.get_snapshot_file())?,
+            flate2::Compression::default(),
+        ));
+
+        // The paths walked are compared as is, so they all have to be canonical
+        let current_directory = current_directory.canonicalize()?;
+        let excluded = [Some(self.run_directory.as_path()), store_directory]
+            .into_iter()
+            .flatten()
+            .filter_map(|directory| directory.canonicalize().ok())
+            .collect::<Vec<_>>();
+        let walker = ignore::WalkBuilder::new(&current_directory)
+            .hidden(false)
+            .filter_entry(move |entry| {
+                entry.file_name() != ".git" && !excluded.iter().any(|e| e == entry.path())
+            })
+            .build();
+        for entry in walker {
+            let entry = entry?;
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+            let relative_path = entry.path().strip_prefix(&current_directory)?;
+            archive.append_path_with_name(entry.path(), relative_path)?;
+        }
+
+        archive.into_inner()?.finish()?;
+        Ok(())
+    }
+
+    /// Get all the files holding this run's output, in the order they were written.
+    pub fn get_output_files(&self) -> Result<Vec<PathBuf>> {
+        let mut files = self
+            .run_directory
+            .read_dir()
+            .with_context(|| format!("Could not open {:?}", &self.run_directory))?
+            .filter_map(|x| x.ok())
+            .filter(|x| x.file_name().to_str().is_some_and(is_output_file_name))
+            .map(|x| x.path())
+            .collect::<Vec<_>>();
+        files.sort();
+        Ok(files)
+    }
+
     pub fn get_data(&self) -> Result<RunData> {
         let data_file = self.get_data_file();
-        serde_json::from_reader(
+        let data = serde_json::from_reader(
             std::fs::File::open(&data_file)
                 .with_context(|| format!("Could not open {:?}", &data_file))?,
         )
-        .with_context(|| format!("Could not parse JSON in {:?}", &data_file))
+        .with_context(|| format!("Could not parse JSON in {:?}", &data_file))?;
+        // Data from older versions of rum is migrated as it is read, and saved in the current
+        // format whenever it is next updated
+        serde_json::from_value(migrate(data)?)
+            .with_context(|| format!("Invalid run data in {:?}", &data_file))
     }
 
     fn set_data(&self, run: &RunData) -> Result<()> {
-        serde_json::to_writer(std::fs::File::create(self.get_data_file())?, run)?;
+        // Write to a temporary file first, so that whoever reads the data at the same time never
+        // sees it half-written
+        let temporary_file = self.get_data_file().with_extension("json.tmp");
+        serde_json::to_writer(std::fs::File::create(&temporary_file)?, run)?;
+        std::fs::rename(temporary_file, self.get_data_file())?;
         Ok(())
     }
 
+    /// Change the run's label (or remove it, with `None`).
+    pub fn set_label(&self, label: Option<String>) -> Result<()> {
+        self.update_data(|run_data| {
+            Ok(RunData {
+                label: label.clone(),
+                ..run_data
+            })
+        })
+    }
+
     fn update_data<F>(&self, f: F) -> Result<()>
     where
         F: Fn(RunData) -> Result<RunData>,
@@ -162,114 +1374,933 @@ impl Run {
         Ok(())
     }
 
+    /// Put together what the run's output goes through on its way to the output log (which may be
+    /// split, limited or encrypted): copies to other files (`--tee`) and to the terminal
+    /// (`terminal`, with `--fg` or `--interactive`), system logs (`--log-to`), stripping escape sequences
+    /// (`--strip-ansi`) and rate limiting (`--rate-limit`).
+    /// Returns `None` if the run can just write its output into the output log itself.
+    fn output_pipeline(
+        &self,
+        label: Option<&str>,
+        terminal: Option<Box<dyn Write>>,
+        options: &StartOptions,
+    ) -> std::result::Result<Option<Box<dyn Write>>, ForkedError> {
+        let output_error = |e: std::io::Error| ForkedError::CouldntCreateOutputFile {
+            message: e.to_string(),
+        };
+        if let Some(log_file) = &options.log_file {
+            File::create(log_file).map_err(output_error)?;
+            // Whatever writes to the run's output file below actually writes to the log file
+            std::os::unix::fs::symlink(log_file, self.get_output_file()).map_err(output_error)?;
+        }
+
+        let log: Box<dyn Write> = match (
+            &options.encryption_key,
+            options.log_layout(),
+            options.log_limit(),
+        ) {
+            (Some(key), _, _) => Box::new(Encryptor::new(
+                File::create(self.get_encrypted_output_file()).map_err(output_error)?,
+                key,
+            )),
+            (None, LogLayout::Single, None)
+                if options.redact.is_empty()
+                    && options.tee.is_empty()
+                    && terminal.is_none()
+                    && options.log_to.is_empty()
+                    && options.rate_limit.is_none()
+                    && !options.strip_ansi =>
+            {
+                return Ok(None)
+            }
+            (None, layout, limit) => {
+                Box::new(LogWriter::new(&self.run_directory, layout, limit).map_err(output_error)?)
+            }
+        };
+
+        let copies = options
+            .tee
+            .iter()
+            .map(|path| Ok(Box::new(File::create(path)?) as Box<dyn Write>))
+            .collect::<std::io::Result<Vec<_>>>()
+            .map_err(output_error)?;
+        let log: Box<dyn Write> = match copies.is_empty() {
+            true => log,
+            false => Box::new(Tee::new(log, copies)),
+        };
+
+        let logs = options
+            .log_to
+            .iter()
+            .map(|target| {
+                target
+                    .connect(&self.id, label)
+                    .map_err(|e| ForkedError::CouldntConnectToLog {
+                        log: target.name().to_string(),
+                        message: e.to_string(),
+                    })
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let log: Box<dyn Write> = match logs.is_empty() {
+            true => log,
+            false => Box::new(Forwarder::new(log, logs)),
+        };
+
+        // Whatever is recorded is stripped of colors and such, but the terminal still shows them
+        let log: Box<dyn Write> = match options.strip_ansi {
+            true => Box::new(AnsiStripper::new(log)),
+            false => log,
+        };
+        let log: Box<dyn Write> = match terminal {
+            Some(terminal) => Box::new(Tee::new(log, vec![terminal])),
+            None => log,
+        };
+
+        Ok(Some(match options.rate_limit {
+            Some(max_lines) => Box::new(RateLimiter::new(log, max_lines, options.sample_every)),
+            None => log,
+        }))
+    }
+
     fn spawn_process(
         &self,
         command: Vec<String>,
         label: Option<String>,
-    ) -> std::result::Result<Child, ForkedError> {
-        let output_file_path = self.get_output_file();
-        let output_file = std::fs::File::create(output_file_path).map_err(|e| {
-            ForkedError::CouldntCreateOutputFile {
-                message: e.to_string(),
+        stdin: Option<OwnedFd>,
+        terminal: Option<OwnedFd>,
+        options: &StartOptions,
+    ) -> std::result::Result<(Child, Option<OutputPipe>), ForkedError> {
+        let output_error = |e: std::io::Error| ForkedError::CouldntCreateOutputFile {
+            message: e.to_string(),
+        };
+        // Interactive runs get a terminal of their own, which whoever attaches to them sees
+        let pty = match options.interactive {
+            true => Some(session::open_pty(options.width, options.height).map_err(output_error)?),
+            false => None,
+        };
+        let terminal: Option<Box<dyn Write>> = match &pty {
+            Some(pty) => {
+                let attachments = Attachments::default();
+                // The size of the run's terminal is up to whoever is attached, unless it was given
+                let fixed_size = options.width.is_some() || options.height.is_some();
+                let master = pty.master.try_clone().map_err(output_error)?;
+                session::serve(&self.get_attach_socket(), master, &attachments, fixed_size)
+                    .map_err(output_error)?;
+                Some(Box::new(attachments))
             }
-        })?;
-        let output_file_raw = output_file.as_raw_fd();
+            None => terminal.map(|terminal| Box::new(File::from(terminal)) as Box<dyn Write>),
+        };
+        let log = self.output_pipeline(label.as_deref(), terminal, options)?;
+        let (output, output_pipe): (OwnedFd, _) = match (log, &pty) {
+            (None, _) => (
+                std::fs::File::create(self.get_output_file())
+                    .map_err(output_error)?
+                    .into(),
+                None,
+            ),
+            (Some(log), Some(pty)) => (
+                pty.slave.try_clone().map_err(output_error)?,
+                Some((
+                    Box::new(File::from(pty.master.try_clone().map_err(output_error)?))
+                        as Box<dyn Read>,
+                    log,
+                )),
+            ),
+            (Some(log), None) => {
+                let (reader, writer) = std::io::pipe().map_err(output_error)?;
+                (
+                    writer.into(),
+                    Some((Box::new(reader) as Box<dyn Read>, log)),
+                )
+            }
+        };
+        let output_clone = output.try_clone().map_err(output_error)?;
 
-        let gid = getpgid(None).unwrap(); // this will always succeed, since we are getting the pgid of the current process
+        let (stdin, input_copy) = match stdin {
+            None if options.interactive => {
+                (Stdio::from(output.try_clone().map_err(output_error)?), None)
+            }
+            None => (Stdio::null(), None),
+            Some(stdin) if !options.save_stdin => (Stdio::from(stdin), None),
+            Some(stdin) => {
+                let input_log = File::create(self.get_input_file()).map_err(output_error)?;
+                let (reader, writer) = std::io::pipe().map_err(output_error)?;
+                (
+                    Stdio::from(reader),
+                    Some((File::from(stdin), input_log, writer)),
+                )
+            }
+        };
 
-        let process = std::process::Command::new(command.first().unwrap())
+        let sandbox = options
+            .sandbox
+            .zip(std::env::current_dir().ok())
+            .map(|(preset, directory)| Sandbox::new(preset, &directory));
+        let mut process = match &sandbox {
+            Some(sandbox) => {
+                let mut process = std::process::Command::new("bwrap");
+                process
+                    .args(&sandbox.bwrap_arguments)
+                    .arg("--")
+                    .arg(command.first().unwrap());
+                process
+            }
+            None => std::process::Command::new(command.first().unwrap()),
+        };
+        // The monitor ignores SIGHUP (see `detach`), but the run should get it like any process
+        // SAFETY: we only make a system call in the child, before it execs
+        unsafe {
+            process.pre_exec(|| {
+                signal(Signal::SIGHUP, SigHandler::SigDfl)?;
+                Ok(())
+            });
+        }
+        if let Some(oom_score_adj) = options.oom_score_adj {
+            let oom_score_adj = oom_score_adj.to_string();
+            // SAFETY: we only write to a file in the child, before it execs
+            unsafe {
+                process
+                    .pre_exec(move || std::fs::write("/proc/self/oom_score_adj", &oom_score_adj));
+            }
+        }
+        if !options.ulimits.is_empty() {
+            let ulimits = options.ulimits.clone();
+            // SAFETY: we only make system calls in the child, before it execs
+            unsafe {
+                process.pre_exec(move || {
+                    for ulimit in &ulimits {
+                        ulimit.apply()?;
+                    }
+                    Ok(())
+                });
+            }
+        }
+        if options.core_dumps {
+            // SAFETY: we only make system calls in the child, before it execs
+            unsafe {
+                process.pre_exec(|| Ok(core_dump::enable()?));
+            }
+        }
+        if let Some(umask) = options.umask {
+            // SAFETY: we only make a system call in the child, before it execs
+            unsafe {
+                process.pre_exec(move || {
+                    nix::sys::stat::umask(nix::sys::stat::Mode::from_bits_truncate(umask));
+                    Ok(())
+                });
+            }
+        }
+        if let Some(cpus) = options.cpus.clone() {
+            // SAFETY: we only make a system call in the child, before it execs
+            unsafe {
+                process.pre_exec(move || Ok(cpus.apply()?));
+            }
+        }
+        match options.interactive {
+            // SAFETY: we only make system calls in the child, before it execs
+            true => unsafe {
+                process.pre_exec(session::take_terminal);
+            },
+            // Give the run its own process group, so that signalling it doesn't also hit us
+            false => {
+                process.process_group(0);
+            }
+        }
+        if options.no_network {
+            let (uid, gid) = (getuid(), getgid());
+            // SAFETY: we only make system calls and write to files in the child, before it execs
+            unsafe {
+                process.pre_exec(move || sandbox::isolate_network(uid, gid));
+            }
+        }
+        let process = process
             .args(&command[1..])
-            .stdout(unsafe { std::process::Stdio::from_raw_fd(output_file_raw) })
-            .stderr(unsafe { std::process::Stdio::from_raw_fd(output_file_raw) })
-            .stdin(std::process::Stdio::null())
+            .envs(&options.env)
+            .env(RUN_ID_ENV, &self.id)
+            .envs(options.width.map(|width| ("COLUMNS", width.to_string())))
+            .envs(options.height.map(|height| ("LINES", height.to_string())))
+            .stdout(output)
+            .stderr(output_clone)
+            .stdin(stdin)
             .spawn()
             .map_err(|e| ForkedError::FailedToSpawn {
                 command: command.first().unwrap().to_string(),
                 message: e.to_string(),
             })?;
 
-        setpgid(Pid::from_raw(0), Pid::from_raw(0))
-            .map_err(|e| ForkedError::CouldntSetProcessGroup(e.desc().to_string()))?;
+        if let Some((mut input, mut input_log, mut writer)) = input_copy {
+            std::thread::spawn(move || copy_input(&mut input, &mut input_log, &mut writer));
+        }
 
         self.set_data(&RunData {
             command,
             label,
+            name: Some(generate_name(&self.id)),
+            parent: options.parent.clone(),
             start_datetime: Utc::now(),
+            artifacts: options.artifacts.clone(),
+            expected_duration: options.expect,
+            stall_after: options.stall_after,
+            working_directory: std::env::current_dir().ok(),
+            oom_score_adj: options.oom_score_adj,
+            ulimits: options.ulimits.clone(),
+            umask: options.umask,
+            cpus: options.cpus.clone(),
+            no_network: options.no_network,
+            sandbox: sandbox.clone(),
+            environment: options.environment.clone(),
+            log_to: options.log_to.clone(),
+            encrypted: options.encryption_key.is_some(),
+            lock: options.lock.clone(),
+            boot_id: proc::boot_id().ok(),
+            owner: Some(events::current_user()),
+            schema_version: SCHEMA_VERSION,
 
-            state: RunDataState::Running { pgid: gid },
+            state: RunDataState::Running {
+                pgid: Pid::from_raw(process.id() as i32),
+                pid: Some(Pid::from_raw(process.id() as i32)),
+                monitor: Some(getpid()),
+            },
         })
         .map_err(|e| ForkedError::CouldntSetData {
             message: e.to_string(),
         })?;
+        self.record_event(Event::Started {
+            pid: process.id() as i32,
+        });
 
-        Ok(process)
+        Ok((process, output_pipe))
     }
 
-    pub fn start(&self, command: Vec<String>, label: Option<String>) -> Result<()> {
+    /// Make this run track an existing process, which we didn't start.
+    pub fn adopt(
+        &self,
+        pid: Pid,
+        label: Option<String>,
+        output_file: Option<PathBuf>,
+    ) -> Result<()> {
+        let info = proc::process_info(pid.as_raw())?;
+        let command = proc::process_command(pid.as_raw())?;
+        let start_datetime =
+            proc::boot_time()? + chrono::Duration::milliseconds((info.start_time * 1000.0) as i64);
+
+        match output_file {
+            Some(output_file) => std::os::unix::fs::symlink(
+                output_file
+                    .canonicalize()
+                    .with_context(|| format!("Could not find {:?}", output_file))?,
+                self.get_output_file(),
+            )?,
+            None => {
+                std::fs::File::create(self.get_output_file())?;
+            }
+        }
+
+        self.set_data(&RunData {
+            label,
+            name: Some(generate_name(&self.id)),
+            parent: None,
+            command,
+            start_datetime,
+            artifacts: Vec::new(),
+            expected_duration: None,
+            stall_after: None,
+            working_directory: std::fs::read_link(format!("/proc/{}/cwd", pid)).ok(),
+            oom_score_adj: None,
+            ulimits: Vec::new(),
+            umask: None,
+            cpus: None,
+            no_network: false,
+            sandbox: None,
+            environment: None,
+            log_to: Vec::new(),
+            encrypted: false,
+            lock: None,
+            boot_id: proc::boot_id().ok(),
+            owner: Some(events::current_user()),
+            schema_version: SCHEMA_VERSION,
+            state: RunDataState::Running {
+                pgid: Pid::from_raw(info.pgid),
+                pid: Some(pid),
+                monitor: None,
+            },
+        })?;
+        self.record_event(Event::Adopted { pid: pid.as_raw() });
+
+        self.watch(Watched::Process(pid))
+    }
+
+    /// Whether this run is supposedly running, but whatever was monitoring it has died, so that
+    /// it would never be recorded as done.
+    pub fn is_orphaned(&self, data: &RunData) -> bool {
+        match data.state {
+            RunDataState::Running {
+                monitor: Some(monitor),
+                ..
+            }
+            | RunDataState::Queued { monitor, .. } => !proc::process_is_alive(monitor.as_raw()),
+            _ => false,
+        }
+    }
+
+    /// Make sure the run is the current user's to `action` (e.g. "remove"), unless `force`: in
+    /// stores shared by several users, one shouldn't get in the way of someone else's runs by
+    /// mistake.
+    pub fn check_owner(&self, data: &RunData, action: &str, force: bool) -> Result<()> {
+        match &data.owner {
+            Some(owner) if !force && *owner != events::current_user() => Err(Error::msg(format!(
+                "Run '{}' belongs to {} (pass --force to {} it anyway)",
+                self.id, owner, action
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the run is still marked as running, even though its process is gone and has been
+    /// for long enough that its monitor should have noticed (e.g. because the monitor died, or its
+    /// PID was reused since).
+    pub fn is_stuck(&self, data: &RunData) -> bool {
+        let RunDataState::Running { pgid, pid, .. } = data.state else {
+            return false;
+        };
+        let is_alive = match pid {
+            Some(pid) => process_started_at(pid, data.start_datetime),
+            None => proc::process_group_is_alive(pgid.as_raw()),
+        };
+        let last_updated = std::fs::metadata(self.get_data_file())
+            .and_then(|metadata| metadata.modified())
+            .map(DateTime::<Utc>::from);
+        !is_alive
+            && last_updated.is_ok_and(|last_updated| {
+                (Utc::now() - last_updated).to_std().unwrap_or_default() > STUCK_AFTER
+            })
+    }
+
+    /// Start monitoring this run again, after its monitor died. Returns whether the run is still
+    /// running; if it isn't, it is immediately marked as done.
+    pub fn readopt(&self) -> Result<bool> {
+        let data = self.get_data()?;
+        let (pgid, pid, monitor) = match data.state {
+            RunDataState::Running { pgid, pid, monitor } => (pgid, pid, monitor),
+            RunDataState::Queued { monitor, .. } if proc::process_is_alive(monitor.as_raw()) => {
+                return Err(Error::msg("Run is still being monitored"));
+            }
+            // Nothing is going to start it anymore
+            RunDataState::Queued { .. } => return self.mark_done(-2).map(|_| false),
+            RunDataState::Done { .. } => return Err(Error::msg("Run is not running")),
+        };
+        if let Some(monitor) = monitor {
+            if proc::process_is_alive(monitor.as_raw()) {
+                return Err(Error::msg("Run is still being monitored"));
+            }
+        }
+
+        // The PID may have been reused by now, so make sure it is the same process
+        let watched = match pid {
+            Some(pid) if process_started_at(pid, data.start_datetime) => Watched::Process(pid),
+            Some(_) => return self.mark_done(-3).map(|_| false),
+            // Runs from before we recorded PIDs; all we have is the process group
+            None => Watched::ProcessGroup(pgid),
+        };
+        if !watched.is_alive() {
+            self.mark_done(-3)?;
+            return Ok(false);
+        }
+        self.watch(watched)?;
+        self.record_event(Event::Readopted);
+        Ok(true)
+    }
+
+    /// Cancel a queued run, before it gets to start.
+    pub fn cancel(&self) -> Result<()> {
+        let RunDataState::Queued { monitor, .. } = self.get_data()?.state else {
+            return Err(Error::msg("Run is not queued"));
+        };
+        kill(monitor, Signal::SIGKILL).with_context(|| "Couldn't stop the run's monitor")?;
+        self.record_event(Event::Cancelled);
+        self.mark_done(-1)
+    }
+
+    /// Mark the run as done with the given exit code, without any resource usage (i.e. without
+    /// having properly waited for it). Runs which are already done are left as they are, so as
+    /// not to overwrite what a proper monitor recorded.
+    pub fn mark_done(&self, exit_code: i32) -> Result<()> {
+        if let RunDataState::Done { .. } = self.get_data()?.state {
+            return Ok(());
+        }
+        self.record_event(Event::Finished { exit_code });
+        let end_datetime = Utc::now();
+        self.update_data(|run_data| match run_data.state {
+            RunDataState::Running { .. } | RunDataState::Queued { .. } => Ok(RunData {
+                state: RunDataState::Done {
+                    exit_code,
+                    end_datetime,
+                    resource_usage: None,
+                    failed_output: None,
+                },
+                ..run_data
+            }),
+            RunDataState::Done { .. } => Ok(run_data),
+        })
+    }
+
+    /// Fork a process which waits for `watched` (which need not be our child) to exit, and then
+    /// marks the run as done. Since we can't wait for it, its exit code is unknown.
+    fn watch(&self, watched: Watched) -> Result<()> {
+        match fork().map_err(|e| Error::msg(format!("Failed to fork: error code {}", e)))? {
+            Fork::Child => {
+                detach().expect("couldn't detach forked child process");
+                let result = self
+                    .update_data(|run_data| match run_data.state {
+                        RunDataState::Running { pgid, pid, .. } => Ok(RunData {
+                            state: RunDataState::Running {
+                                pgid,
+                                pid,
+                                monitor: Some(getpid()),
+                            },
+                            ..run_data
+                        }),
+                        _ => Ok(run_data),
+                    })
+                    .and_then(|_| {
+                        while watched.is_alive() {
+                            std::thread::sleep(std::time::Duration::from_secs(1));
+                        }
+                        self.mark_done(-3)
+                    });
+                std::process::exit(if result.is_ok() { 0 } else { 1 });
+            }
+            Fork::Parent(_) => Ok(()),
+        }
+    }
+
+    /// Mark the run as queued, waiting for `lock` before starting.
+    fn mark_queued(
+        &self,
+        lock: &str,
+        command: &[String],
+        label: &Option<String>,
+        options: &StartOptions,
+    ) -> std::result::Result<(), ForkedError> {
+        self.set_data(&RunData {
+            command: command.to_vec(),
+            label: label.clone(),
+            name: Some(generate_name(&self.id)),
+            parent: options.parent.clone(),
+            start_datetime: Utc::now(),
+            artifacts: options.artifacts.clone(),
+            expected_duration: options.expect,
+            stall_after: options.stall_after,
+            working_directory: std::env::current_dir().ok(),
+            oom_score_adj: options.oom_score_adj,
+            ulimits: options.ulimits.clone(),
+            umask: options.umask,
+            cpus: options.cpus.clone(),
+            no_network: options.no_network,
+            sandbox: None,
+            environment: options.environment.clone(),
+            log_to: options.log_to.clone(),
+            encrypted: options.encryption_key.is_some(),
+            lock: options.lock.clone(),
+            boot_id: proc::boot_id().ok(),
+            owner: Some(events::current_user()),
+            schema_version: SCHEMA_VERSION,
+            state: RunDataState::Queued {
+                lock: lock.to_string(),
+                monitor: getpid(),
+            },
+        })
+        .map_err(|e| ForkedError::CouldntSetData {
+            message: e.to_string(),
+        })?;
+        self.record_event(Event::Queued {
+            lock: lock.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Take the lock backed by `lock_file`, marking the run as queued (and calling `on_queued`)
+    /// if we have to wait for it. The lock is held for as long as the returned file is open.
+    fn take_lock<F>(
+        &self,
+        lock_file: &Path,
+        command: &[String],
+        label: &Option<String>,
+        options: &StartOptions,
+        on_queued: &mut F,
+    ) -> std::result::Result<File, ForkedError>
+    where
+        F: FnMut(&str),
+    {
+        let lock = options.lock.clone().unwrap_or_default();
+        let lock_error = |e: std::io::Error| ForkedError::CouldntTakeLock {
+            lock: lock.clone(),
+            message: e.to_string(),
+        };
+
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(lock_file)
+            .map_err(lock_error)?;
+        match file.try_lock() {
+            Ok(()) => return Ok(file),
+            Err(TryLockError::WouldBlock) => (),
+            Err(TryLockError::Error(e)) => return Err(lock_error(e)),
+        }
+
+        self.mark_queued(&lock, command, label, options)?;
+        on_queued(&lock);
+
+        file.lock().map_err(lock_error)?;
+        Ok(file)
+    }
+
+    /// Take a slot of each of the run's quotas, marking the run as queued (and calling
+    /// `on_queued`) while it waits for one, or failing if the quotas are to be enforced by
+    /// rejecting runs. The slots are held for as long as the returned files are open.
+    fn take_quota_slots<F>(
+        &self,
+        command: &[String],
+        label: &Option<String>,
+        options: &StartOptions,
+        on_queued: &mut F,
+    ) -> std::result::Result<Vec<File>, ForkedError>
+    where
+        F: FnMut(&str),
+    {
+        let mut taken = Vec::new();
+        for slots in &options.quota {
+            let lock_error = |e: std::io::Error| ForkedError::CouldntTakeLock {
+                lock: slots.name.clone(),
+                message: e.to_string(),
+            };
+            let mut waiting = false;
+            let file = loop {
+                if let Some(file) = slots.try_take().map_err(lock_error)? {
+                    break file;
+                }
+                if options.reject_over_quota {
+                    return Err(ForkedError::QuotaExceeded {
+                        quota: slots.name.clone(),
+                        limit: slots.limit,
+                    });
+                }
+                // A slot could be freed by any of the runs holding one, so there's no single lock
+                // to block on
+                if !waiting {
+                    waiting = true;
+                    self.mark_queued(&slots.name, command, label, options)?;
+                    on_queued(&slots.name);
+                }
+                std::thread::sleep(quota::POLL_INTERVAL);
+            };
+            taken.push(file);
+        }
+        Ok(taken)
+    }
+
+    pub fn start(
+        &self,
+        command: Vec<String>,
+        label: Option<String>,
+        lock_file: Option<PathBuf>,
+        options: &StartOptions,
+    ) -> Result<()> {
         assert!(!command.is_empty());
 
+        if options.snapshot {
+            let directory = match &options.working_directory {
+                Some(directory) => directory.clone(),
+                None => std::env::current_dir()?,
+            };
+            let store_directory = options.store_directory.as_deref();
+            if let Err(e) = self.snapshot_working_directory(&directory, store_directory) {
+                std::fs::remove_dir_all(&self.run_directory)?;
+                return Err(e.context("Could not snapshot the working directory"));
+            }
+        }
+
         let (sender, receiver) = ipc_channel::ipc::channel::<Message>()?;
 
         #[derive(Serialize, Deserialize, Debug)]
         enum Message {
             Started,
+            /// Queued, waiting for the given lock.
+            Queued(String),
             Err(ForkedError),
         }
 
-        setpgid(Pid::from_raw(0), Pid::from_raw(0))
-            .map_err(|e| Error::msg(format!("couldnt set run pgid: {}", e.desc())))?;
-
         match fork().map_err(|e| Error::msg(format!("Failed to fork: error code {}", e)))? {
             Fork::Child => {
-                close_fd().expect("couldn't close file descriptors in forked child process");
-                match self.spawn_process(command, label) {
-                    Ok(mut process) => {
-                        sender.send(Message::Started)?;
+                // Once queued, whoever started the run has moved on, so there's no one to report to
+                let mut queued = false;
+                // This has to be done before detaching, which replaces our standard input
+                let stdin = match options.feeds_stdin() {
+                    true => std::io::stdin().as_fd().try_clone_to_owned().ok(),
+                    false => None,
+                };
+                // Likewise for our standard output, which the run's output is copied to with `--fg`
+                let terminal = match options.fg {
+                    true => std::io::stdout().as_fd().try_clone_to_owned().ok(),
+                    false => None,
+                };
+                let mut on_queued = |lock: &str| {
+                    if !queued {
+                        queued = true;
+                        let _ = sender.send(Message::Queued(lock.to_string()));
+                    }
+                };
+                let spawned = detach()
+                    .map_err(|e| ForkedError::CouldntDetach(e.to_string()))
+                    .and_then(|_| {
+                        // Only this process (which becomes the run's monitor) moves there
+                        if let Some(directory) = &options.working_directory {
+                            std::env::set_current_dir(directory).map_err(|e| {
+                                ForkedError::CouldntEnterDirectory {
+                                    directory: directory.to_string_lossy().into_owned(),
+                                    message: e.to_string(),
+                                }
+                            })?;
+                        }
+                        let lock = match &lock_file {
+                            Some(lock_file) => Some(self.take_lock(
+                                lock_file,
+                                &command,
+                                &label,
+                                options,
+                                &mut on_queued,
+                            )?),
+                            None => None,
+                        };
+                        let slots =
+                            self.take_quota_slots(&command, &label, options, &mut on_queued)?;
+                        if let (Some(annotate), Some(terminal)) = (options.annotate, &terminal) {
+                            let title = format!(
+                                "rum: {}",
+                                label.clone().unwrap_or_else(|| shell_words::join(&command))
+                            );
+                            let _ = nix::unistd::write(
+                                terminal,
+                                annotate.start_group(&title).as_bytes(),
+                            );
+                        }
+                        let spawned =
+                            self.spawn_process(command, label, stdin, terminal, options)?;
+                        Ok((spawned, (lock, slots)))
+                    });
+                // We mustn't return to whatever the parent was doing (e.g. the daemon's loop)
+                let result = (|| -> Result<()> {
+                    match spawned {
+                        Ok(((mut process, output_pipe), _lock)) => {
+                            if !queued {
+                                sender.send(Message::Started)?;
+                            }
 
-                        match process.wait() {
-                            Ok(exit_status) => self.update_data(|run_data| {
-                                Ok(RunData {
-                                    state: RunDataState::Done {
-                                        exit_code: exit_status.code().unwrap_or(-1),
-                                        end_datetime: Utc::now(),
-                                    },
-                                    ..run_data
-                                })
-                            }),
-                            Err(_) => self.update_data(|run_data| {
+                            // Don't hold the run's output up while the hook runs
+                            let start_hook = options.hooks_directory.as_deref().and_then(|hooks| {
+                                let data = self.get_data().ok()?;
+                                hooks::start_hook(hooks, Hook::OnStart, self, &data).ok()?
+                            });
+
+                            // There's no one to tell if this fails, and it's no reason not to run
+                            let inhibitor = options
+                                .inhibit_sleep
+                                .then(|| SleepInhibitor::take(self).ok())
+                                .flatten();
+
+                            let metrics = MetricsRecorder::start(
+                                self.get_metrics_file(),
+                                Pid::from_raw(process.id() as i32),
+                            );
+
+                            if let Some((mut reader, log)) = output_pipe {
+                                let mut log = Redactor::new(log, options.redact.clone());
+                                // Even if we fail to write the log, we still want to wait for the process
+                                let _ = std::io::copy(&mut reader, &mut log);
+                                let _ = log.finish();
+                            }
+
+                            let (exit_status, resource_usage) =
+                                match ResourceUsage::wait_for(&mut process) {
+                                    Ok((exit_status, usage)) => (Ok(exit_status), Some(usage)),
+                                    Err(e) => (Err(e), None),
+                                };
+                            let exit_code = match exit_status {
+                                Ok(exit_status) => exit_status.code().unwrap_or(-1),
+                                Err(_) => -2,
+                            };
+                            let end_datetime = Utc::now();
+                            drop(inhibitor);
+                            metrics.stop();
+
+                            // Failing to collect some artifacts shouldn't prevent us from recording that the run is done
+                            let _ = self.collect_artifacts(&options.artifacts);
+                            if options.core_dumps
+                                && exit_status.is_ok_and(|status| status.core_dumped())
+                            {
+                                let _ = self.capture_core_dump(process.id());
+                            }
+                            let failed_output = match &options.fail_on_regex {
+                                Some(regex) => self
+                                    .find_in_output(regex, options.encryption_key.as_ref())
+                                    .unwrap_or(None),
+                                None => None,
+                            };
+
+                            self.update_data(|run_data| {
                                 Ok(RunData {
                                     state: RunDataState::Done {
-                                        exit_code: -2,
-                                        end_datetime: Utc::now(),
+                                        exit_code,
+                                        end_datetime,
+                                        resource_usage,
+                                        failed_output: failed_output.clone(),
                                     },
                                     ..run_data
                                 })
-                            }),
+                            })?;
+                            self.record_event(Event::Finished { exit_code });
+                            if options.interactive {
+                                let _ = std::fs::remove_file(self.get_attach_socket());
+                            }
+
+                            if let Some(hooks) = &options.hooks_directory {
+                                // As with telemetry, there's no one to tell if the hooks fail
+                                let data = self.get_data()?;
+                                let _ = hooks::run_hook(hooks, Hook::OnFinish, self, &data);
+                                if !data.state.is_success() {
+                                    let _ = hooks::run_hook(hooks, Hook::OnFail, self, &data);
+                                }
+                            }
+                            if let Some(mut start_hook) = start_hook {
+                                let _ = start_hook.wait();
+                            }
+
+                            if let Some(telemetry) = &options.telemetry {
+                                // There's no one to tell about it if this fails, anyway
+                                let _ =
+                                    telemetry::export_run_span(telemetry, self, &self.get_data()?);
+                            }
+                            if let Some(archive) = &options.archive {
+                                if archive::archive_run(archive, self).is_ok() {
+                                    self.record_event(Event::Archived {
+                                        location: archive::location(archive, self),
+                                    });
+                                }
+                            }
+                            if let Some(email) = &options.email {
+                                let data = self.get_data()?;
+                                if email::should_send(email, &data) {
+                                    let _ = email::send_run_summary(
+                                        email,
+                                        self,
+                                        &data,
+                                        options.encryption_key.as_ref(),
+                                    );
+                                }
+                            }
+                            Ok(())
+                        }
+                        Err(e) if queued => {
+                            self.mark_done(-2)?;
+                            Err(Error::from(e))
+                        }
+                        Err(e) => {
+                            sender.send(Message::Err(e.clone()))?;
+                            std::fs::remove_dir_all(&self.run_directory)?;
+                            Err(Error::from(e))
                         }
                     }
-                    Err(e) => {
-                        sender.send(Message::Err(e.clone()))?;
-                        std::fs::remove_dir_all(&self.run_directory)?;
-                        Err(Error::from(e))
-                    }
-                }
+                })();
+                std::process::exit(if result.is_ok() { 0 } else { 1 });
             }
             Fork::Parent(_) => {
                 let message = receiver
                     .recv()
                     .map_err(|_| Error::msg("Failed to communicate with forked process"))?;
-                match message {
-                    Message::Err(e) => Err(Error::from(e)),
-                    Message::Started => {
-                        println!("Started run {}", self.id);
-                        Ok(())
+                let message = match message {
+                    Message::Err(e) => return Err(Error::from(e)),
+                    Message::Started => format!("Started run {}", self.id),
+                    Message::Queued(lock) => {
+                        format!("Queued run {} (waiting for lock '{}')", self.id, lock)
+                    }
+                };
+                match options.id_only {
+                    true => {
+                        eprintln!("{}", message);
+                        println!("{}", self.id);
                     }
+                    false => println!("{}", message),
                 }
+                Ok(())
             }
         }
     }
 }
 
+/// Copy `input` into both `input_log` and `writer` (the run's standard input), until the input
+/// ends or the run stops reading it.
+fn copy_input(input: &mut File, input_log: &mut File, writer: &mut PipeWriter) {
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let how_much_was_read = match input.read(&mut buffer) {
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+        let chunk = &buffer[..how_much_was_read];
+        // The saved copy is best-effort, but there's no point in going on once the run is gone
+        let _ = input_log.write_all(chunk);
+        if writer.write_all(chunk).is_err() {
+            return;
+        }
+    }
+}
+
+/// Detach the current (forked) process from the terminal and session it was started from, so that
+/// it survives them going away (e.g. when an SSH connection drops).
+fn detach() -> std::io::Result<()> {
+    setsid()?;
+    // SAFETY: we are not installing a handler, just ignoring the signal
+    unsafe { signal(Signal::SIGHUP, SigHandler::SigIgn) }?;
+    let null = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")?;
+    for fd in 0..=2 {
+        dup2(null.as_raw_fd(), fd)?;
+    }
+    Ok(())
+}
+
+/// What to wait for, when monitoring a process which isn't our child.
+#[derive(Debug, Clone, Copy)]
+enum Watched {
+    Process(Pid),
+    ProcessGroup(Pid),
+}
+
+impl Watched {
+    fn is_alive(&self) -> bool {
+        match self {
+            Watched::Process(pid) => proc::process_is_alive(pid.as_raw()),
+            Watched::ProcessGroup(pgid) => proc::process_group_is_alive(pgid.as_raw()),
+        }
+    }
+}
+
+/// Check whether the process with the given PID is the one which started at `start_datetime`,
+/// and not a new one which happens to have been given the same PID.
+fn process_started_at(pid: Pid, start_datetime: DateTime<Utc>) -> bool {
+    let (Ok(info), Ok(boot_time)) = (proc::process_info(pid.as_raw()), proc::boot_time()) else {
+        return false;
+    };
+    let started = boot_time + chrono::Duration::milliseconds((info.start_time * 1000.0) as i64);
+    // The boot time only has a precision of one second, and we record the start time of the runs
+    // we spawn ourselves only after spawning them
+    (started - start_datetime).num_seconds().abs() <= 5
+}
+
 mod serde_nix_pid {
     use nix::unistd::Pid;
     use serde::{Deserialize, Deserializer, Serializer};
@@ -287,4 +2318,26 @@ mod serde_nix_pid {
     {
         Ok(Pid::from_raw(i32::deserialize(deserializer)?))
     }
+
+    pub mod option {
+        use nix::unistd::Pid;
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S>(pid: &Option<Pid>, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            match pid {
+                Some(pid) => serializer.serialize_some(&pid.as_raw()),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Pid>, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            Ok(Option::<i32>::deserialize(deserializer)?.map(Pid::from_raw))
+        }
+    }
 }
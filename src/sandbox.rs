@@ -0,0 +1,137 @@
+use std::{
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::Path,
+};
+
+use anyhow::{Error, Result};
+use nix::{
+    libc,
+    sched::{unshare, CloneFlags},
+    unistd::{Gid, Uid},
+};
+use serde::{Deserialize, Serialize};
+
+/// How much a sandboxed run (see `--sandbox`) is allowed to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum SandboxPreset {
+    /// Everything is read-only, except for the current directory and a private /tmp
+    ReadOnly,
+    /// Only system directories (e.g. /usr and /etc) are visible, read-only; the current directory
+    /// is writable, and the home directory and /tmp are private and empty
+    Strict,
+}
+
+/// How a run was sandboxed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sandbox {
+    pub preset: SandboxPreset,
+    /// The arguments bubblewrap was given (before the command).
+    pub bwrap_arguments: Vec<String>,
+}
+
+impl Sandbox {
+    pub fn new(preset: SandboxPreset, directory: &Path) -> Self {
+        let mut arguments: Vec<String> = Vec::new();
+        let mut add = |new: &[&str]| arguments.extend(new.iter().map(|a| a.to_string()));
+        match preset {
+            SandboxPreset::ReadOnly => add(&["--ro-bind", "/", "/"]),
+            SandboxPreset::Strict => {
+                for system_directory in ["/usr", "/bin", "/sbin", "/lib", "/lib64", "/etc", "/opt"]
+                {
+                    add(&["--ro-bind-try", system_directory, system_directory]);
+                }
+                if let Some(home) = directories::BaseDirs::new().map(|d| d.home_dir().to_owned()) {
+                    add(&["--tmpfs", &home.to_string_lossy()]);
+                }
+            }
+        }
+        let directory = directory.to_string_lossy();
+        add(&["--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp"]);
+        // This comes last, so that it isn't hidden by e.g. the private /tmp
+        add(&["--bind", &directory, &directory, "--chdir", &directory]);
+        Self {
+            preset,
+            bwrap_arguments: arguments,
+        }
+    }
+}
+
+/// Check whether bubblewrap is installed, so as to fail with a helpful message before even trying
+/// to start a sandboxed run.
+pub fn check_bwrap() -> Result<()> {
+    let is_installed = std::env::var_os("PATH").is_some_and(|path| {
+        std::env::split_paths(&path).any(|directory| directory.join("bwrap").is_file())
+    });
+    match is_installed {
+        true => Ok(()),
+        false => Err(Error::msg(
+            "--sandbox needs bubblewrap (`bwrap`) to be installed",
+        )),
+    }
+}
+
+/// Check whether `isolate_network` can work, so as to fail with a helpful message before even
+/// trying to start a run.
+pub fn check_network_isolation() -> Result<()> {
+    if Uid::effective().is_root() {
+        return Ok(());
+    }
+    let setting_is = |path: &str, value: &str| {
+        std::fs::read_to_string(path).is_ok_and(|setting| setting.trim() == value)
+    };
+    if setting_is("/proc/sys/user/max_user_namespaces", "0")
+        || setting_is("/proc/sys/kernel/unprivileged_userns_clone", "0")
+        || setting_is(
+            "/proc/sys/kernel/apparmor_restrict_unprivileged_userns",
+            "1",
+        )
+    {
+        return Err(Error::msg(
+            "--no-network needs unprivileged user namespaces, which are disabled on this system \
+             (or run rum as root)",
+        ));
+    }
+    Ok(())
+}
+
+/// Move the current process into a network namespace of its own, where only the loopback
+/// interface exists, so that whatever it starts from then on can't reach the network.
+///
+/// Unless we are root, this requires a user namespace as well, in which our user and group are
+/// mapped to themselves.
+pub fn isolate_network(uid: Uid, gid: Gid) -> std::io::Result<()> {
+    if uid.is_root() {
+        unshare(CloneFlags::CLONE_NEWNET)?;
+    } else {
+        unshare(CloneFlags::CLONE_NEWUSER | CloneFlags::CLONE_NEWNET)?;
+        std::fs::write("/proc/self/setgroups", "deny")?;
+        std::fs::write("/proc/self/uid_map", format!("{uid} {uid} 1"))?;
+        std::fs::write("/proc/self/gid_map", format!("{gid} {gid} 1"))?;
+    }
+    // Plenty of programs expect to be able to talk to themselves over localhost
+    let _ = bring_loopback_up();
+    Ok(())
+}
+
+fn bring_loopback_up() -> std::io::Result<()> {
+    // SAFETY: these are plain system calls, on a request we fully initialize
+    unsafe {
+        let socket = match libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0) {
+            -1 => return Err(std::io::Error::last_os_error()),
+            fd => OwnedFd::from_raw_fd(fd),
+        };
+        let mut request: libc::ifreq = std::mem::zeroed();
+        for (i, c) in b"lo".iter().enumerate() {
+            request.ifr_name[i] = *c as libc::c_char;
+        }
+        if libc::ioctl(socket.as_raw_fd(), libc::SIOCGIFFLAGS, &mut request) == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        request.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short;
+        if libc::ioctl(socket.as_raw_fd(), libc::SIOCSIFFLAGS, &request) == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,262 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{Read, Write},
+    os::{
+        fd::{AsFd, AsRawFd, OwnedFd},
+        unix::net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::{Context, Result};
+use nix::{
+    poll::{poll, PollFd, PollFlags, PollTimeout},
+    pty::{openpty, Winsize},
+    sys::signal::{self, SigHandler, Signal},
+};
+use termion::raw::IntoRawMode;
+
+/// The key which detaches from an interactive run: Ctrl+].
+pub const DETACH_KEY: u8 = 0x1d;
+
+/// How much of the latest output is replayed to whoever attaches, so that they see where the run
+/// is at (e.g. its prompt) rather than a blank screen.
+const REPLAY_SIZE: usize = 16 * 1024;
+
+/// The size of the pseudo-terminal of interactive runs, unless told otherwise.
+const DEFAULT_SIZE: (u16, u16) = (80, 24);
+
+// What attached terminals send the run is framed as a type, a big-endian 16-bit length and that
+// many bytes of payload
+const FRAME_INPUT: u8 = 0;
+const FRAME_RESIZE: u8 = 1;
+
+nix::ioctl_write_ptr_bad!(set_window_size, nix::libc::TIOCSWINSZ, Winsize);
+nix::ioctl_write_int_bad!(set_controlling_terminal, nix::libc::TIOCSCTTY);
+
+/// Whether the terminal has been resized since we last checked, while attached.
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_resize(_: i32) {
+    RESIZED.store(true, Ordering::SeqCst);
+}
+
+/// A pseudo-terminal for an interactive run: the run gets the slave as its terminal, and we read
+/// its output from and write its input to the master.
+pub struct Pty {
+    pub master: OwnedFd,
+    pub slave: OwnedFd,
+}
+
+/// Open a pseudo-terminal of the given size (in columns and lines).
+pub fn open_pty(width: Option<u16>, height: Option<u16>) -> std::io::Result<Pty> {
+    let size = Winsize {
+        ws_col: width.unwrap_or(DEFAULT_SIZE.0),
+        ws_row: height.unwrap_or(DEFAULT_SIZE.1),
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let pty = openpty(&size, None)?;
+    Ok(Pty {
+        master: pty.master,
+        slave: pty.slave,
+    })
+}
+
+/// Make the process a session leader with its standard input (the slave of a [`Pty`]) as its
+/// controlling terminal, as a shell in a terminal emulator would be. Only meant to be called in
+/// the child, before it execs.
+pub fn take_terminal() -> std::io::Result<()> {
+    nix::unistd::setsid()?;
+    // SAFETY: the ioctl only takes an integer argument
+    unsafe { set_controlling_terminal(0, 0) }?;
+    Ok(())
+}
+
+fn set_size(fd: &impl AsRawFd, width: u16, height: u16) -> std::io::Result<()> {
+    let size = Winsize {
+        ws_col: width,
+        ws_row: height,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: the ioctl only reads the size we give it
+    unsafe { set_window_size(fd.as_raw_fd(), &size) }?;
+    Ok(())
+}
+
+/// The terminals attached to an interactive run, which its output is copied to (as it goes
+/// through the output pipeline, raw). Once dropped, at the end of the output, they are detached.
+#[derive(Default)]
+pub struct Attachments(Arc<Mutex<AttachmentsState>>);
+
+#[derive(Default)]
+struct AttachmentsState {
+    clients: Vec<UnixStream>,
+    /// The latest output, to replay to whoever attaches next.
+    recent: VecDeque<u8>,
+    /// Whether the output has ended, so there's nothing left to attach to.
+    closed: bool,
+}
+
+impl Write for Attachments {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut state = self.0.lock().unwrap();
+        state.recent.extend(buf);
+        let excess = state.recent.len().saturating_sub(REPLAY_SIZE);
+        state.recent.drain(..excess);
+        // Whoever went away is simply forgotten
+        state
+            .clients
+            .retain_mut(|client| client.write_all(buf).is_ok());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Attachments {
+    fn drop(&mut self) {
+        let mut state = self.0.lock().unwrap();
+        state.closed = true;
+        for client in state.clients.drain(..) {
+            let _ = client.shutdown(std::net::Shutdown::Both);
+        }
+    }
+}
+
+/// Let terminals attach to an interactive run through `socket`, in the background: whoever
+/// attaches is shown the latest output and then the output as it comes, and what they type goes
+/// to the run. Their terminal's size becomes the run's, unless `fixed_size`.
+pub fn serve(
+    socket: &Path,
+    master: OwnedFd,
+    attachments: &Attachments,
+    fixed_size: bool,
+) -> std::io::Result<()> {
+    let listener = UnixListener::bind(socket)?;
+    let master = Arc::new(File::from(master));
+    let state = attachments.0.clone();
+    std::thread::spawn(move || {
+        for client in listener.incoming().filter_map(|client| client.ok()) {
+            let (master, state) = (master.clone(), state.clone());
+            std::thread::spawn(move || serve_client(client, &master, &state, fixed_size));
+        }
+    });
+    Ok(())
+}
+
+fn serve_client(
+    mut client: UnixStream,
+    mut master: &File,
+    state: &Mutex<AttachmentsState>,
+    fixed_size: bool,
+) -> std::io::Result<()> {
+    {
+        let mut state = state.lock().unwrap();
+        if state.closed {
+            return Ok(());
+        }
+        let (first, second) = state.recent.as_slices();
+        client.write_all(first)?;
+        client.write_all(second)?;
+        state.clients.push(client.try_clone()?);
+    }
+
+    loop {
+        let mut header = [0; 3];
+        client.read_exact(&mut header)?;
+        let mut payload = vec![0; u16::from_be_bytes([header[1], header[2]]) as usize];
+        client.read_exact(&mut payload)?;
+        match (header[0], payload.as_slice()) {
+            (FRAME_INPUT, input) => master.write_all(input)?,
+            (FRAME_RESIZE, &[w1, w2, h1, h2]) if !fixed_size => set_size(
+                master,
+                u16::from_be_bytes([w1, w2]),
+                u16::from_be_bytes([h1, h2]),
+            )?,
+            _ => (),
+        }
+    }
+}
+
+fn send_frame(socket: &mut UnixStream, kind: u8, payload: &[u8]) -> std::io::Result<()> {
+    let mut frame = vec![kind];
+    frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    frame.extend_from_slice(payload);
+    socket.write_all(&frame)
+}
+
+fn send_size(socket: &mut UnixStream) -> std::io::Result<()> {
+    let (width, height) = termion::terminal_size()?;
+    let mut payload = width.to_be_bytes().to_vec();
+    payload.extend_from_slice(&height.to_be_bytes());
+    send_frame(socket, FRAME_RESIZE, &payload)
+}
+
+/// Attach the terminal to an interactive run through its `socket`, until detaching (with
+/// [`DETACH_KEY`]) or the run finishing. Returns whether the run finished.
+pub fn attach(socket: &Path) -> Result<bool> {
+    let mut connection = UnixStream::connect(socket)
+        .with_context(|| format!("Could not connect to {:?}", socket))?;
+    let _raw_mode = std::io::stdout().into_raw_mode()?;
+    // SAFETY: the handler only sets an atomic boolean
+    unsafe {
+        signal::signal(Signal::SIGWINCH, SigHandler::Handler(on_resize))?;
+    }
+    send_size(&mut connection)?;
+
+    let finished = Arc::new(AtomicBool::new(false));
+    {
+        let (mut connection, finished) = (connection.try_clone()?, finished.clone());
+        // Written to unbuffered, for prompts and such to show up right away
+        let mut stdout = File::from(std::io::stdout().as_fd().try_clone_to_owned()?);
+        std::thread::spawn(move || {
+            let _ = std::io::copy(&mut connection, &mut stdout);
+            // The connection only ends once the run does (or we detach)
+            finished.store(true, Ordering::SeqCst);
+        });
+    }
+
+    let stdin = std::io::stdin();
+    let mut buffer = [0; 4096];
+    let mut detached = false;
+    while !detached && !finished.load(Ordering::SeqCst) {
+        if RESIZED.swap(false, Ordering::SeqCst) {
+            send_size(&mut connection)?;
+        }
+        let mut fds = [PollFd::new(stdin.as_fd(), PollFlags::POLLIN)];
+        match poll(&mut fds, PollTimeout::from(100u16)) {
+            Ok(0) | Err(nix::errno::Errno::EINTR) => continue,
+            Ok(_) => (),
+            Err(e) => return Err(e.into()),
+        }
+        // Reading directly, as whatever a buffered reader held on to wouldn't wake us up
+        let input = match nix::unistd::read(stdin.as_raw_fd(), &mut buffer)? {
+            0 => {
+                detached = true;
+                &buffer[..0]
+            }
+            n => &buffer[..n],
+        };
+        let input = match input.iter().position(|b| *b == DETACH_KEY) {
+            Some(position) => {
+                detached = true;
+                &input[..position]
+            }
+            None => input,
+        };
+        if !input.is_empty() {
+            send_frame(&mut connection, FRAME_INPUT, input)?;
+        }
+    }
+    let _ = connection.shutdown(std::net::Shutdown::Both);
+    Ok(!detached)
+}
@@ -0,0 +1,100 @@
+use std::{io::Write, os::unix::net::UnixDatagram, path::Path};
+
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Where the system journal takes entries, in its native protocol.
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+/// Where syslog takes messages.
+const SYSLOG_SOCKET: &str = "/dev/log";
+
+/// A system log to forward runs' output to, line by line, besides their output log (see
+/// `--log-to`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogTarget {
+    /// The systemd journal, with the run's ID and label as fields of each entry
+    Journald,
+    /// Syslog, tagged with the run's ID
+    Syslog,
+}
+
+impl LogTarget {
+    fn socket(self) -> &'static str {
+        match self {
+            LogTarget::Journald => JOURNALD_SOCKET,
+            LogTarget::Syslog => SYSLOG_SOCKET,
+        }
+    }
+
+    /// Make sure that the log can be written to at all, to tell the user before starting a run
+    /// rather than never.
+    pub fn check(self) -> Result<()> {
+        match Path::new(self.socket()).exists() {
+            true => Ok(()),
+            false => Err(Error::msg(format!(
+                "Can't log to {}, as there is no {}",
+                self.name(),
+                self.socket()
+            ))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogTarget::Journald => "journald",
+            LogTarget::Syslog => "syslog",
+        }
+    }
+
+    pub fn connect(self, run_id: &str, label: Option<&str>) -> std::io::Result<SystemLog> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(self.socket())?;
+        Ok(SystemLog {
+            target: self,
+            socket,
+            run_id: run_id.to_string(),
+            label: label.map(str::to_string),
+        })
+    }
+}
+
+/// A connection to a system log, for the output of a run.
+pub struct SystemLog {
+    target: LogTarget,
+    socket: UnixDatagram,
+    run_id: String,
+    label: Option<String>,
+}
+
+impl SystemLog {
+    /// Send a line of the run's output (without its line ending) to the log.
+    pub fn send(&self, line: &[u8]) -> std::io::Result<()> {
+        let mut message = Vec::new();
+        match self.target {
+            LogTarget::Journald => {
+                // The message goes in the binary-safe form, as output may be anything
+                message.extend_from_slice(b"MESSAGE\n");
+                message.extend_from_slice(&(line.len() as u64).to_le_bytes());
+                message.extend_from_slice(line);
+                message.push(b'\n');
+                writeln!(message, "PRIORITY=6")?;
+                writeln!(message, "SYSLOG_IDENTIFIER=rum")?;
+                writeln!(message, "RUM_RUN_ID={}", self.run_id)?;
+                if let Some(label) = &self.label {
+                    writeln!(message, "RUM_LABEL={}", label.replace('\n', " "))?;
+                }
+            }
+            LogTarget::Syslog => {
+                // user.info
+                write!(message, "<14>rum-{}: ", &self.run_id[..8])?;
+                if let Some(label) = &self.label {
+                    write!(message, "({}) ", label)?;
+                }
+                message.extend_from_slice(line);
+            }
+        }
+        self.socket.send(&message)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+
+use crate::{
+    config::Telemetry,
+    runs::{Run, RunData, RunDataState},
+};
+
+/// How long to wait for the collector before giving up.
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn attribute(key: &str, value: Value) -> Value {
+    json!({ "key": key, "value": value })
+}
+
+fn nanos(datetime: DateTime<Utc>) -> String {
+    datetime.timestamp_nanos().to_string()
+}
+
+/// Send a span for a finished run to an OpenTelemetry collector, over OTLP/HTTP (as JSON).
+pub fn export_run_span(telemetry: &Telemetry, run: &Run, data: &RunData) -> Result<()> {
+    let RunDataState::Done {
+        end_datetime,
+        exit_code,
+        ..
+    } = data.state
+    else {
+        return Ok(());
+    };
+
+    // Run IDs are UUIDs, i.e. 128 random bits, which is exactly what a trace ID is
+    let trace_id = run.id.replace('-', "");
+    let mut attributes = vec![
+        attribute("rum.run.id", json!({ "stringValue": run.id })),
+        attribute(
+            "process.command_line",
+            json!({ "stringValue": shell_words::join(&data.command) }),
+        ),
+        attribute(
+            "process.exit_code",
+            json!({ "intValue": exit_code.to_string() }),
+        ),
+        attribute(
+            "rum.run.status",
+            json!({ "stringValue": data.state.status() }),
+        ),
+    ];
+    if let Some(label) = &data.label {
+        attributes.push(attribute("rum.run.label", json!({ "stringValue": label })));
+    }
+
+    let span = json!({
+        "traceId": trace_id,
+        "spanId": &trace_id[..16],
+        "name": data.label.clone().unwrap_or_else(|| data.command[0].clone()),
+        // SPAN_KIND_INTERNAL
+        "kind": 1,
+        "startTimeUnixNano": nanos(data.start_datetime),
+        "endTimeUnixNano": nanos(end_datetime),
+        "attributes": attributes,
+        // STATUS_CODE_OK or STATUS_CODE_ERROR
+        "status": if data.state.is_success() {
+            json!({ "code": 1 })
+        } else {
+            json!({ "code": 2, "message": data.state.status() })
+        },
+    });
+    let body = json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [attribute("service.name", json!({ "stringValue": telemetry.service_name }))],
+            },
+            "scopeSpans": [{
+                "scope": { "name": "rum", "version": env!("CARGO_PKG_VERSION") },
+                "spans": [span],
+            }],
+        }],
+    });
+
+    let url = format!("{}/v1/traces", telemetry.endpoint.trim_end_matches('/'));
+    let mut request = ureq::AgentBuilder::new()
+        .timeout(EXPORT_TIMEOUT)
+        .build()
+        .post(&url)
+        .set("Content-Type", "application/json");
+    for (header, value) in &telemetry.headers {
+        request = request.set(header, value);
+    }
+    request.send_string(&body.to_string())?;
+    Ok(())
+}
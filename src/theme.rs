@@ -0,0 +1,108 @@
+use std::sync::OnceLock;
+
+use colored::{Color, ColoredString, Colorize};
+use serde::{Deserialize, Deserializer};
+
+/// The colors used to show runs' statuses (and such), from `theme` in the configuration.
+///
+/// Colors are given by name (e.g. `red` or `bright blue`), as `#rrggbb`, or as `none` to not color
+/// something at all. Colors are never used when the `NO_COLOR` environment variable is set.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    /// Runs which succeeded.
+    pub success: ThemeColor,
+    /// Runs which failed.
+    pub failure: ThemeColor,
+    /// Runs which were killed.
+    pub killed: ThemeColor,
+    /// Runs which crashed.
+    pub crashed: ThemeColor,
+    /// Runs whose outcome is unknown.
+    pub unknown: ThemeColor,
+    /// Warnings, e.g. about runs being stalled or overdue.
+    pub warning: ThemeColor,
+    /// Notes, e.g. about runs being monitored again.
+    pub note: ThemeColor,
+    /// Runs' labels.
+    pub label: ThemeColor,
+    /// Lines highlighted as errors in the viewer.
+    pub errors: ThemeColor,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            success: ThemeColor(Some(Color::Green)),
+            failure: ThemeColor(Some(Color::Red)),
+            killed: ThemeColor(Some(Color::Yellow)),
+            crashed: ThemeColor(Some(Color::Magenta)),
+            unknown: ThemeColor(Some(Color::Blue)),
+            warning: ThemeColor(Some(Color::Yellow)),
+            note: ThemeColor(Some(Color::Blue)),
+            label: ThemeColor(Some(Color::Cyan)),
+            errors: ThemeColor(Some(Color::Red)),
+        }
+    }
+}
+
+/// A color in the theme, or `None` for no color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThemeColor(pub Option<Color>);
+
+impl<'de> Deserialize<'de> for ThemeColor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let color = String::deserialize(deserializer)?;
+        parse_color(&color).map(ThemeColor).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "invalid color '{}' (expected e.g. 'red', 'bright blue', '#d70000' or 'none')",
+                color
+            ))
+        })
+    }
+}
+
+fn parse_color(color: &str) -> Option<Option<Color>> {
+    if color.eq_ignore_ascii_case("none") {
+        return Some(None);
+    }
+    if let Some(hex) = color.strip_prefix('#') {
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        return match hex.len() {
+            6 => Some(Some(Color::TrueColor {
+                r: channel(0)?,
+                g: channel(2)?,
+                b: channel(4)?,
+            })),
+            _ => None,
+        };
+    }
+    color.parse().ok().map(Some)
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// Use `theme` from now on (rather than the default one).
+pub fn set(theme: Theme) {
+    let _ = THEME.set(theme);
+}
+
+pub fn get() -> &'static Theme {
+    THEME.get_or_init(Theme::default)
+}
+
+/// Color `text` with one of the theme's colors, e.g. `paint("[done]", get().success)`.
+pub fn paint(text: &str, color: ThemeColor) -> ColoredString {
+    match color.0 {
+        Some(color) => text.color(color),
+        None => text.normal(),
+    }
+}
+
+/// Get the escape sequence to switch to `color` on a terminal, if colors are to be used at all.
+pub fn escape_sequence(color: ThemeColor) -> Option<String> {
+    match (color.0, colored::control::SHOULD_COLORIZE.should_colorize()) {
+        (Some(color), true) => Some(format!("\x1b[{}m", color.to_fg_str())),
+        _ => None,
+    }
+}
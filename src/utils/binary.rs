@@ -0,0 +1,30 @@
+/// Guess whether `text` (decoded lossily from arbitrary bytes) is actually binary data.
+pub fn looks_binary(text: &str) -> bool {
+    let mut suspicious = 0;
+    let mut total = 0;
+    for c in text.chars() {
+        total += 1;
+        match c {
+            '\0' => return true,
+            '\n' | '\r' | '\t' | '\x1b' | '\x08' => (),
+            char::REPLACEMENT_CHARACTER => suspicious += 1,
+            c if c.is_control() => suspicious += 1,
+            _ => (),
+        }
+    }
+    // Allow for the odd stray byte in otherwise textual output
+    total > 0 && suspicious * 10 > total
+}
+
+/// Escape everything in `text` that could mess with the terminal, keeping only newlines and tabs.
+pub fn escape_control(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' | '\t' => escaped.push(c),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
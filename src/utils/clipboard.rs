@@ -0,0 +1,49 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{Error, Result};
+use base64::Engine;
+
+/// Copy `text` to the system clipboard, with `wl-copy`, `xclip` or `xsel` in a graphical session
+/// where one of them is installed, or otherwise by asking the terminal to do it (with an OSC 52
+/// escape sequence), which also works over SSH in most terminals. Returns how it was copied.
+pub fn copy(text: &str, terminal: &mut impl Write) -> Result<&'static str> {
+    let mut tools: Vec<(&str, &[&str])> = Vec::new();
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        tools.push(("wl-copy", &[]));
+    }
+    if std::env::var_os("DISPLAY").is_some() {
+        tools.push(("xclip", &["-selection", "clipboard"]));
+        tools.push(("xsel", &["--clipboard", "--input"]));
+    }
+    for (tool, arguments) in tools {
+        if copy_with(tool, arguments, text).is_ok() {
+            return Ok(tool);
+        }
+    }
+
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    write!(terminal, "\x1b]52;c;{}\x07", encoded)?;
+    terminal.flush()?;
+    Ok("the terminal")
+}
+
+fn copy_with(tool: &str, arguments: &[&str], text: &str) -> Result<()> {
+    let mut child = Command::new(tool)
+        .args(arguments)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(text.as_bytes())?;
+    match child.wait()?.success() {
+        true => Ok(()),
+        false => Err(Error::msg(format!("{} failed", tool))),
+    }
+}
@@ -0,0 +1,104 @@
+use colored::Colorize;
+use serde_json::{Map, Value};
+
+use crate::theme::{self, paint};
+
+/// Fields holding the time an entry was logged at, as named by common logging libraries.
+const TIME_FIELDS: [&str; 4] = ["time", "timestamp", "ts", "@timestamp"];
+/// Fields holding an entry's level.
+const LEVEL_FIELDS: [&str; 4] = ["level", "lvl", "severity", "log.level"];
+/// Fields holding an entry's message.
+const MESSAGE_FIELDS: [&str; 3] = ["msg", "message", "@message"];
+
+/// Renders structured (JSON-lines) logs readably: each line holding a JSON object is shown as its
+/// time, level and message, followed by the rest of its fields as `key=value`. Other lines are
+/// left as they are.
+#[derive(Debug, Default)]
+pub struct JsonlRenderer {
+    /// The current (incomplete) line, which can't be rendered until we've seen all of it.
+    pending: String,
+}
+
+impl JsonlRenderer {
+    /// Render the complete lines in `text` (along with whatever was pending from before), keeping
+    /// a trailing incomplete line for later.
+    pub fn render(&mut self, text: &str) -> String {
+        self.pending.push_str(text);
+        let Some(end) = self.pending.rfind('\n') else {
+            return String::new();
+        };
+        let rendered = self.pending[..=end]
+            .split_inclusive('\n')
+            .map(
+                |line| match render_line(line.trim_end_matches(['\n', '\r'])) {
+                    Some(rendered) => rendered + "\n",
+                    None => line.to_string(),
+                },
+            )
+            .collect();
+        self.pending.drain(..=end);
+        rendered
+    }
+}
+
+/// Render a single line, if it is a JSON object.
+fn render_line(line: &str) -> Option<String> {
+    if !line.trim_start().starts_with('{') {
+        return None;
+    }
+    let Ok(Value::Object(mut fields)) = serde_json::from_str::<Value>(line) else {
+        return None;
+    };
+
+    let mut take = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| fields.remove(*name))
+            .map(|value| match value {
+                Value::String(s) => s,
+                value => value.to_string(),
+            })
+    };
+    let time = take(&TIME_FIELDS);
+    let level = take(&LEVEL_FIELDS);
+    let message = take(&MESSAGE_FIELDS);
+
+    let mut parts = Vec::new();
+    if let Some(time) = time {
+        parts.push(time.dimmed().to_string());
+    }
+    if let Some(level) = level {
+        parts.push(colorize_level(&level));
+    }
+    if let Some(message) = message {
+        parts.push(message.bold().to_string());
+    }
+    parts.extend(render_fields(&fields));
+    Some(parts.join(" "))
+}
+
+fn colorize_level(level: &str) -> String {
+    let theme = theme::get();
+    let padded = format!("{:<5}", level.to_uppercase());
+    match level.to_lowercase().as_str() {
+        "trace" | "debug" => padded.dimmed(),
+        "info" | "notice" => paint(&padded, theme.success),
+        "warn" | "warning" => paint(&padded, theme.warning),
+        "error" | "err" => paint(&padded, theme.failure),
+        "fatal" | "critical" | "crit" | "panic" | "alert" | "emergency" => {
+            paint(&padded, theme.failure).bold()
+        }
+        _ => padded.normal(),
+    }
+    .to_string()
+}
+
+fn render_fields(fields: &Map<String, Value>) -> impl Iterator<Item = String> + '_ {
+    fields.iter().map(|(key, value)| {
+        let value = match value {
+            Value::String(s) if !s.is_empty() && !s.contains(char::is_whitespace) => s.clone(),
+            value => value.to_string(),
+        };
+        format!("{}{}", format!("{key}=").cyan(), value)
+    })
+}
@@ -1,8 +1,211 @@
+use anyhow::{Context, Error, Result};
 use chrono::{DateTime, Local, Utc};
 
+pub mod binary;
+pub mod clipboard;
+pub mod jsonl;
+pub mod names;
+pub mod proc;
 pub mod tail;
+pub mod viewport;
 
 
+/// How dates and times are shown, from `datetime_format` in the configuration or
+/// `--datetime-format`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DatetimeFormat {
+    /// E.g. `Thu Oct 15 10:50:01 2026`.
+    #[default]
+    Long,
+    /// ISO 8601, e.g. `2026-10-15T10:50:01+02:00`.
+    Iso,
+    /// E.g. `2026-10-15 10:50`.
+    Short,
+    /// Relative to now, e.g. `3h 05m ago`.
+    Relative,
+    /// A `strftime`-like format string, e.g. `%d/%m %H:%M`.
+    Custom(String),
+}
+
+impl std::str::FromStr for DatetimeFormat {
+    type Err = Error;
+
+    fn from_str(format: &str) -> Result<Self> {
+        Ok(match format {
+            "long" => Self::Long,
+            "iso" | "iso8601" => Self::Iso,
+            "short" => Self::Short,
+            "relative" => Self::Relative,
+            format if format.contains('%') => {
+                // Catch invalid format strings now, rather than when formatting fails later
+                let items = chrono::format::StrftimeItems::new(format);
+                if items
+                    .clone()
+                    .any(|item| item == chrono::format::Item::Error)
+                {
+                    return Err(Error::msg(format!("Invalid date/time format '{}'", format)));
+                }
+                Self::Custom(format.to_string())
+            }
+            format => {
+                return Err(Error::msg(format!(
+                    "Unknown date/time format '{}' (expected long, iso, short, relative, or a \
+                     strftime format such as '%d/%m %H:%M')",
+                    format
+                )))
+            }
+        })
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for DatetimeFormat {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+static DATETIME_FORMAT: std::sync::OnceLock<DatetimeFormat> = std::sync::OnceLock::new();
+
+/// Show dates and times in `format` from now on.
+pub fn set_datetime_format(format: DatetimeFormat) {
+    let _ = DATETIME_FORMAT.set(format);
+}
+
 pub fn format_datetime(datetime: DateTime<Utc>) -> String {
-    datetime.with_timezone(&Local).format("%c").to_string()
+    let local = datetime.with_timezone(&Local);
+    match DATETIME_FORMAT.get().unwrap_or(&DatetimeFormat::Long) {
+        DatetimeFormat::Long => local.format("%c").to_string(),
+        DatetimeFormat::Iso => local.to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+        DatetimeFormat::Short => local.format("%Y-%m-%d %H:%M").to_string(),
+        DatetimeFormat::Relative => {
+            let elapsed = Utc::now() - datetime;
+            match elapsed < chrono::Duration::zero() {
+                true => format!("in {}", format_duration(-elapsed)),
+                false => format!("{} ago", format_duration(elapsed)),
+            }
+        }
+        DatetimeFormat::Custom(format) => local.format(format).to_string(),
+    }
+}
+
+/// Format a duration compactly, e.g. `3d 4h`, `1h 05m` or `42s`.
+pub fn format_duration(duration: chrono::Duration) -> String {
+    let seconds = duration.num_seconds().max(0);
+    let (days, hours, minutes, seconds) = (
+        seconds / 86400,
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60,
+    );
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {:02}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Get the total size of the files in a directory (recursively).
+pub fn directory_size(path: &std::path::Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in path.read_dir()? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            directory_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+/// Format a size in bytes in a human-readable way, e.g. `12.3 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Parse a human-readable size such as `512`, `64K`, `100M` or `2G` (powers of 1024) into bytes.
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let digits_end = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(digits_end);
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("Invalid size '{}'", size))?;
+    let multiplier: u64 = match unit.to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" | "KI" => 1 << 10,
+        "M" | "MI" => 1 << 20,
+        "G" | "GI" => 1 << 30,
+        "T" | "TI" => 1 << 40,
+        _ => return Err(Error::msg(format!("Invalid size unit '{}'", unit))),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| Error::msg(format!("Size '{}' is too large", size)))
+}
+
+/// Parse a human-readable duration such as `500ms`, `30s`, `2h` or `1h30m` (a bare number is taken
+/// to be in seconds).
+pub fn parse_duration(duration: &str) -> Result<std::time::Duration> {
+    let invalid = || Error::msg(format!("Invalid duration '{}'", duration));
+    let too_large = || Error::msg(format!("Duration '{}' is too large", duration));
+
+    let mut rest = duration.trim();
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    let mut total = std::time::Duration::ZERO;
+    while !rest.is_empty() {
+        let digits_end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (number, tail) = rest.split_at(digits_end);
+        // Only digits are left, so this can only fail if there are none, or too many
+        let number: u64 = number.parse().map_err(|_| match number.is_empty() {
+            true => invalid(),
+            false => too_large(),
+        })?;
+        let unit_end = tail
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_end);
+        let seconds = |s: u64| {
+            number
+                .checked_mul(s)
+                .map(std::time::Duration::from_secs)
+                .ok_or_else(too_large)
+        };
+        let part = match unit {
+            "ms" => std::time::Duration::from_millis(number),
+            "" | "s" => seconds(1)?,
+            "m" => seconds(60)?,
+            "h" => seconds(60 * 60)?,
+            "d" => seconds(24 * 60 * 60)?,
+            "w" => seconds(7 * 24 * 60 * 60)?,
+            _ => return Err(invalid()),
+        };
+        total = total.checked_add(part).ok_or_else(too_large)?;
+        rest = tail;
+    }
+    Ok(total)
 }
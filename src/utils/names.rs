@@ -0,0 +1,92 @@
+/// Adjectives for the first half of generated names.
+const ADJECTIVES: [&str; 64] = [
+    "admiring", "agile", "amazing", "bold", "brave", "bright", "calm", "clever", "cool", "crisp",
+    "curious", "daring", "dazzling", "eager", "elated", "epic", "fervent", "festive", "fierce",
+    "focused", "frosty", "gallant", "gentle", "gifted", "happy", "hardy", "hopeful", "humble",
+    "jolly", "keen", "kind", "lively", "loyal", "lucid", "lucky", "mellow", "merry", "mighty",
+    "modest", "nimble", "noble", "patient", "peaceful", "plucky", "polite", "proud", "quick",
+    "quiet", "radiant", "rapid", "serene", "sharp", "sleepy", "snappy", "steady", "stoic", "sunny",
+    "swift", "tender", "trusty", "vibrant", "vigilant", "witty", "zealous",
+];
+
+/// Notable scientists and engineers, for the second half of generated names.
+const NAMES: [&str; 64] = [
+    "agnesi",
+    "archimedes",
+    "babbage",
+    "bardeen",
+    "bell",
+    "bohr",
+    "boole",
+    "cantor",
+    "carson",
+    "cerf",
+    "curie",
+    "darwin",
+    "dijkstra",
+    "einstein",
+    "euclid",
+    "euler",
+    "faraday",
+    "fermat",
+    "fermi",
+    "feynman",
+    "franklin",
+    "galileo",
+    "gauss",
+    "germain",
+    "goodall",
+    "hamilton",
+    "hawking",
+    "hopper",
+    "hypatia",
+    "johnson",
+    "kepler",
+    "knuth",
+    "lamarr",
+    "lamport",
+    "leavitt",
+    "liskov",
+    "lovelace",
+    "maxwell",
+    "mcclintock",
+    "meitner",
+    "mendel",
+    "minsky",
+    "newton",
+    "noether",
+    "pascal",
+    "pasteur",
+    "planck",
+    "poincare",
+    "ramanujan",
+    "ritchie",
+    "sagan",
+    "shannon",
+    "somerville",
+    "tesla",
+    "thompson",
+    "torvalds",
+    "turing",
+    "volhard",
+    "wilson",
+    "wing",
+    "wozniak",
+    "wright",
+    "yalow",
+    "zuse",
+];
+
+/// Generate a memorable name (e.g. `brave-turing`) for the run with the given ID. The name is
+/// derived from the ID, so that the same run always gets the same name.
+pub fn generate_name(id: &str) -> String {
+    // FNV-1a, which (unlike the standard library's hasher) is stable across versions
+    let hash = id.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!(
+        "{}-{}",
+        ADJECTIVES[(hash % ADJECTIVES.len() as u64) as usize],
+        NAMES[((hash >> 32) % NAMES.len() as u64) as usize]
+    )
+}
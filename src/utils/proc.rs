@@ -0,0 +1,172 @@
+use std::collections::HashSet;
+
+use anyhow::{Context, Error, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use nix::unistd::{sysconf, SysconfVar};
+
+/// A snapshot of a process' state, as read from `/proc/<pid>/stat`.
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessInfo {
+    pub pid: i32,
+    pub ppid: i32,
+    pub pgid: i32,
+    /// The process' state, e.g. `R` (running), `S` (sleeping) or `Z` (zombie).
+    pub state: char,
+    /// When the process started, in seconds since boot.
+    pub start_time: f64,
+    /// Total CPU time (user + system) used so far, in seconds.
+    pub cpu_time: f64,
+    /// Resident set size, in bytes.
+    pub rss: u64,
+}
+
+/// Resources being used by a group of processes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    /// Total CPU time (user + system) used so far, in seconds.
+    pub cpu_time: f64,
+    /// Resident set size, in bytes.
+    pub rss: u64,
+    pub processes: usize,
+}
+
+impl Usage {
+    pub fn of<'a>(processes: impl IntoIterator<Item = &'a ProcessInfo>) -> Self {
+        processes.into_iter().fold(Self::default(), |usage, process| Self {
+            cpu_time: usage.cpu_time + process.cpu_time,
+            rss: usage.rss + process.rss,
+            processes: usage.processes + 1,
+        })
+    }
+}
+
+fn parse_stat(stat: &str, clock_ticks: f64, page_size: u64) -> Result<ProcessInfo> {
+    // The command name (in parentheses) may contain spaces, so we split after it
+    let (pid, rest) = stat
+        .split_once(" (")
+        .ok_or_else(|| Error::msg("Malformed stat file"))?;
+    let (_, rest) = rest
+        .rsplit_once(") ")
+        .ok_or_else(|| Error::msg("Malformed stat file"))?;
+    let fields = rest.split_whitespace().collect::<Vec<_>>();
+    let field = |i: usize| -> Result<&str> {
+        fields
+            .get(i)
+            .copied()
+            .ok_or_else(|| Error::msg("Malformed stat file"))
+    };
+
+    Ok(ProcessInfo {
+        pid: pid.parse()?,
+        ppid: field(1)?.parse()?,
+        pgid: field(2)?.parse()?,
+        state: field(0)?.chars().next().unwrap_or('?'),
+        start_time: field(19)?.parse::<u64>()? as f64 / clock_ticks,
+        cpu_time: (field(11)?.parse::<u64>()? + field(12)?.parse::<u64>()?) as f64 / clock_ticks,
+        rss: field(21)?.parse::<u64>()? * page_size,
+    })
+}
+
+fn clock_ticks_and_page_size() -> Result<(f64, u64)> {
+    Ok((
+        sysconf(SysconfVar::CLK_TCK)?.unwrap_or(100) as f64,
+        sysconf(SysconfVar::PAGE_SIZE)?.unwrap_or(4096) as u64,
+    ))
+}
+
+/// Get information on a single process.
+pub fn process_info(pid: i32) -> Result<ProcessInfo> {
+    let (clock_ticks, page_size) = clock_ticks_and_page_size()?;
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid))
+        .with_context(|| format!("No process with PID {}", pid))?;
+    parse_stat(&stat, clock_ticks, page_size)
+}
+
+/// Check whether a process exists (and is not a zombie waiting to be reaped).
+pub fn process_is_alive(pid: i32) -> bool {
+    process_info(pid).is_ok_and(|info| info.state != 'Z' && info.state != 'X')
+}
+
+/// Check whether any (non-zombie) process is left in a process group.
+pub fn process_group_is_alive(pgid: i32) -> bool {
+    all_processes().is_ok_and(|processes| {
+        processes
+            .iter()
+            .any(|p| p.pgid == pgid && p.state != 'Z' && p.state != 'X')
+    })
+}
+
+/// Get the PIDs of all the (non-zombie) processes in the process group `pgid` or descending from
+/// `pid`, along with all of their descendants, even those which moved to other process groups.
+pub fn process_tree(pgid: i32, pid: Option<i32>) -> Result<Vec<i32>> {
+    let processes = all_processes()?;
+    let mut tree = processes
+        .iter()
+        .filter(|p| p.pgid == pgid || Some(p.pid) == pid)
+        .map(|p| p.pid)
+        .collect::<HashSet<_>>();
+    loop {
+        let children = processes
+            .iter()
+            .filter(|p| !tree.contains(&p.pid) && tree.contains(&p.ppid))
+            .map(|p| p.pid)
+            .collect::<Vec<_>>();
+        if children.is_empty() {
+            break;
+        }
+        tree.extend(children);
+    }
+    Ok(processes
+        .iter()
+        .filter(|p| tree.contains(&p.pid) && p.state != 'Z' && p.state != 'X')
+        .map(|p| p.pid)
+        .collect())
+}
+
+/// Get the command line a process was started with.
+pub fn process_command(pid: i32) -> Result<Vec<String>> {
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid))
+        .with_context(|| format!("Could not read the command of process {}", pid))?;
+    Ok(cmdline
+        .split(|&b| b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect())
+}
+
+/// Get when the system booted.
+pub fn boot_time() -> Result<DateTime<Utc>> {
+    let stat = std::fs::read_to_string("/proc/stat").with_context(|| "Could not read /proc/stat")?;
+    let btime = stat
+        .lines()
+        .find_map(|line| line.strip_prefix("btime "))
+        .ok_or_else(|| Error::msg("Could not find the boot time in /proc/stat"))?;
+    Ok(Utc.timestamp(btime.trim().parse()?, 0))
+}
+
+/// Get an ID which is different every time the system boots.
+pub fn boot_id() -> Result<String> {
+    Ok(std::fs::read_to_string("/proc/sys/kernel/random/boot_id")
+        .with_context(|| "Could not read the boot ID")?
+        .trim()
+        .to_string())
+}
+
+/// Get information on all the processes in the system.
+pub fn all_processes() -> Result<Vec<ProcessInfo>> {
+    let (clock_ticks, page_size) = clock_ticks_and_page_size()?;
+
+    Ok(std::fs::read_dir("/proc")
+        .with_context(|| "Could not read /proc")?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.chars().all(|c| c.is_ascii_digit()))
+        })
+        // Processes may exit while we are looking at them, so we just skip whatever we can't read
+        .filter_map(|entry| std::fs::read_to_string(entry.path().join("stat")).ok())
+        .filter_map(|stat| parse_stat(&stat, clock_ticks, page_size).ok())
+        .collect())
+}
@@ -1,42 +1,122 @@
-use std::{io::{Read, Seek}, path::Path, sync::mpsc::{TryRecvError, channel}};
+use std::{io::{Read, Seek}, path::{Path, PathBuf}, sync::mpsc::{RecvTimeoutError, channel}, time::{Duration, Instant}};
 
-use anyhow::{Result, Error};
+use anyhow::Result;
 use notify::Watcher;
 
-pub fn follow_tail<F, G>(path: &Path, mut on_new_text: F, mut on_iter: G) -> Result<()>
+/// How much is read from the files at a time.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// How long to wait for new output before handing control back to the caller.
+const TICK: Duration = Duration::from_millis(50);
+
+/// Follows a sequence of files in `directory`, as given by `list_files`, as if they were a single
+/// file. Files are expected to be listed in the order they are written to.
+///
+/// Only (roughly) the last `initial_bytes` bytes of existing output are shown, starting at a line
+/// boundary; text is then passed to `on_new_text` in bounded chunks, so memory usage stays constant
+/// regardless of how big the files are.
+///
+/// New output is noticed through filesystem notifications; since these are not available on every
+/// filesystem (e.g. NFS or sshfs), the files' sizes are also checked every `poll_interval`.
+pub fn follow_tail<L, F, G>(
+    directory: &Path,
+    mut list_files: L,
+    initial_bytes: u64,
+    poll_interval: Duration,
+    mut on_new_text: F,
+    mut on_iter: G,
+) -> Result<()>
 where
+    L: FnMut() -> Result<Vec<PathBuf>>,
     F: FnMut(&str) -> Result<()>,
     G: FnMut() -> Result<bool>,
 {
     let (tx, rx) = channel();
-    let mut watcher: notify::RecommendedWatcher =
-        Watcher::new(tx, std::time::Duration::from_millis(50))?;
-    watcher.watch(&path, notify::RecursiveMode::NonRecursive)?;
-
-    let mut file = std::fs::File::open(path)?;
-    let mut buffer = String::new();
-    let mut seek_location = 0; // TODO what happens when the file is really big?
-
-    let mut update = || -> Result<()> {
-        buffer.clear();
-        file.seek(std::io::SeekFrom::Start(seek_location))?;
-        let how_much_was_read = file.read_to_string(&mut buffer)?;
-        seek_location += how_much_was_read as u64;
-        on_new_text(&buffer)?;
+    // If we can't watch the directory, we just rely on polling
+    let _watcher = notify::raw_watcher(tx)
+        .and_then(|mut watcher| {
+            watcher.watch(directory, notify::RecursiveMode::NonRecursive)?;
+            Ok(watcher)
+        })
+        .ok();
+
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut pending = Vec::new();
+    let (mut current, mut skip_partial_line) = initial_position(&list_files()?, initial_bytes)?;
+
+    let mut update = |files: &[PathBuf]| -> Result<()> {
+        let first = match &current {
+            Some((current_path, _)) => files.iter().position(|f| f >= current_path).unwrap_or(files.len()),
+            None => 0,
+        };
+
+        for path in &files[first..] {
+            let mut seek_location = match &current {
+                Some((current_path, seek_location)) if current_path == path => *seek_location,
+                _ => 0,
+            };
+
+            let mut file = std::fs::File::open(path)?;
+            file.seek(std::io::SeekFrom::Start(seek_location))?;
+            loop {
+                let how_much_was_read = file.read(&mut buffer)?;
+                if how_much_was_read == 0 {
+                    break;
+                }
+                seek_location += how_much_was_read as u64;
+
+                let mut new_bytes = &buffer[..how_much_was_read];
+                if skip_partial_line {
+                    match new_bytes.iter().position(|&b| b == b'\n') {
+                        Some(newline) => {
+                            new_bytes = &new_bytes[newline + 1..];
+                            skip_partial_line = false;
+                        }
+                        None => continue,
+                    }
+                }
+
+                pending.extend_from_slice(new_bytes);
+                let text = take_complete_text(&mut pending);
+                if !text.is_empty() {
+                    on_new_text(&text)?;
+                }
+            }
+            current = Some((path.clone(), seek_location));
+        }
         Ok(())
     };
 
-    update()?;
+    let files = list_files()?;
+    update(&files)?;
+    let mut last_poll = Instant::now();
+    let mut last_stat = stat_files(&files);
     loop {
-        match rx.try_recv() {
-            Ok(notify::DebouncedEvent::Write(_)) => {
-                update()?;
-            },
-            Ok(_) => (),
-            Err(TryRecvError::Empty) => std::thread::sleep(std::time::Duration::from_millis(10)),
-            Err(TryRecvError::Disconnected) => {
-                return Err(Error::msg("Output file watcher disconnected"));
-            },
+        let timeout = TICK.min(poll_interval.saturating_sub(last_poll.elapsed()));
+        match rx.recv_timeout(timeout) {
+            Ok(event) => {
+                // Writes usually come in bursts, so handle all of the pending events at once
+                let mut has_new_output = is_new_output(&event);
+                for event in rx.try_iter() {
+                    has_new_output |= is_new_output(&event);
+                }
+                if has_new_output {
+                    update(&list_files()?)?;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            // The sender is dropped right away when there is no watcher, in which case we poll
+            Err(RecvTimeoutError::Disconnected) => std::thread::sleep(timeout),
+        }
+
+        if last_poll.elapsed() >= poll_interval {
+            let files = list_files()?;
+            let stat = stat_files(&files);
+            if stat != last_stat {
+                update(&files)?;
+                last_stat = stat;
+            }
+            last_poll = Instant::now();
         }
 
         if on_iter()? {
@@ -46,3 +126,51 @@ where
 
     Ok(())
 }
+
+fn is_new_output(event: &notify::RawEvent) -> bool {
+    event
+        .op
+        .as_ref()
+        .is_ok_and(|op| op.intersects(notify::Op::WRITE | notify::Op::CREATE | notify::Op::RENAME))
+}
+
+/// Cheaply summarize the state of `files`, so that we can tell whether there is new output.
+fn stat_files(files: &[PathBuf]) -> Vec<(PathBuf, Option<u64>)> {
+    files
+        .iter()
+        .map(|f| (f.clone(), std::fs::metadata(f).ok().map(|m| m.len())))
+        .collect()
+}
+
+/// Find where to start reading so that only the last `initial_bytes` bytes of `files` are read.
+/// Also returns whether we are starting in the middle of the output (and thus maybe of a line).
+fn initial_position(files: &[PathBuf], initial_bytes: u64) -> Result<(Option<(PathBuf, u64)>, bool)> {
+    let sizes = files
+        .iter()
+        .map(|f| Ok(std::fs::metadata(f)?.len()))
+        .collect::<Result<Vec<_>>>()?;
+    let mut to_skip = sizes.iter().sum::<u64>().saturating_sub(initial_bytes);
+    if to_skip == 0 {
+        return Ok((None, false));
+    }
+
+    for (path, size) in files.iter().zip(sizes) {
+        if to_skip < size {
+            return Ok((Some((path.clone(), to_skip)), true));
+        }
+        to_skip -= size;
+    }
+    unreachable!("we never skip more than the total size")
+}
+
+/// Decode the bytes in `pending` into text, leaving behind a trailing incomplete UTF-8 sequence
+/// (if any) to be completed by the next read. Invalid UTF-8 is replaced by U+FFFD.
+fn take_complete_text(pending: &mut Vec<u8>) -> String {
+    let complete = match std::str::from_utf8(pending) {
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        _ => pending.len(),
+    };
+    let text = String::from_utf8_lossy(&pending[..complete]).into_owned();
+    pending.drain(..complete);
+    text
+}
@@ -0,0 +1,426 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, VecDeque},
+    io::Write,
+    ops::Range,
+};
+
+use regex::Regex;
+
+use crate::theme;
+
+/// How many lines are kept around at most; the oldest ones are dropped beyond that.
+const MAX_LINES: usize = 100_000;
+
+/// How many columns the view moves by when scrolling horizontally.
+const HORIZONTAL_STEP: usize = 8;
+
+/// How many lines are shown above an error jumped to, for context.
+const ERROR_CONTEXT: usize = 2;
+
+/// The lines of a run's output, as shown in a region of the terminal: either wrapped to fit its
+/// width, or truncated and scrolled horizontally. Lines are numbered from the start of the output,
+/// including those which were dropped.
+#[derive(Debug)]
+pub struct Viewport {
+    /// The complete lines, ready to be shown.
+    lines: VecDeque<String>,
+    /// How many lines were dropped from the start, for there being too many.
+    dropped: usize,
+    /// The last line, as long as it isn't complete.
+    partial: String,
+    /// Whether long lines are wrapped (rather than truncated).
+    wrap: bool,
+    /// The first column shown, when lines are truncated.
+    left: usize,
+    /// The first line shown, or `None` to follow the end of the output.
+    top: Option<usize>,
+    /// The lines shown the last time the viewport was drawn.
+    shown: Range<usize>,
+    /// How many rows the viewport had the last time it was drawn.
+    height: usize,
+    /// Lines matching this are highlighted as errors.
+    error_regex: Option<Regex>,
+    /// The lines which match `error_regex`.
+    errors: BTreeSet<usize>,
+    /// The error last jumped to, if any.
+    selected_error: Option<usize>,
+    /// The lines being selected, as the line the selection started at and the line the cursor is
+    /// at, if lines are being selected.
+    selection: Option<(usize, usize)>,
+}
+
+impl Viewport {
+    pub fn new(error_regex: Option<Regex>) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            dropped: 0,
+            partial: String::new(),
+            wrap: true,
+            left: 0,
+            top: None,
+            shown: 0..0,
+            height: 0,
+            error_regex,
+            errors: BTreeSet::new(),
+            selected_error: None,
+            selection: None,
+        }
+    }
+
+    /// Add output to the view.
+    pub fn push(&mut self, text: &str) {
+        self.partial.push_str(text);
+        let Some(end) = self.partial.rfind('\n') else {
+            return;
+        };
+        for line in self.partial[..end].split('\n') {
+            let line = sanitize(line);
+            if let Some(regex) = &self.error_regex {
+                if regex.is_match(&strip_styles(&line)) {
+                    self.errors.insert(self.dropped + self.lines.len());
+                }
+            }
+            self.lines.push_back(line);
+        }
+        self.partial.drain(..=end);
+        while self.lines.len() > MAX_LINES {
+            self.lines.pop_front();
+            self.errors.remove(&self.dropped);
+            self.dropped += 1;
+        }
+    }
+
+    /// The number just past the last line.
+    fn end(&self) -> usize {
+        self.dropped + self.lines.len() + usize::from(!self.partial.is_empty())
+    }
+
+    fn line(&self, number: usize) -> Cow<'_, str> {
+        match self.lines.get(number - self.dropped) {
+            Some(line) => Cow::Borrowed(line),
+            None => Cow::Owned(sanitize(&self.partial)),
+        }
+    }
+
+    /// Get the number of the first line shown (counting from 1), and how many lines there are.
+    pub fn position(&self) -> (usize, usize) {
+        (self.shown.start + 1, self.end())
+    }
+
+    pub fn is_following(&self) -> bool {
+        self.top.is_none()
+    }
+
+    pub fn is_wrapping(&self) -> bool {
+        self.wrap
+    }
+
+    pub fn toggle_wrap(&mut self) {
+        self.wrap = !self.wrap;
+        self.left = 0;
+    }
+
+    /// Scroll `steps` steps to the right (or to the left, if negative). Does nothing when lines
+    /// are wrapped.
+    pub fn scroll_horizontally(&mut self, steps: isize) {
+        if self.wrap {
+            return;
+        }
+        self.left = self
+            .left
+            .saturating_add_signed(steps * HORIZONTAL_STEP as isize);
+    }
+
+    /// Scroll `lines` lines down (or up, if negative). Scrolling past the end of the output
+    /// follows it again.
+    pub fn scroll(&mut self, lines: isize) {
+        let top = self.top.unwrap_or(self.shown.start);
+        self.top = Some(
+            top.saturating_add_signed(lines)
+                .clamp(self.dropped, self.end().max(1) - 1),
+        );
+    }
+
+    /// Scroll a page down (or up, if negative).
+    pub fn scroll_pages(&mut self, pages: isize) {
+        self.scroll(pages * self.height.saturating_sub(1).max(1) as isize);
+    }
+
+    pub fn scroll_to_start(&mut self) {
+        self.top = Some(self.dropped);
+    }
+
+    /// Show the end of the output, and keep showing it as more is added.
+    pub fn follow(&mut self) {
+        self.top = None;
+    }
+
+    /// Jump to the next (or, if not `forward`, the previous) line highlighted as an error;
+    /// returns whether there was any.
+    pub fn jump_to_error(&mut self, forward: bool) -> bool {
+        let target = match (forward, self.selected_error) {
+            (true, Some(selected)) => self.errors.range(selected + 1..).next(),
+            (true, None) => self.errors.range(self.shown.start..).next(),
+            (false, Some(selected)) => self.errors.range(..selected).next_back(),
+            (false, None) => self.errors.range(..self.shown.end).next_back(),
+        };
+        let Some(&target) = target else {
+            return false;
+        };
+        self.selected_error = Some(target);
+        self.top = Some(target.saturating_sub(ERROR_CONTEXT).max(self.dropped));
+        true
+    }
+
+    pub fn is_selecting(&self) -> bool {
+        self.selection.is_some()
+    }
+
+    /// Start selecting lines, from the last line shown if following the output, or the first one
+    /// otherwise.
+    pub fn start_selection(&mut self) {
+        if self.end() == self.dropped {
+            return;
+        }
+        let cursor = match self.top {
+            None => self.shown.end.saturating_sub(1),
+            Some(_) => self.shown.start,
+        }
+        .clamp(self.dropped, self.end() - 1);
+        self.selection = Some((cursor, cursor));
+    }
+
+    pub fn cancel_selection(&mut self) {
+        self.selection = None;
+    }
+
+    /// Move the end of the selection `lines` lines down (or up, if negative), scrolling to keep it
+    /// on screen.
+    pub fn move_cursor(&mut self, lines: isize) {
+        let Some((anchor, cursor)) = self.selection else {
+            return;
+        };
+        let cursor = cursor
+            .saturating_add_signed(lines)
+            .clamp(self.dropped, self.end() - 1);
+        self.selection = Some((anchor, cursor));
+        if cursor < self.shown.start {
+            self.top = Some(cursor);
+        } else if cursor >= self.shown.end {
+            self.scroll((cursor + 1 - self.shown.end) as isize);
+        }
+    }
+
+    /// Stop selecting lines, and get the text of the ones which were selected (without colors or
+    /// styles).
+    pub fn take_selection(&mut self) -> Option<(usize, String)> {
+        let (anchor, cursor) = self.selection.take()?;
+        let lines = anchor.min(cursor).max(self.dropped)..anchor.max(cursor) + 1;
+        let text = lines
+            .clone()
+            .map(|number| strip_styles(&self.line(number)))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Some((lines.len(), text))
+    }
+
+    /// Split the line numbered `number` into the rows it takes up on screen, given its `width`.
+    fn rows(&self, number: usize, width: usize) -> Vec<String> {
+        let line = self.line(number);
+        if !self.wrap {
+            return vec![slice_columns(&line, self.left, width)];
+        }
+        let row_count = display_width(&line).div_ceil(width).max(1);
+        (0..row_count)
+            .map(|row| slice_columns(&line, row * width, width))
+            .collect()
+    }
+
+    /// Get the rows which show as much as possible of the end of the output, along with the first
+    /// line they show (maybe partially).
+    fn last_rows(&self, width: usize, height: usize) -> (usize, VecDeque<(usize, String)>) {
+        let mut rows = VecDeque::new();
+        let mut first = self.end();
+        while first > self.dropped && rows.len() < height {
+            first -= 1;
+            for row in self.rows(first, width).into_iter().rev() {
+                rows.push_front((first, row));
+            }
+        }
+        while rows.len() > height {
+            rows.pop_front();
+        }
+        (first, rows)
+    }
+
+    /// Draw the output into the `height` rows starting at `first_row`.
+    pub fn render(
+        &mut self,
+        screen: &mut impl Write,
+        first_row: u16,
+        (width, height): (u16, u16),
+    ) -> std::io::Result<()> {
+        let (width, height) = (usize::from(width).max(1), usize::from(height));
+        let (last_top, last_rows) = self.last_rows(width, height);
+        let rows = match self.top {
+            // Once everything up to the end fits on screen, follow it
+            Some(top) if top < last_top => {
+                let top = top.max(self.dropped);
+                (top..self.end())
+                    .flat_map(|number| {
+                        self.rows(number, width)
+                            .into_iter()
+                            .map(move |row| (number, row))
+                    })
+                    .take(height)
+                    .collect()
+            }
+            _ => {
+                self.top = None;
+                last_rows
+            }
+        };
+        self.shown = match (rows.front(), rows.back()) {
+            (Some((first, _)), Some((last, _))) => *first..*last + 1,
+            _ => self.end()..self.end(),
+        };
+        self.height = height;
+
+        for offset in 0..height {
+            write!(
+                screen,
+                "{}{}",
+                termion::cursor::Goto(1, first_row + offset as u16),
+                termion::clear::CurrentLine
+            )?;
+            if let Some((number, row)) = rows.get(offset) {
+                let is_selected = self.selection.is_some_and(|(anchor, cursor)| {
+                    (anchor.min(cursor)..=anchor.max(cursor)).contains(number)
+                });
+                if is_selected || self.selected_error == Some(*number) {
+                    write!(screen, "{}", termion::style::Invert)?;
+                }
+                if self.errors.contains(number) {
+                    match theme::escape_sequence(theme::get().errors) {
+                        Some(color) => write!(screen, "{}", color)?,
+                        None => write!(screen, "{}", termion::style::Bold)?,
+                    }
+                }
+                write!(screen, "{}{}", row, termion::style::Reset)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Make `line` safe to draw anywhere on the screen: tabs are expanded, only what would end up
+/// visible after carriage returns is kept, and escape sequences other than colors and styles are
+/// dropped.
+fn sanitize(line: &str) -> String {
+    let line = line.trim_end_matches('\r');
+    let line = line.rsplit('\r').next().unwrap_or(line);
+    let mut sanitized = String::with_capacity(line.len());
+    let mut column = 0;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => {
+                let sequence = take_escape_sequence(&mut chars);
+                if sequence.starts_with('[') && sequence.ends_with('m') {
+                    sanitized.push('\x1b');
+                    sanitized.push_str(&sequence);
+                }
+            }
+            '\t' => {
+                let spaces = 8 - column % 8;
+                sanitized.extend(std::iter::repeat_n(' ', spaces));
+                column += spaces;
+            }
+            c if c.is_control() => (),
+            c => {
+                sanitized.push(c);
+                column += 1;
+            }
+        }
+    }
+    sanitized
+}
+
+/// Take the rest of an escape sequence (after the `ESC`) from `chars`.
+fn take_escape_sequence(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut sequence = String::new();
+    match chars.next() {
+        Some('[') => {
+            sequence.push('[');
+            // Parameters and intermediate bytes, then a final byte
+            for c in chars.by_ref() {
+                sequence.push(c);
+                if ('\x40'..='\x7e').contains(&c) {
+                    break;
+                }
+            }
+        }
+        Some(']') => {
+            // Operating system commands run until BEL or ST
+            while let Some(c) = chars.next() {
+                if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                    break;
+                }
+            }
+        }
+        Some(c) => sequence.push(c),
+        None => (),
+    }
+    sequence
+}
+
+/// Remove the colors and styles from `line` (as sanitized).
+fn strip_styles(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut in_escape = false;
+    for c in line.chars() {
+        match (in_escape, c) {
+            (false, '\x1b') => in_escape = true,
+            (false, c) => stripped.push(c),
+            (true, 'm') => in_escape = false,
+            (true, _) => (),
+        }
+    }
+    stripped
+}
+
+/// How many columns `line` (as sanitized) takes up.
+fn display_width(line: &str) -> usize {
+    strip_styles(line).chars().count()
+}
+
+/// Get the `width` columns of `line` (as sanitized) starting at column `start`, keeping the colors
+/// and styles in effect.
+fn slice_columns(line: &str, start: usize, width: usize) -> String {
+    let mut slice = String::new();
+    let mut column = 0;
+    let mut in_escape = false;
+    for c in line.chars() {
+        match (in_escape, c) {
+            (false, '\x1b') => {
+                in_escape = true;
+                slice.push(c);
+            }
+            (false, c) => {
+                if column >= start + width {
+                    break;
+                }
+                if column >= start {
+                    slice.push(c);
+                }
+                column += 1;
+            }
+            (true, c) => {
+                in_escape = c != 'm';
+                slice.push(c);
+            }
+        }
+    }
+    slice
+}